@@ -13,16 +13,79 @@ use std::convert::TryFrom;
 pub struct Archive {
     root: std::path::PathBuf,      // The root directory.
     db_conn: rusqlite::Connection, // An sqlite connection.
+    sounding_cache: std::sync::Mutex<soundings::SoundingCache>, // Recently parsed files.
+    derived_indexing: DerivedIndexing, // Parameters to compute when adding files.
 }
 
+// An archive can be moved to another thread.
+const _: () = {
+    const fn assert_send<T: Send>() {}
+    assert_send::<Archive>();
+};
+
+mod analogs;
+pub use analogs::{Analog, AnalogFeatures, DistanceMetric};
+
 mod clean;
+
+mod climatology;
+pub use climatology::{Climatology, ClimoParam};
+
+mod compare;
+pub use compare::{ModelComparison, TimeAlignment};
+
+mod derived;
+pub use derived::{DerivedIndexing, DerivedParams};
+
+mod dprog;
+pub use dprog::DprogDt;
+
+mod ensemble;
+pub use ensemble::{EnsembleGroup, EnsembleMember, EnsembleRun, EnsembleStat, EnsembleStats};
+
+mod file_iter;
+pub use file_iter::FileIter;
+
+mod file_query;
+pub use file_query::{FileOrder, FileQuery};
+
+mod file_record;
+pub use file_record::FileRecord;
+
+mod hazards;
+pub use hazards::{HazardIndices, PrecipType};
+
+mod hourly_params;
+
+mod id_history;
+pub use id_history::IdHistoryEntry;
+
+mod merge;
 mod modify;
 
+mod observations;
+pub use observations::{Season, VerificationStats, VerifyParam};
+
+mod qpf;
+pub use qpf::QpfRecord;
+
 mod query;
-pub use query::StationSummary;
+pub use query::{NearbyStation, StationMatch, StationMatchKind, StationSummary};
 
 mod root;
 
+mod site_table;
+pub use site_table::{SiteImportReport, SiteTableFormat, SiteUpdate};
+
+mod soundings;
+pub use soundings::ParsedSounding;
+
+mod time_series;
+pub use time_series::{SeriesPoint, StitchRule};
+
+mod valid_time;
+pub use valid_time::RunSounding;
+
 struct InternalSiteInfo {
     station_num: StationNumber,
     id: Option<String>,
@@ -161,20 +224,23 @@ mod unit {
                 notes: Some("Major air travel hub.".to_owned()),
                 state: Some(StateProv::IL),
                 time_zone: None,
+                ..SiteInfo::default()
             },
             SiteInfo {
                 station_num: StationNumber::from(2),
                 name: Some("Seattle".to_owned()),
                 notes: Some("A coastal city with coffe and rain".to_owned()),
                 state: Some(StateProv::WA),
-                time_zone: Some(chrono::FixedOffset::west_opt(8 * 3600).unwrap()),
+                time_zone: Some(chrono_tz::America::Los_Angeles.into()),
+                ..SiteInfo::default()
             },
             SiteInfo {
                 station_num: StationNumber::from(3),
                 name: Some("Missoula".to_owned()),
                 notes: Some("In a valley.".to_owned()),
                 state: None,
-                time_zone: Some(chrono::FixedOffset::west_opt(7 * 3600).unwrap()),
+                time_zone: Some(chrono::FixedOffset::west_opt(7 * 3600).unwrap().into()),
+                ..SiteInfo::default()
             },
         ]
     }
@@ -0,0 +1,364 @@
+//! Search the archive for soundings similar to a given one.
+
+use crate::{
+    archive::{Archive, ClimoParam, FileQuery, FileRecord},
+    errors::BufkitDataErr,
+};
+use chrono::NaiveDateTime;
+use metfor::{HectoPascal, Knots, Quantity, WindUV};
+use rusqlite::ToSql;
+use sounding_analysis::Sounding;
+use strum::IntoEnumIterator;
+use strum_macros::{EnumIter, EnumString, IntoStaticStr};
+
+/// The pressure levels used for the profile features.
+const FEATURE_LEVELS: [f64; 4] = [850.0, 700.0, 500.0, 300.0];
+
+/// The values that describe a sounding when comparing it with others.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, EnumString, IntoStaticStr, EnumIter, Hash)]
+#[strum(ascii_case_insensitive)]
+pub enum AnalogFeatures {
+    /// The surface temperature and dew point and the temperature and dew point at 850, 700, 500,
+    /// and 300 hPa (C).
+    #[strum(to_string = "thermo")]
+    Thermo,
+    /// The u and v wind components at the surface and at 850, 700, 500, and 300 hPa (knots).
+    #[strum(to_string = "wind")]
+    Wind,
+    /// The parameters used for climatologies, see `ClimoParam`.
+    #[strum(to_string = "indexes")]
+    Indexes,
+}
+
+impl AnalogFeatures {
+    /// Get the name used for the features in the index.
+    pub fn as_static_str(self) -> &'static str {
+        self.into()
+    }
+
+    /// Compute the feature vector for a sounding, if all the values are available. Soundings with
+    /// a level below ground, e.g. 850 hPa at a high elevation site, have no profile features.
+    pub fn vector(self, snd: &Sounding) -> Option<Vec<f64>> {
+        let at_levels = || {
+            FEATURE_LEVELS
+                .iter()
+                .map(|&p| sounding_analysis::linear_interpolate_sounding(snd, HectoPascal(p)).ok())
+        };
+
+        match self {
+            AnalogFeatures::Thermo => {
+                let mut vals = vec![
+                    snd.sfc_temperature().into_option()?.unpack(),
+                    snd.sfc_dew_point().into_option()?.unpack(),
+                ];
+                for row in at_levels() {
+                    let row = row?;
+                    vals.push(row.temperature.into_option()?.unpack());
+                    vals.push(row.dew_point.into_option()?.unpack());
+                }
+
+                Some(vals)
+            }
+            AnalogFeatures::Wind => {
+                let mut vals = vec![];
+                let winds = std::iter::once(snd.sfc_wind().into_option())
+                    .chain(at_levels().map(|row| row.and_then(|row| row.wind.into_option())));
+                for wind in winds {
+                    let WindUV { u, v } = WindUV::<Knots>::from(wind?);
+                    vals.push(u.unpack());
+                    vals.push(v.unpack());
+                }
+
+                Some(vals)
+            }
+            AnalogFeatures::Indexes => ClimoParam::iter().map(|param| param.value(snd)).collect(),
+        }
+    }
+}
+
+/// How to measure the distance between two feature vectors.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DistanceMetric {
+    /// The square root of the sum of the squared differences.
+    Euclidean,
+    /// The sum of the absolute differences.
+    Manhattan,
+    /// Euclidean distance after dividing each feature by its standard deviation among the
+    /// candidates, so features with large values do not swamp the others.
+    #[default]
+    Standardized,
+}
+
+impl DistanceMetric {
+    fn distance(self, a: &[f64], b: &[f64], scales: &[f64]) -> f64 {
+        let diffs = a.iter().zip(b).map(|(a, b)| a - b);
+
+        match self {
+            DistanceMetric::Euclidean => diffs.map(|d| d * d).sum::<f64>().sqrt(),
+            DistanceMetric::Manhattan => diffs.map(f64::abs).sum(),
+            DistanceMetric::Standardized => diffs
+                .zip(scales)
+                .filter(|&(_, &scale)| scale > 0.0)
+                .map(|(d, scale)| (d / scale).powi(2))
+                .sum::<f64>()
+                .sqrt(),
+        }
+    }
+}
+
+/// A sounding in the archive similar to the one searched for.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Analog {
+    /// The file the sounding is in.
+    pub record: FileRecord,
+    /// The valid time of the sounding.
+    pub valid_time: NaiveDateTime,
+    /// The hours from the initialization time to the valid time.
+    pub lead_hours: i32,
+    /// The distance from the sounding searched for, smaller is more similar.
+    pub distance: f64,
+}
+
+impl Archive {
+    /// Compute and store the feature vectors of every sounding in the files matching a query,
+    /// replacing any that were already stored. Returns the number of files indexed.
+    ///
+    /// Only soundings with stored features are considered by `find_analogs`.
+    pub fn index_analog_features(
+        &self,
+        query: &FileQuery,
+        features: AnalogFeatures,
+    ) -> Result<usize, BufkitDataErr> {
+        let records = self.file_records(query)?;
+        let feature_set = features.as_static_str();
+
+        let tx = self.db_conn.unchecked_transaction()?;
+        let mut del_stmt = self
+            .db_conn
+            .prepare("DELETE FROM analog_features WHERE file_name = ?1 AND feature_set = ?2")?;
+        let mut ins_stmt = self.db_conn.prepare(
+            "
+                INSERT OR REPLACE INTO analog_features (
+                    file_name, valid_time, lead_hours, feature_set, features)
+                VALUES (?1, ?2, ?3, ?4, ?5)
+            ",
+        )?;
+
+        for record in &records {
+            del_stmt.execute([&record.file_name as &dyn ToSql, &feature_set])?;
+
+            for (snd, _) in self.shared_soundings(record)?.iter() {
+                let (valid_time, vector) = match (snd.valid_time(), features.vector(snd)) {
+                    (Some(valid_time), Some(vector)) => (valid_time, vector),
+                    _ => continue,
+                };
+
+                let lead_hours = (valid_time - record.init_time).num_hours() as i32;
+                ins_stmt.execute([
+                    &record.file_name as &dyn ToSql,
+                    &valid_time,
+                    &lead_hours,
+                    &feature_set,
+                    &serde_json::to_string(&vector)?,
+                ])?;
+            }
+        }
+
+        drop((del_stmt, ins_stmt));
+        tx.commit()?;
+
+        Ok(records.len())
+    }
+
+    /// Find the soundings in the files matching a query most similar to `target`, most similar
+    /// first.
+    ///
+    /// Only soundings indexed with `index_analog_features` are searched. Returns at most `count`
+    /// analogs, or fewer if the target does not have all the features.
+    pub fn find_analogs(
+        &self,
+        target: &Sounding,
+        query: &FileQuery,
+        features: AnalogFeatures,
+        metric: DistanceMetric,
+        count: usize,
+    ) -> Result<Vec<Analog>, BufkitDataErr> {
+        let target_vector = match features.vector(target) {
+            Some(vector) => vector,
+            None => return Ok(vec![]),
+        };
+
+        let mut stmt = self.db_conn.prepare(
+            "
+                SELECT valid_time, lead_hours, features
+                FROM analog_features
+                WHERE file_name = ?1 AND feature_set = ?2
+            ",
+        )?;
+
+        let mut candidates: Vec<(Analog, Vec<f64>)> = vec![];
+        for record in self.file_records(query)? {
+            let mut rows =
+                stmt.query([&record.file_name as &dyn ToSql, &features.as_static_str()])?;
+            while let Some(row) = rows.next()? {
+                let vector: Vec<f64> = serde_json::from_str(&row.get::<_, String>(2)?)?;
+                if vector.len() != target_vector.len() {
+                    continue;
+                }
+
+                let analog = Analog {
+                    record: record.clone(),
+                    valid_time: row.get(0)?,
+                    lead_hours: row.get(1)?,
+                    distance: 0.0,
+                };
+                candidates.push((analog, vector));
+            }
+        }
+
+        let scales = std_devs(&candidates, target_vector.len());
+        for (analog, vector) in candidates.iter_mut() {
+            analog.distance = metric.distance(&target_vector, vector, &scales);
+        }
+
+        candidates.sort_by(|a, b| a.0.distance.total_cmp(&b.0.distance));
+
+        Ok(candidates
+            .into_iter()
+            .take(count)
+            .map(|(analog, _)| analog)
+            .collect())
+    }
+}
+
+/// The standard deviation of each feature among the candidates.
+fn std_devs(candidates: &[(Analog, Vec<f64>)], num_features: usize) -> Vec<f64> {
+    let n = candidates.len() as f64;
+
+    (0..num_features)
+        .map(|i| {
+            let mean = candidates.iter().map(|(_, v)| v[i]).sum::<f64>() / n;
+            let var = candidates
+                .iter()
+                .map(|(_, v)| (v[i] - mean).powi(2))
+                .sum::<f64>()
+                / n;
+
+            var.sqrt()
+        })
+        .collect()
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                          Unit Tests
+--------------------------------------------------------------------------------------------------*/
+#[cfg(test)]
+mod unit {
+    use super::*;
+    use crate::archive::unit::*; // test helpers.
+    use crate::{models::Model, site::StationNumber};
+
+    #[test]
+    fn test_distance_metrics() {
+        let (a, b) = ([0.0, 0.0], [3.0, 40.0]);
+        let scales = [1.0, 10.0];
+
+        assert_eq!(
+            DistanceMetric::Euclidean.distance(&a, &b, &scales),
+            3.0f64.hypot(40.0)
+        );
+        assert_eq!(DistanceMetric::Manhattan.distance(&a, &b, &scales), 43.0);
+        assert_eq!(DistanceMetric::Standardized.distance(&a, &b, &scales), 5.0);
+        // Features that do not vary are ignored.
+        assert_eq!(
+            DistanceMetric::Standardized.distance(&a, &b, &[0.0, 10.0]),
+            4.0
+        );
+    }
+
+    #[test]
+    fn test_find_analogs() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+        let query = FileQuery::new().station(kmso).model(Model::GFS);
+
+        let target = arch
+            .retrieve_most_recent_soundings(kmso, Model::NAM)
+            .unwrap()
+            .remove(12)
+            .0;
+
+        // Nothing is indexed yet.
+        let analogs = arch
+            .find_analogs(
+                &target,
+                &query,
+                AnalogFeatures::Thermo,
+                DistanceMetric::default(),
+                5,
+            )
+            .unwrap();
+        assert!(analogs.is_empty());
+
+        for features in AnalogFeatures::iter() {
+            assert_eq!(arch.index_analog_features(&query, features).unwrap(), 3);
+            // Indexing again replaces the old values.
+            assert_eq!(arch.index_analog_features(&query, features).unwrap(), 3);
+
+            for metric in [
+                DistanceMetric::Euclidean,
+                DistanceMetric::Manhattan,
+                DistanceMetric::Standardized,
+            ] {
+                let analogs = arch
+                    .find_analogs(&target, &query, features, metric, 5)
+                    .unwrap();
+                assert_eq!(analogs.len(), 5);
+                assert!(analogs.windows(2).all(|w| w[0].distance <= w[1].distance));
+                assert!(analogs.iter().all(|a| a.record.model == Model::GFS));
+            }
+        }
+
+        // A GFS sounding is its own best analog.
+        let record = arch.file_record(
+            kmso,
+            Model::GFS,
+            arch.inventory(kmso, Model::GFS).unwrap()[1],
+        );
+        let record = record.unwrap();
+        let own = arch.record_soundings(&record).unwrap().remove(6).0;
+        let best = arch
+            .find_analogs(
+                &own,
+                &query,
+                AnalogFeatures::Thermo,
+                DistanceMetric::Euclidean,
+                1,
+            )
+            .unwrap()
+            .remove(0);
+        assert_eq!(best.record, record);
+        assert_eq!(Some(best.valid_time), own.valid_time());
+        assert_eq!(best.distance, 0.0);
+
+        // Removing a file removes its features.
+        let count_features = |arch: &Archive| -> i64 {
+            arch.db_conn
+                .query_row(
+                    "SELECT COUNT(*) FROM analog_features WHERE file_name = ?1",
+                    [&record.file_name],
+                    |row| row.get(0),
+                )
+                .unwrap()
+        };
+        assert!(count_features(&arch) > 0);
+        arch.remove(kmso, Model::GFS, record.init_time).unwrap();
+        assert_eq!(count_features(&arch), 0);
+    }
+}
@@ -5,6 +5,7 @@ use metfor::Quantity;
 use std::{collections::HashSet, io::Read, str::FromStr};
 
 struct CleanMethodInternalSiteInfo {
+    tag: Option<String>,
     station_num: crate::site::StationNumber,
     model: crate::models::Model,
     id: Option<String>,
@@ -37,6 +38,9 @@ impl Archive {
         let mut files_not_in_index = file_system_vals.difference(&index_vals);
         self.handle_files_in_archive_but_not_index(&arch, &mut files_not_in_index)?;
 
+        println!("Rebuilding the station id history.");
+        arch.rebuild_id_history()?;
+
         println!("Compressing index.");
         arch.db_conn.execute("VACUUM", [])?;
 
@@ -45,7 +49,9 @@ impl Archive {
 
     #[inline]
     fn get_all_files_from_index(&self, arch: &Archive) -> Result<HashSet<String>, BufkitDataErr> {
-        let mut all_files_stmt = arch.db_conn.prepare("SELECT file_name FROM files")?;
+        let mut all_files_stmt = arch
+            .db_conn
+            .prepare("SELECT file_name FROM files UNION SELECT file_name FROM member_files")?;
 
         let index_vals: Result<HashSet<String>, BufkitDataErr> = all_files_stmt
             .query_map([], |row| row.get::<_, String>(0))?
@@ -75,12 +81,16 @@ impl Archive {
         let mut del_stmt = arch
             .db_conn
             .prepare("DELETE FROM files WHERE file_name = ?1")?;
+        let mut del_member_stmt = arch
+            .db_conn
+            .prepare("DELETE FROM member_files WHERE file_name = ?1")?;
 
         arch.db_conn
             .execute("BEGIN TRANSACTION", [])?;
 
         for missing_file in files_in_index_but_not_on_file_system {
             del_stmt.execute(&[missing_file])?;
+            del_member_stmt.execute([missing_file])?;
             println!("Removing {} from index.", missing_file);
         }
         arch.db_conn
@@ -111,11 +121,20 @@ impl Archive {
                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
             ",
         )?;
+        let mut insert_member_stmt = arch.db_conn.prepare(
+            "
+                INSERT INTO member_files (
+                    tag, station_num, model, init_time, end_time, file_name, id, lat, lon,
+                    elevation_m)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+            ",
+        )?;
 
         arch.db_conn
             .execute("BEGIN TRANSACTION", [])?;
         for extra_file in files_not_in_index {
             let message = if let Some(CleanMethodInternalSiteInfo {
+                tag,
                 station_num,
                 model,
                 id,
@@ -126,27 +145,39 @@ impl Archive {
             }) = arch.extract_site_info_from_file(&extra_file)
             {
                 if arch.site(station_num).is_none() {
-                    let site = crate::site::SiteInfo {
-                        station_num,
-                        ..crate::site::SiteInfo::default()
-                    };
-
-                    arch.add_site(&site)?;
+                    arch.add_site(&Archive::new_site_at(station_num, coords))?;
                 };
+                arch.fill_site_from_file(station_num, id.as_deref(), coords, elevation)?;
 
                 let station_num: u32 = station_num.into();
 
-                match insert_stmt.execute(&[
-                    &station_num as &dyn rusqlite::types::ToSql,
-                    &model.as_static_str() as &dyn rusqlite::types::ToSql,
-                    &init_time as &dyn rusqlite::types::ToSql,
-                    &end_time as &dyn rusqlite::types::ToSql,
-                    &extra_file,
-                    &id,
-                    &coords.lat,
-                    &coords.lon,
-                    &elevation.unpack(),
-                ]) {
+                let inserted = match tag {
+                    Some(tag) => insert_member_stmt.execute([
+                        &tag as &dyn rusqlite::types::ToSql,
+                        &station_num,
+                        &model.as_static_str(),
+                        &init_time,
+                        &end_time,
+                        &extra_file,
+                        &id,
+                        &coords.lat,
+                        &coords.lon,
+                        &elevation.unpack(),
+                    ]),
+                    None => insert_stmt.execute(&[
+                        &station_num as &dyn rusqlite::types::ToSql,
+                        &model.as_static_str() as &dyn rusqlite::types::ToSql,
+                        &init_time as &dyn rusqlite::types::ToSql,
+                        &end_time as &dyn rusqlite::types::ToSql,
+                        &extra_file,
+                        &id,
+                        &coords.lat,
+                        &coords.lon,
+                        &elevation.unpack(),
+                    ]),
+                };
+
+                match inserted {
                     Ok(_) => format!("Added {}", extra_file),
                     Err(_) => {
                         std::fs::remove_file(arch.data_root().join(extra_file))?;
@@ -170,9 +201,18 @@ impl Archive {
     fn extract_site_info_from_file(&self, fname: &str) -> Option<CleanMethodInternalSiteInfo> {
         let tokens: Vec<&str> = fname.split(|c| c == '_' || c == '.').collect();
 
-        if tokens.len() != 5 || tokens[3] != "buf" || tokens[4] != "gz" {
-            return None;
-        }
+        // Member files have the member tag, which may contain underscores, after the site id.
+        let (tokens, tag) = match tokens.as_slice() {
+            [init, model, id, tag @ .., "buf", "gz"] => {
+                let tag = if tag.is_empty() {
+                    None
+                } else {
+                    Some(tag.join("_"))
+                };
+                ([*init, *model, *id], tag)
+            }
+            _ => return None,
+        };
 
         let model = crate::models::Model::from_str(tokens[1]).ok()?;
 
@@ -197,6 +237,7 @@ impl Archive {
         };
 
         Some(CleanMethodInternalSiteInfo {
+            tag,
             station_num,
             model,
             id,
@@ -210,7 +251,12 @@ impl Archive {
 
 #[cfg(test)]
 mod unit {
-    use crate::archive::unit::*; // test helpers.
+    use crate::{
+        archive::unit::*, // test helpers.
+        errors::BufkitDataErr,
+        models::Model,
+        site::StationNumber,
+    };
 
     #[test]
     fn test_clean() {
@@ -223,4 +269,55 @@ mod unit {
 
         arch.clean().unwrap();
     }
+
+    #[test]
+    fn test_clean_member_files() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+
+        let (site_id, _, text) = get_test_data()
+            .into_iter()
+            .find(|(_, model, _)| *model == Model::GFS)
+            .unwrap();
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+
+        let record = arch
+            .add_member_file("arw_ctl", &site_id, Model::SREF, &text)
+            .unwrap();
+        let init_time = record.init_time;
+        let path = arch.data_root().join(&record.file_name);
+
+        // Member files are kept.
+        arch.clean().unwrap();
+        assert!(path.exists());
+        assert_eq!(
+            arch.member_file_record("arw_ctl", kmso, Model::SREF, init_time)
+                .unwrap(),
+            record
+        );
+
+        // Member files missing from the index are added back.
+        arch.db_conn
+            .execute("DELETE FROM member_files", [])
+            .unwrap();
+        arch.clean().unwrap();
+        assert!(path.exists());
+        assert_eq!(
+            arch.member_file_record("arw_ctl", kmso, Model::SREF, init_time)
+                .unwrap(),
+            record
+        );
+
+        // Member files missing from the archive are removed from the index.
+        std::fs::remove_file(&path).unwrap();
+        arch.clean().unwrap();
+        assert!(matches!(
+            arch.member_file_record("arw_ctl", kmso, Model::SREF, init_time),
+            Err(BufkitDataErr::NotInIndex)
+        ));
+    }
 }
@@ -0,0 +1,422 @@
+//! Climatologies of sounding parameters per station, model, month, and forecast lead time.
+
+use crate::{
+    archive::{Archive, FileQuery},
+    errors::BufkitDataErr,
+    models::Model,
+    site::StationNumber,
+};
+use chrono::{Datelike, Duration};
+use metfor::{HectoPascal, Quantity};
+use sounding_analysis::Sounding;
+use strum_macros::{EnumIter, EnumString, IntoStaticStr};
+
+/// Parameters a climatology can be built for.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, EnumString, IntoStaticStr, EnumIter, Hash)]
+#[strum(ascii_case_insensitive)]
+pub enum ClimoParam {
+    /// Precipitable water (mm).
+    #[strum(to_string = "pwat")]
+    Pwat,
+    /// Temperature at 500 hPa (C).
+    #[strum(to_string = "t500")]
+    T500,
+    /// Height of the lowest freezing level above sea level (m).
+    #[strum(to_string = "fzlv")]
+    FreezingLevel,
+    /// Surface based CAPE (J/kg), zero if there is no level of free convection.
+    #[strum(to_string = "cape")]
+    Cape,
+}
+
+impl ClimoParam {
+    /// Get the name used for the parameter in the index.
+    pub fn as_static_str(self) -> &'static str {
+        self.into()
+    }
+
+    /// Compute the value of the parameter for a sounding, if possible.
+    pub fn value(self, snd: &Sounding) -> Option<f64> {
+        match self {
+            ClimoParam::Pwat => sounding_analysis::precipitable_water(snd)
+                .ok()
+                .map(|pw| pw.unpack()),
+            ClimoParam::T500 => {
+                sounding_analysis::linear_interpolate_sounding(snd, HectoPascal(500.0))
+                    .ok()
+                    .and_then(|row| row.temperature.into_option())
+                    .map(|t| t.unpack())
+            }
+            ClimoParam::FreezingLevel => sounding_analysis::freezing_levels(snd)
+                .ok()
+                .and_then(|lvls| lvls.first().and_then(|lvl| lvl.height.into_option()))
+                .map(|h| h.unpack()),
+            ClimoParam::Cape => sounding_analysis::surface_parcel(snd)
+                .and_then(|pcl| sounding_analysis::lift_parcel(pcl, snd))
+                .ok()
+                // No level of free convection means there is no CAPE.
+                .map(|anal| anal.cape().into_option().map_or(0.0, |cape| cape.unpack())),
+        }
+    }
+}
+
+/// The distribution of a parameter for a station and model, in one month at one lead time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Climatology {
+    /// The station.
+    pub station_num: StationNumber,
+    /// The model.
+    pub model: Model,
+    /// The month of the valid times, 1 - 12.
+    pub month: u32,
+    /// The hours from the initialization time to the valid time.
+    pub lead_hours: i32,
+    /// The parameter.
+    pub param: ClimoParam,
+    /// The number of soundings the distribution was built from.
+    pub num_samples: usize,
+    /// The mean value.
+    pub mean: f64,
+    /// The 0th through 100th percentiles.
+    pub percentiles: Vec<f64>,
+}
+
+impl Climatology {
+    fn from_samples(
+        station_num: StationNumber,
+        model: Model,
+        month: u32,
+        lead_hours: i32,
+        param: ClimoParam,
+        mut samples: Vec<f64>,
+    ) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+
+        samples.sort_by(f64::total_cmp);
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+
+        let last = (samples.len() - 1) as f64;
+        let percentiles = (0..=100)
+            .map(|pct| {
+                let idx = pct as f64 / 100.0 * last;
+                let (below, above) = (idx.floor() as usize, idx.ceil() as usize);
+                let frac = idx - idx.floor();
+                samples[below] + frac * (samples[above] - samples[below])
+            })
+            .collect();
+
+        Some(Climatology {
+            station_num,
+            model,
+            month,
+            lead_hours,
+            param,
+            num_samples: samples.len(),
+            mean,
+            percentiles,
+        })
+    }
+
+    /// Get the value at a percentile, 0 - 100.
+    pub fn value_at(&self, percentile: f64) -> f64 {
+        let idx = percentile.clamp(0.0, 100.0);
+        let below = idx.floor() as usize;
+        let above = idx.ceil() as usize;
+        let frac = idx - idx.floor();
+
+        self.percentiles[below] + frac * (self.percentiles[above] - self.percentiles[below])
+    }
+
+    /// Get the percentile, 0 - 100, of a value in this distribution.
+    pub fn percentile_of(&self, value: f64) -> f64 {
+        let pcts = &self.percentiles;
+
+        if value <= pcts[0] {
+            return 0.0;
+        }
+
+        // Find the first percentile above the value.
+        match pcts.iter().position(|&pct| pct > value) {
+            None => 100.0,
+            Some(above) => {
+                let below = above - 1;
+                below as f64 + (value - pcts[below]) / (pcts[above] - pcts[below])
+            }
+        }
+    }
+}
+
+impl Archive {
+    /// Scan the archive and build the climatologies of the parameters for each lead time, storing
+    /// them in the index and replacing all of those built before for the station, model, and month.
+    ///
+    /// Every file for the station and model with a valid time in `month` at one of the lead times
+    /// is parsed, so this can take a while. Lead times without any soundings are left out.
+    pub fn build_climatology(
+        &self,
+        station_num: StationNumber,
+        model: Model,
+        month: u32,
+        lead_hours: &[i32],
+        params: &[ClimoParam],
+    ) -> Result<Vec<Climatology>, BufkitDataErr> {
+        if !(1..=12).contains(&month) {
+            return Err(BufkitDataErr::GeneralError(format!(
+                "invalid month: {}",
+                month
+            )));
+        }
+
+        let station_num = self.resolve_station_num(station_num)?;
+
+        // samples[lead index][param index]
+        let mut samples: Vec<Vec<Vec<f64>>> = vec![vec![vec![]; params.len()]; lead_hours.len()];
+
+        let query = FileQuery::new().station(station_num).model(model);
+        for record in self.file_records(&query)? {
+            let valid_times: Vec<_> = lead_hours
+                .iter()
+                .map(|&lead| record.init_time + Duration::hours(lead as i64))
+                .collect();
+
+            if !valid_times
+                .iter()
+                .any(|vt| vt.month() == month && record.covers(*vt))
+            {
+                continue;
+            }
+
+            for (snd, _) in self.shared_soundings(&record)?.iter() {
+                let valid_time = match snd.valid_time() {
+                    Some(vt) if vt.month() == month => vt,
+                    _ => continue,
+                };
+
+                if let Some(lead_idx) = valid_times.iter().position(|&vt| vt == valid_time) {
+                    for (param_idx, param) in params.iter().enumerate() {
+                        if let Some(val) = param.value(snd) {
+                            samples[lead_idx][param_idx].push(val);
+                        }
+                    }
+                }
+            }
+        }
+
+        let climos: Vec<Climatology> = lead_hours
+            .iter()
+            .zip(samples)
+            .flat_map(|(&lead, lead_samples)| {
+                params
+                    .iter()
+                    .zip(lead_samples)
+                    .filter_map(move |(&param, vals)| {
+                        Climatology::from_samples(station_num, model, month, lead, param, vals)
+                    })
+            })
+            .collect();
+
+        let tx = self.db_conn.unchecked_transaction()?;
+        tx.execute(
+            "DELETE FROM climatology WHERE station_num = ?1 AND model = ?2 AND month = ?3",
+            rusqlite::params![Into::<u32>::into(station_num), model.as_static_str(), month],
+        )?;
+        for climo in &climos {
+            tx.execute(
+                "
+                    INSERT OR REPLACE INTO climatology
+                        (station_num, model, month, lead_hours, param, num_samples, mean, percentiles)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+                ",
+                rusqlite::params![
+                    Into::<u32>::into(climo.station_num),
+                    climo.model.as_static_str(),
+                    climo.month,
+                    climo.lead_hours,
+                    climo.param.as_static_str(),
+                    climo.num_samples as i64,
+                    climo.mean,
+                    serde_json::to_string(&climo.percentiles)?,
+                ],
+            )?;
+        }
+        tx.commit()?;
+
+        Ok(climos)
+    }
+
+    /// Get a climatology stored in the index by `build_climatology`.
+    pub fn climatology(
+        &self,
+        station_num: StationNumber,
+        model: Model,
+        month: u32,
+        lead_hours: i32,
+        param: ClimoParam,
+    ) -> Result<Climatology, BufkitDataErr> {
+        let station_num = self.resolve_station_num(station_num)?;
+
+        let (num_samples, mean, percentiles): (i64, f64, String) = self
+            .db_conn
+            .query_row(
+                "
+                    SELECT num_samples, mean, percentiles
+                    FROM climatology
+                    WHERE station_num = ?1 AND model = ?2 AND month = ?3 AND lead_hours = ?4
+                        AND param = ?5
+                ",
+                rusqlite::params![
+                    Into::<u32>::into(station_num),
+                    model.as_static_str(),
+                    month,
+                    lead_hours,
+                    param.as_static_str(),
+                ],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .map_err(|err| match err {
+                rusqlite::Error::QueryReturnedNoRows => BufkitDataErr::NotInIndex,
+                err => BufkitDataErr::Database(err),
+            })?;
+
+        Ok(Climatology {
+            station_num,
+            model,
+            month,
+            lead_hours,
+            param,
+            num_samples: num_samples as usize,
+            mean,
+            percentiles: serde_json::from_str(&percentiles)?,
+        })
+    }
+
+    /// Get the percentile of a parameter from a sounding in the stored climatology for the month
+    /// of its valid time.
+    pub fn climatology_percentile(
+        &self,
+        station_num: StationNumber,
+        model: Model,
+        lead_hours: i32,
+        param: ClimoParam,
+        snd: &Sounding,
+    ) -> Result<f64, BufkitDataErr> {
+        let month = snd
+            .valid_time()
+            .ok_or(BufkitDataErr::MissingValidTime)?
+            .month();
+        let value = param.value(snd).ok_or(BufkitDataErr::NotEnoughData)?;
+
+        self.climatology(station_num, model, month, lead_hours, param)
+            .map(|climo| climo.percentile_of(value))
+    }
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                          Unit Tests
+--------------------------------------------------------------------------------------------------*/
+#[cfg(test)]
+mod unit {
+    use super::*;
+    use crate::archive::unit::*; // test helpers.
+
+    #[test]
+    fn test_percentiles() {
+        let kmso = StationNumber::from(727730);
+        let climo = Climatology::from_samples(
+            kmso,
+            Model::GFS,
+            4,
+            0,
+            ClimoParam::Pwat,
+            (0..=10).rev().map(f64::from).collect(),
+        )
+        .unwrap();
+
+        assert_eq!(climo.num_samples, 11);
+        assert_eq!(climo.mean, 5.0);
+        assert_eq!(climo.value_at(0.0), 0.0);
+        assert_eq!(climo.value_at(50.0), 5.0);
+        assert_eq!(climo.value_at(100.0), 10.0);
+        assert!((climo.value_at(25.5) - 2.55).abs() < 1.0e-9);
+        assert!((climo.percentile_of(2.55) - 25.5).abs() < 1.0e-9);
+        assert_eq!(climo.percentile_of(-1.0), 0.0);
+        assert_eq!(climo.percentile_of(11.0), 100.0);
+
+        assert_eq!("PWAT".parse::<ClimoParam>().unwrap(), ClimoParam::Pwat);
+        assert_eq!(ClimoParam::FreezingLevel.as_static_str(), "fzlv");
+    }
+
+    #[test]
+    fn test_build_climatology() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+        let params = [ClimoParam::Pwat, ClimoParam::T500, ClimoParam::Cape];
+
+        let climos = arch
+            .build_climatology(kmso, Model::GFS, 4, &[0, 24], &params)
+            .unwrap();
+        assert_eq!(climos.len(), 6);
+        for climo in &climos {
+            assert_eq!(climo.num_samples, 3);
+            assert_eq!(climo.percentiles.len(), 101);
+            assert!(climo.percentiles.windows(2).all(|w| w[0] <= w[1]));
+
+            let stored = arch
+                .climatology(kmso, Model::GFS, 4, climo.lead_hours, climo.param)
+                .unwrap();
+            assert_eq!(stored.num_samples, climo.num_samples);
+            assert_eq!(stored.mean, climo.mean);
+            assert!(
+                stored
+                    .percentiles
+                    .iter()
+                    .zip(&climo.percentiles)
+                    .all(|(a, b)| (a - b).abs() < 1.0e-9)
+            );
+        }
+
+        let t500 = arch
+            .climatology(kmso, Model::GFS, 4, 24, ClimoParam::T500)
+            .unwrap();
+        assert!(t500.mean < 0.0 && t500.mean > -50.0);
+
+        let snd = arch
+            .retrieve_most_recent_soundings(kmso, Model::GFS)
+            .unwrap()
+            .remove(0)
+            .0;
+        let pct = arch
+            .climatology_percentile(kmso, Model::GFS, 0, ClimoParam::Pwat, &snd)
+            .unwrap();
+        assert!((0.0..=100.0).contains(&pct));
+
+        assert!(
+            arch.build_climatology(kmso, Model::GFS, 7, &[0], &params)
+                .unwrap()
+                .is_empty()
+        );
+        match arch.climatology(kmso, Model::GFS, 7, 0, ClimoParam::Pwat) {
+            Err(BufkitDataErr::NotInIndex) => {}
+            x => panic!("Should not be found: {:?}", x),
+        }
+
+        // Rebuilding replaces everything built before, even when there are no soundings now.
+        assert!(
+            arch.build_climatology(kmso, Model::GFS, 4, &[240], &params)
+                .unwrap()
+                .is_empty()
+        );
+        match arch.climatology(kmso, Model::GFS, 4, 24, ClimoParam::T500) {
+            Err(BufkitDataErr::NotInIndex) => {}
+            x => panic!("Should not be found: {:?}", x),
+        }
+    }
+}
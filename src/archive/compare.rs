@@ -0,0 +1,291 @@
+//! Compare runs of different models at the valid times they have in common.
+
+use crate::{
+    archive::{Archive, FileRecord, ParsedSounding, hourly_params::HourlyParams},
+    errors::BufkitDataErr,
+    models::Model,
+    site::StationNumber,
+};
+use chrono::NaiveDateTime;
+use std::collections::BTreeMap;
+
+/// How to line up runs that output data at different times, e.g. hourly and 3 hourly.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimeAlignment {
+    /// Only use the valid times every run has data for.
+    #[default]
+    SharedTimes,
+    /// Use every valid time any run has data for, interpolating in time for the runs that do not.
+    /// Times outside the period covered by all the runs are left out.
+    Interpolate,
+}
+
+/// Several model runs lined up on common valid times.
+#[derive(Clone, Debug)]
+pub struct ModelComparison<T> {
+    /// The files that were compared, in the order they were requested.
+    pub runs: Vec<FileRecord>,
+    /// The valid times with one value per run, in the same order as `runs`.
+    pub times: Vec<(NaiveDateTime, Vec<T>)>,
+}
+
+impl Archive {
+    /// Line up a surface or station parameter from several model runs on common valid times.
+    ///
+    /// Each run is a model and an initialization time, or `None` for the most recent run of that
+    /// model. See `param_time_series` for the parameter names.
+    pub fn compare_params(
+        &self,
+        station_num: StationNumber,
+        runs: &[(Model, Option<NaiveDateTime>)],
+        param: &str,
+        alignment: TimeAlignment,
+    ) -> Result<ModelComparison<f64>, BufkitDataErr> {
+        let param = param.to_uppercase();
+        let records = self.comparison_records(station_num, runs)?;
+
+        let mut series: Vec<Vec<(NaiveDateTime, f64)>> = Vec::with_capacity(records.len());
+        for record in &records {
+            let text = self.load_file(&record.file_name)?;
+            let vals = HourlyParams::parse(&text)?
+                .hours
+                .into_iter()
+                .filter_map(|(valid_time, vals)| vals.get(&param).map(|&val| (valid_time, val)))
+                .collect();
+
+            series.push(vals);
+        }
+
+        let times = match alignment {
+            TimeAlignment::SharedTimes => shared_times(series),
+            TimeAlignment::Interpolate => interpolated_times(&series),
+        };
+
+        Ok(ModelComparison {
+            runs: records,
+            times,
+        })
+    }
+
+    /// Line up the soundings from several model runs on the valid times they all have.
+    ///
+    /// Each run is a model and an initialization time, or `None` for the most recent run of that
+    /// model. Soundings are not interpolated in time, so only the shared valid times are returned.
+    pub fn compare_soundings(
+        &self,
+        station_num: StationNumber,
+        runs: &[(Model, Option<NaiveDateTime>)],
+    ) -> Result<ModelComparison<ParsedSounding>, BufkitDataErr> {
+        let records = self.comparison_records(station_num, runs)?;
+
+        let mut series: Vec<Vec<(NaiveDateTime, ParsedSounding)>> =
+            Vec::with_capacity(records.len());
+        for record in &records {
+            let snds = self
+                .record_soundings(record)?
+                .into_iter()
+                .filter_map(|parsed| parsed.0.valid_time().map(|vt| (vt, parsed)))
+                .collect();
+
+            series.push(snds);
+        }
+
+        Ok(ModelComparison {
+            runs: records,
+            times: shared_times(series),
+        })
+    }
+
+    fn comparison_records(
+        &self,
+        station_num: StationNumber,
+        runs: &[(Model, Option<NaiveDateTime>)],
+    ) -> Result<Vec<FileRecord>, BufkitDataErr> {
+        runs.iter()
+            .map(|&(model, init_time)| match init_time {
+                Some(init_time) => self.file_record(station_num, model, init_time),
+                None => self
+                    .inventory_records(station_num, model)?
+                    .pop()
+                    .ok_or(BufkitDataErr::NotInIndex),
+            })
+            .collect()
+    }
+}
+
+/// Keep the valid times present in every series.
+pub(crate) fn shared_times<T>(
+    series: Vec<Vec<(NaiveDateTime, T)>>,
+) -> Vec<(NaiveDateTime, Vec<T>)> {
+    let num_series = series.len();
+
+    let mut by_time: BTreeMap<NaiveDateTime, Vec<T>> = BTreeMap::new();
+    for (i, vals) in series.into_iter().enumerate() {
+        for (valid_time, val) in vals {
+            let at_time = by_time.entry(valid_time).or_default();
+            // Only add to times every earlier series had, so the values stay in order.
+            if at_time.len() == i {
+                at_time.push(val);
+            }
+        }
+    }
+
+    by_time
+        .into_iter()
+        .filter(|(_, vals)| vals.len() == num_series)
+        .collect()
+}
+
+/// Use every valid time in the period all the series cover, interpolating where a series is
+/// missing a time. Each series must be sorted by valid time.
+fn interpolated_times(series: &[Vec<(NaiveDateTime, f64)>]) -> Vec<(NaiveDateTime, Vec<f64>)> {
+    let start = series
+        .iter()
+        .filter_map(|vals| vals.first())
+        .map(|v| v.0)
+        .max();
+    let end = series
+        .iter()
+        .filter_map(|vals| vals.last())
+        .map(|v| v.0)
+        .min();
+    let (start, end) = match (start, end) {
+        (Some(start), Some(end)) if series.iter().all(|vals| !vals.is_empty()) => (start, end),
+        _ => return vec![],
+    };
+
+    let mut times: Vec<NaiveDateTime> = series
+        .iter()
+        .flat_map(|vals| vals.iter().map(|v| v.0))
+        .filter(|&vt| vt >= start && vt <= end)
+        .collect();
+    times.sort();
+    times.dedup();
+
+    times
+        .into_iter()
+        .map(|vt| {
+            let vals = series.iter().map(|vals| interpolate(vals, vt)).collect();
+            (vt, vals)
+        })
+        .collect()
+}
+
+/// Linearly interpolate a sorted series to a time inside of it.
+fn interpolate(series: &[(NaiveDateTime, f64)], valid_time: NaiveDateTime) -> f64 {
+    match series.binary_search_by_key(&valid_time, |v| v.0) {
+        Ok(i) => series[i].1,
+        Err(i) => {
+            let (t0, v0) = series[i - 1];
+            let (t1, v1) = series[i];
+            let frac = (valid_time - t0).num_seconds() as f64 / (t1 - t0).num_seconds() as f64;
+
+            v0 + frac * (v1 - v0)
+        }
+    }
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                          Unit Tests
+--------------------------------------------------------------------------------------------------*/
+#[cfg(test)]
+mod unit {
+    use super::*;
+    use crate::archive::unit::*; // test helpers.
+
+    use chrono::NaiveDate;
+
+    fn time(day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2017, 4, day)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_align_series() {
+        let hourly: Vec<_> = (0..7).map(|h| (time(1, h), h as f64)).collect();
+        let three_hourly = vec![(time(1, 3), 30.0), (time(1, 6), 60.0), (time(1, 9), 90.0)];
+
+        let shared = shared_times(vec![hourly.clone(), three_hourly.clone()]);
+        assert_eq!(
+            shared,
+            vec![(time(1, 3), vec![3.0, 30.0]), (time(1, 6), vec![6.0, 60.0])]
+        );
+
+        let interp = interpolated_times(&[hourly, three_hourly]);
+        let times: Vec<_> = interp.iter().map(|(vt, _)| *vt).collect();
+        assert_eq!(times, (3..7).map(|h| time(1, h)).collect::<Vec<_>>());
+        assert_eq!(interp[1], (time(1, 4), vec![4.0, 40.0]));
+
+        assert!(interpolated_times(&[vec![], vec![(time(1, 0), 1.0)]]).is_empty());
+    }
+
+    #[test]
+    fn test_compare_params() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+        let runs = [(Model::GFS, None), (Model::NAM, Some(time(1, 12)))];
+
+        let shared = arch
+            .compare_params(kmso, &runs, "t2ms", TimeAlignment::SharedTimes)
+            .unwrap();
+        assert_eq!(shared.runs[0].init_time, time(1, 18));
+        assert_eq!(shared.runs[1].init_time, time(1, 12));
+        assert!(!shared.times.is_empty());
+        assert!(shared.times.iter().all(|(_, vals)| vals.len() == 2));
+        assert!(shared.times.windows(2).all(|w| w[0].0 < w[1].0));
+
+        let interp = arch
+            .compare_params(kmso, &runs, "T2MS", TimeAlignment::Interpolate)
+            .unwrap();
+        assert!(interp.times.len() >= shared.times.len());
+        assert_eq!(interp.times[0].0, time(1, 18));
+        assert!(interp.times.last().unwrap().0 <= shared.runs[1].end_time);
+        for (vt, vals) in &shared.times {
+            let (_, ivals) = interp.times.iter().find(|(ivt, _)| ivt == vt).unwrap();
+            assert_eq!(ivals, vals);
+        }
+
+        assert!(
+            arch.compare_params(
+                kmso,
+                &[(Model::GFS, Some(time(3, 0)))],
+                "T2MS",
+                TimeAlignment::SharedTimes
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_compare_soundings() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+        let runs = [(Model::GFS, None), (Model::NAM, None)];
+
+        let cmp = arch.compare_soundings(kmso, &runs).unwrap();
+        assert!(!cmp.times.is_empty());
+        for (vt, snds) in &cmp.times {
+            assert_eq!(snds.len(), 2);
+            assert!(snds.iter().all(|(snd, _)| snd.valid_time() == Some(*vt)));
+            assert!(
+                cmp.runs
+                    .iter()
+                    .all(|run| *vt >= run.init_time && *vt <= run.end_time)
+            );
+        }
+    }
+}
@@ -0,0 +1,474 @@
+//! Index of parameters derived from each forecast hour of the files in the archive.
+
+use crate::{
+    archive::{Archive, FileQuery, FileRecord, ParsedSounding, hazards},
+    errors::BufkitDataErr,
+};
+use chrono::NaiveDateTime;
+use metfor::{HectoPascal, Meters, Quantity};
+use rusqlite::ToSql;
+use std::{collections::HashMap, sync::Arc};
+
+/// Which parameters to compute and store in the index when a file is added.
+///
+/// The station parameters are the values in the `STNPRM` block of each forecast hour, stored with
+/// their bufkit names: SHOW, LIFT, SWET, KINX, LCLP, PWAT, TOTL, CAPE, LCLT, CINS, EQLV, LFCT, and
+/// BRCH. The full analysis adds values computed from the profiles with `sounding-analysis`:
+///
+///  * SBCAPE - surface based CAPE (J/kg)
+///  * SBCIN - surface based CIN (J/kg)
+///  * LCLH - surface based LCL height above ground level (m)
+///  * FZLV - height of the lowest freezing level above sea level (m)
+///  * SHR6 - magnitude of the 0-6 km bulk wind shear (m/s)
+///  * HAINES - Haines index, the low, mid, or high elevation variant depending on the surface
+///    pressure
+///
+/// PWAT is also computed from the profile for files that do not have it in the `STNPRM` block.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DerivedIndexing {
+    /// Do not compute any parameters.
+    #[default]
+    Off,
+    /// Only store the values from the `STNPRM` block, this is cheap.
+    StationParams,
+    /// Store the station parameters and the values computed from the profiles.
+    Full,
+}
+
+/// The derived parameters for one forecast hour of a file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DerivedParams {
+    /// The valid time of the forecast hour.
+    pub valid_time: NaiveDateTime,
+    /// The hours since the model initialization time.
+    pub lead_hours: i32,
+    /// The parameter values by name.
+    pub values: HashMap<String, f64>,
+}
+
+impl Archive {
+    /// Set which parameters are computed and stored in the index when files are added.
+    ///
+    /// This is off by default. Files already in the archive are not changed, use
+    /// `index_derived_params` for those.
+    pub fn set_derived_indexing(&mut self, level: DerivedIndexing) {
+        self.derived_indexing = level;
+    }
+
+    /// Compute and store the derived parameters for all the files matching a query, replacing any
+    /// that were already stored. Using `DerivedIndexing::Off` removes them from the index. The
+    /// hazard indices stored by `index_hazards` are left alone, except HAINES.
+    ///
+    /// Returns the number of files indexed.
+    pub fn index_derived_params(
+        &self,
+        query: &FileQuery,
+        level: DerivedIndexing,
+    ) -> Result<usize, BufkitDataErr> {
+        let records = self.file_records(query)?;
+
+        for record in &records {
+            let soundings = if level == DerivedIndexing::Off {
+                Arc::from(vec![])
+            } else {
+                self.shared_soundings(record)?
+            };
+
+            self.store_derived_params(&record.file_name, record.init_time, &soundings, level)?;
+        }
+
+        Ok(records.len())
+    }
+
+    /// Get the derived parameters stored in the index for a file, in order of valid time.
+    pub fn derived_params(&self, record: &FileRecord) -> Result<Vec<DerivedParams>, BufkitDataErr> {
+        let mut stmt = self.db_conn.prepare(
+            "
+                SELECT valid_time, lead_hours, param, value
+                FROM derived_params
+                WHERE file_name = ?1
+                ORDER BY valid_time ASC
+            ",
+        )?;
+
+        let mut hours: Vec<DerivedParams> = vec![];
+        let mut rows = stmt.query([&record.file_name])?;
+        while let Some(row) = rows.next()? {
+            let valid_time: NaiveDateTime = row.get(0)?;
+            let param: String = row.get(2)?;
+            let value: f64 = row.get(3)?;
+
+            match hours.last_mut() {
+                Some(hour) if hour.valid_time == valid_time => {
+                    hour.values.insert(param, value);
+                }
+                _ => hours.push(DerivedParams {
+                    valid_time,
+                    lead_hours: row.get(1)?,
+                    values: HashMap::from([(param, value)]),
+                }),
+            }
+        }
+
+        Ok(hours)
+    }
+
+    /// Get every stored value of a derived parameter for the files matching a query, along with
+    /// the file and valid time it belongs to.
+    pub fn derived_param_values(
+        &self,
+        query: &FileQuery,
+        param: &str,
+    ) -> Result<Vec<(FileRecord, NaiveDateTime, f64)>, BufkitDataErr> {
+        let mut stmt = self.db_conn.prepare(
+            "
+                SELECT valid_time, value
+                FROM derived_params
+                WHERE file_name = ?1 AND param = ?2
+                ORDER BY valid_time ASC
+            ",
+        )?;
+
+        let mut vals = vec![];
+        for record in self.file_records(query)? {
+            let hours = stmt
+                .query_map([&record.file_name as &dyn ToSql, &param], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })?
+                .collect::<Result<Vec<(NaiveDateTime, f64)>, _>>()?;
+
+            for (valid_time, value) in hours {
+                vals.push((record.clone(), valid_time, value));
+            }
+        }
+
+        Ok(vals)
+    }
+
+    /// Parse the soundings of a file being added so its parameters can be stored, or `None` if
+    /// indexing is off. This is done before the file is stored, so a file that can't be indexed
+    /// is never added.
+    pub(crate) fn parse_for_index(
+        &self,
+        file_name: &str,
+        text: &str,
+    ) -> Result<Option<Vec<ParsedSounding>>, BufkitDataErr> {
+        if self.derived_indexing == DerivedIndexing::Off {
+            return Ok(None);
+        }
+
+        let soundings = sounding_bufkit::BufkitData::init(text, file_name)?
+            .into_iter()
+            .collect();
+
+        Ok(Some(soundings))
+    }
+
+    fn store_derived_params(
+        &self,
+        file_name: &str,
+        init_time: NaiveDateTime,
+        soundings: &[ParsedSounding],
+        level: DerivedIndexing,
+    ) -> Result<(), BufkitDataErr> {
+        let tx = self.db_conn.unchecked_transaction()?;
+        self.write_derived_params(file_name, init_time, soundings, level)?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Replace the parameters stored for a file. This should be done inside a transaction.
+    pub(crate) fn write_derived_params(
+        &self,
+        file_name: &str,
+        init_time: NaiveDateTime,
+        soundings: &[ParsedSounding],
+        level: DerivedIndexing,
+    ) -> Result<(), BufkitDataErr> {
+        // The hazard indices are managed by `index_hazards`.
+        let [_, hdw, ptype, snrat] = hazards::HAZARD_PARAMS;
+        self.db_conn.execute(
+            "DELETE FROM derived_params WHERE file_name = ?1 AND param NOT IN (?2, ?3, ?4)",
+            [file_name, hdw, ptype, snrat],
+        )?;
+
+        let mut stmt = self.db_conn.prepare(
+            "
+                INSERT OR REPLACE INTO derived_params
+                    (file_name, valid_time, lead_hours, param, value)
+                VALUES (?1, ?2, ?3, ?4, ?5)
+            ",
+        )?;
+
+        for (snd, bufkit_params) in soundings {
+            let valid_time = match snd.valid_time() {
+                Some(valid_time) => valid_time,
+                None => continue,
+            };
+            let lead_hours = snd
+                .lead_time()
+                .into_option()
+                .unwrap_or_else(|| (valid_time - init_time).num_hours() as i32);
+
+            let mut params = station_params(bufkit_params);
+            if level == DerivedIndexing::Full {
+                params.extend(analysis_params(snd, &params));
+            }
+
+            for (param, value) in params {
+                if value.is_finite() {
+                    stmt.execute([
+                        &file_name as &dyn ToSql,
+                        &valid_time,
+                        &lead_hours,
+                        &param,
+                        &value,
+                    ])?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Map the station parameters parsed by `sounding-bufkit` back to their names in the file.
+fn station_params(bufkit_params: &HashMap<&'static str, f64>) -> Vec<(&'static str, f64)> {
+    const NAMES: &[(&str, &str)] = &[
+        ("Showalter", "SHOW"),
+        ("LI", "LIFT"),
+        ("SWeT", "SWET"),
+        ("K", "KINX"),
+        ("LCL", "LCLP"),
+        ("PWAT", "PWAT"),
+        ("TotalTotals", "TOTL"),
+        ("CAPE", "CAPE"),
+        ("LCLTemperature", "LCLT"),
+        ("CIN", "CINS"),
+        ("EquilibriumLevel", "EQLV"),
+        ("LFC", "LFCT"),
+        ("BulkRichardsonNumber", "BRCH"),
+    ];
+
+    NAMES
+        .iter()
+        .filter_map(|&(key, name)| bufkit_params.get(key).map(|&val| (name, val)))
+        .collect()
+}
+
+/// Compute the parameters that need the profiles. Any that can't be computed are left out.
+fn analysis_params(
+    snd: &sounding_analysis::Sounding,
+    station_params: &[(&'static str, f64)],
+) -> Vec<(&'static str, f64)> {
+    let mut params = vec![];
+
+    if let Ok(anal) = sounding_analysis::surface_parcel(snd)
+        .and_then(|pcl| sounding_analysis::lift_parcel(pcl, snd))
+    {
+        if let Some(cape) = anal.cape().into_option() {
+            params.push(("SBCAPE", cape.unpack()));
+        }
+        if let Some(cin) = anal.cin().into_option() {
+            params.push(("SBCIN", cin.unpack()));
+        }
+        if let Some(lcl) = anal.lcl_height_agl().into_option() {
+            params.push(("LCLH", lcl.unpack()));
+        }
+    }
+
+    if !station_params.iter().any(|&(name, _)| name == "PWAT")
+        && let Ok(pwat) = sounding_analysis::precipitable_water(snd)
+    {
+        params.push(("PWAT", pwat.unpack()));
+    }
+
+    if let Some(fzlv) = sounding_analysis::freezing_levels(snd)
+        .ok()
+        .and_then(|levels| levels.first().and_then(|lvl| lvl.height.into_option()))
+    {
+        params.push(("FZLV", fzlv.unpack()));
+    }
+
+    if let Some(shear) = sounding_analysis::layer_agl(snd, Meters(6000.0))
+        .ok()
+        .and_then(|lyr| lyr.wind_shear())
+    {
+        params.push(("SHR6", shear.u.unpack().hypot(shear.v.unpack())));
+    }
+
+    if let Some(haines) = haines(snd) {
+        params.push(("HAINES", haines));
+    }
+
+    params
+}
+
+/// The Haines index, using the low, mid, or high elevation variant depending on how much of the
+/// atmosphere is above the surface.
+pub(crate) fn haines(snd: &sounding_analysis::Sounding) -> Option<f64> {
+    let sfc_p = snd.station_pressure().into_option()?.unpack();
+
+    // (bottom hPa, top hPa, stability breaks, moisture breaks)
+    let (bottom, top, stability, moisture) = if sfc_p >= 950.0 {
+        (950.0, 850.0, (4.0, 8.0), (6.0, 10.0))
+    } else if sfc_p >= 850.0 {
+        (850.0, 700.0, (6.0, 11.0), (6.0, 13.0))
+    } else {
+        (700.0, 500.0, (18.0, 22.0), (15.0, 21.0))
+    };
+
+    let bottom_row =
+        sounding_analysis::linear_interpolate_sounding(snd, HectoPascal(bottom)).ok()?;
+    let top_row = sounding_analysis::linear_interpolate_sounding(snd, HectoPascal(top)).ok()?;
+
+    // The moisture term uses the dew point depression at 850 hPa for the low and mid variants.
+    let moist_row = if bottom == 950.0 {
+        sounding_analysis::linear_interpolate_sounding(snd, HectoPascal(850.0)).ok()?
+    } else {
+        bottom_row
+    };
+
+    let lapse = bottom_row.temperature.into_option()?.unpack()
+        - top_row.temperature.into_option()?.unpack();
+    let depression =
+        moist_row.temperature.into_option()?.unpack() - moist_row.dew_point.into_option()?.unpack();
+
+    let category = |val: f64, (low, high): (f64, f64)| {
+        if val < low {
+            1.0
+        } else if val < high {
+            2.0
+        } else {
+            3.0
+        }
+    };
+
+    Some(category(lapse, stability) + category(depression, moisture))
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                          Unit Tests
+--------------------------------------------------------------------------------------------------*/
+#[cfg(test)]
+mod unit {
+    use super::*;
+    use crate::{
+        archive::{AnalogFeatures, unit::*}, // test helpers.
+        models::Model,
+        site::StationNumber,
+    };
+
+    #[test]
+    fn test_derived_params_at_ingest() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        arch.set_derived_indexing(DerivedIndexing::Full);
+        fill_test_archive(&mut arch);
+
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+        let record = arch.inventory_records(kmso, Model::GFS).unwrap().remove(0);
+
+        let hours = arch.derived_params(&record).unwrap();
+        assert!(!hours.is_empty());
+        assert_eq!(hours[0].valid_time, record.init_time);
+        assert_eq!(hours[0].lead_hours, 0);
+        assert!(hours.windows(2).all(|w| w[0].valid_time < w[1].valid_time));
+        for name in ["SHOW", "PWAT", "CAPE", "FZLV", "SHR6", "HAINES"] {
+            assert!(hours[0].values.contains_key(name), "missing {}", name);
+        }
+
+        let haines = hours[0].values["HAINES"];
+        assert!((2.0..=6.0).contains(&haines));
+
+        // Removing a file removes its parameters.
+        arch.remove(kmso, Model::GFS, record.init_time).unwrap();
+        assert!(arch.derived_params(&record).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_replacing_file_clears_index() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        arch.set_derived_indexing(DerivedIndexing::Full);
+        fill_test_archive(&mut arch);
+
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+        let query = FileQuery::new().model(Model::GFS);
+        let record = arch.file_records(&query).unwrap().remove(0);
+        arch.index_hazards(&query).unwrap();
+        arch.index_analog_features(&query, AnalogFeatures::Thermo)
+            .unwrap();
+
+        let count_rows = |arch: &Archive, table: &str| -> i64 {
+            arch.db_conn
+                .query_row(
+                    &format!("SELECT COUNT(*) FROM {} WHERE file_name = ?1", table),
+                    [&record.file_name],
+                    |row| row.get(0),
+                )
+                .unwrap()
+        };
+        assert!(count_rows(&arch, "derived_params") > 0);
+        assert!(count_rows(&arch, "analog_features") > 0);
+
+        // Nothing computed from the old contents is kept, even with indexing off.
+        let text = arch.retrieve(kmso, Model::GFS, record.init_time).unwrap();
+        arch.set_derived_indexing(DerivedIndexing::Off);
+        arch.add("kmso", None, None, Model::GFS, &text).unwrap();
+        assert_eq!(arch.file_records(&query).unwrap()[0], record);
+        assert_eq!(count_rows(&arch, "derived_params"), 0);
+        assert_eq!(count_rows(&arch, "analog_features"), 0);
+    }
+
+    #[test]
+    fn test_query_derived_params() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+
+        let query = FileQuery::new().model(Model::GFS);
+        let record = arch.file_records(&query).unwrap().remove(0);
+        assert!(arch.derived_params(&record).unwrap().is_empty());
+
+        let indexed = arch
+            .index_derived_params(&query, DerivedIndexing::StationParams)
+            .unwrap();
+        assert_eq!(indexed, 3);
+
+        let hours = arch.derived_params(&record).unwrap();
+        assert!(hours[0].values.contains_key("PWAT"));
+        assert!(!hours[0].values.contains_key("SBCAPE"));
+
+        let pwats = arch.derived_param_values(&query, "PWAT").unwrap();
+        assert!(pwats.len() > 3);
+        let max_pwat = pwats.iter().map(|(_, _, pw)| *pw).fold(f64::MIN, f64::max);
+
+        let wet = query.clone().param_range("PWAT", max_pwat, f64::INFINITY);
+        let wet_files = arch.file_records(&wet).unwrap();
+        assert!(!wet_files.is_empty() && wet_files.len() <= 3);
+        assert!(
+            arch.file_records(&wet.param_range("PWAT", f64::NEG_INFINITY, -1.0))
+                .unwrap()
+                .is_empty()
+        );
+
+        arch.index_derived_params(&query, DerivedIndexing::Off)
+            .unwrap();
+        assert!(
+            arch.derived_param_values(&query, "PWAT")
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,256 @@
+//! Run to run consistency of forecasts for a single valid time, a.k.a. dprog/dt.
+
+use crate::{
+    archive::{Archive, SeriesPoint, StitchRule},
+    errors::BufkitDataErr,
+    models::Model,
+    site::StationNumber,
+};
+use chrono::NaiveDateTime;
+
+/// The forecasts of a parameter at one valid time from every run that covered it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DprogDt {
+    /// The valid time of the forecasts.
+    pub valid_time: NaiveDateTime,
+    /// The forecast from each run, oldest run first.
+    pub forecasts: Vec<(Model, SeriesPoint)>,
+}
+
+impl DprogDt {
+    /// Only keep the forecasts from one model.
+    pub fn for_model(&self, model: Model) -> DprogDt {
+        DprogDt {
+            valid_time: self.valid_time,
+            forecasts: self
+                .forecasts
+                .iter()
+                .filter(|(mdl, _)| *mdl == model)
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// The forecast values, oldest run first.
+    pub fn values(&self) -> impl Iterator<Item = f64> + '_ {
+        self.forecasts.iter().map(|(_, pnt)| pnt.value)
+    }
+
+    /// The mean of the forecasts.
+    pub fn mean(&self) -> Option<f64> {
+        if self.forecasts.is_empty() {
+            return None;
+        }
+
+        Some(self.values().sum::<f64>() / self.forecasts.len() as f64)
+    }
+
+    /// The standard deviation of the forecasts, the spread between runs or models.
+    pub fn spread(&self) -> Option<f64> {
+        let mean = self.mean()?;
+        let var = self.values().map(|val| (val - mean).powi(2)).sum::<f64>()
+            / self.forecasts.len() as f64;
+
+        Some(var.sqrt())
+    }
+
+    /// The difference between the largest and smallest forecasts.
+    pub fn range(&self) -> Option<f64> {
+        let max = self.values().reduce(f64::max)?;
+        let min = self.values().reduce(f64::min)?;
+
+        Some(max - min)
+    }
+
+    /// The change in the forecast per hour of initialization time, from a least squares fit.
+    ///
+    /// Positive values mean newer runs forecast a larger value. Needs forecasts from at least two
+    /// different initialization times of a single model, use `for_model` to pick one from a
+    /// `multi_model_dprog_dt`. Forecasts from more than one model give `None`.
+    pub fn trend(&self) -> Option<f64> {
+        if !self.single_model() {
+            return None;
+        }
+
+        let first = self.forecasts.first()?.1.init_time;
+        let xs: Vec<f64> = self
+            .forecasts
+            .iter()
+            .map(|(_, pnt)| (pnt.init_time - first).num_minutes() as f64 / 60.0)
+            .collect();
+
+        let n = xs.len() as f64;
+        let x_mean = xs.iter().sum::<f64>() / n;
+        let y_mean = self.mean()?;
+
+        let (cov, var) = xs
+            .iter()
+            .zip(self.values())
+            .fold((0.0, 0.0), |(cov, var), (x, y)| {
+                (
+                    cov + (x - x_mean) * (y - y_mean),
+                    var + (x - x_mean).powi(2),
+                )
+            });
+
+        if var == 0.0 { None } else { Some(cov / var) }
+    }
+
+    /// The change from the second newest run to the newest run.
+    ///
+    /// Like `trend`, this needs forecasts from a single model so it is not mixed up with the
+    /// spread between models. Forecasts from more than one model give `None`.
+    pub fn latest_change(&self) -> Option<f64> {
+        if !self.single_model() {
+            return None;
+        }
+
+        match self.forecasts.as_slice() {
+            [.., (_, prev), (_, last)] => Some(last.value - prev.value),
+            _ => None,
+        }
+    }
+
+    fn single_model(&self) -> bool {
+        self.forecasts.windows(2).all(|w| w[0].0 == w[1].0)
+    }
+}
+
+impl Archive {
+    /// Get the forecasts of a surface or station parameter valid at a time from every run of a
+    /// model that covered it. See `param_time_series` for the parameter names.
+    pub fn dprog_dt(
+        &self,
+        station_num: StationNumber,
+        model: Model,
+        param: &str,
+        valid_time: NaiveDateTime,
+    ) -> Result<DprogDt, BufkitDataErr> {
+        self.multi_model_dprog_dt(station_num, &[model], param, valid_time)
+    }
+
+    /// Get the forecasts of a surface or station parameter valid at a time from every run of
+    /// several models, for the spread between models.
+    pub fn multi_model_dprog_dt(
+        &self,
+        station_num: StationNumber,
+        models: &[Model],
+        param: &str,
+        valid_time: NaiveDateTime,
+    ) -> Result<DprogDt, BufkitDataErr> {
+        let mut forecasts = vec![];
+        for &model in models {
+            let series = self.param_time_series(
+                station_num,
+                model,
+                param,
+                valid_time,
+                valid_time,
+                StitchRule::AllRuns,
+            )?;
+
+            forecasts.extend(series.into_iter().map(|pnt| (model, pnt)));
+        }
+
+        forecasts.sort_by_key(|(model, pnt)| (pnt.init_time, *model));
+
+        Ok(DprogDt {
+            valid_time,
+            forecasts,
+        })
+    }
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                          Unit Tests
+--------------------------------------------------------------------------------------------------*/
+#[cfg(test)]
+mod unit {
+    use super::*;
+    use crate::archive::unit::*; // test helpers.
+
+    use chrono::NaiveDate;
+
+    fn time(day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2017, 4, day)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+
+    fn point(init_hour: u32, value: f64) -> (Model, SeriesPoint) {
+        let init_time = time(1, init_hour);
+        let valid_time = time(2, 0);
+
+        let pnt = SeriesPoint {
+            valid_time,
+            init_time,
+            lead_hours: (valid_time - init_time).num_hours() as i32,
+            value,
+        };
+
+        (Model::GFS, pnt)
+    }
+
+    #[test]
+    fn test_dprog_dt_stats() {
+        let dprog = DprogDt {
+            valid_time: time(2, 0),
+            forecasts: vec![point(0, 1.0), point(6, 2.0), point(12, 3.0), point(18, 6.0)],
+        };
+
+        assert_eq!(dprog.mean(), Some(3.0));
+        assert_eq!(dprog.range(), Some(5.0));
+        assert!((dprog.spread().unwrap() - 3.5f64.sqrt()).abs() < 1.0e-9);
+        assert!((dprog.trend().unwrap() - 1.6 / 6.0).abs() < 1.0e-9);
+        assert_eq!(dprog.latest_change(), Some(3.0));
+
+        let single = DprogDt {
+            valid_time: time(2, 0),
+            forecasts: vec![point(0, 1.0)],
+        };
+        assert_eq!(single.spread(), Some(0.0));
+        assert_eq!(single.trend(), None);
+        assert_eq!(single.latest_change(), None);
+        assert_eq!(single.for_model(Model::NAM).mean(), None);
+    }
+
+    #[test]
+    fn test_dprog_dt() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+        let valid_time = time(2, 0);
+
+        let gfs = arch.dprog_dt(kmso, Model::GFS, "T2MS", valid_time).unwrap();
+        let inits: Vec<_> = gfs.forecasts.iter().map(|(_, pnt)| pnt.init_time).collect();
+        assert_eq!(inits, vec![time(1, 6), time(1, 12), time(1, 18)]);
+        assert!(gfs.trend().is_some());
+
+        let both = arch
+            .multi_model_dprog_dt(kmso, &[Model::GFS, Model::NAM], "T2MS", valid_time)
+            .unwrap();
+        assert_eq!(both.forecasts.len(), 6);
+        assert!(
+            both.forecasts
+                .windows(2)
+                .all(|w| w[0].1.init_time <= w[1].1.init_time)
+        );
+        assert_eq!(both.for_model(Model::GFS), gfs);
+        assert!(both.spread().unwrap() >= 0.0);
+
+        // Run to run changes are only for one model at a time.
+        assert_eq!(both.trend(), None);
+        assert_eq!(both.latest_change(), None);
+        assert_eq!(
+            both.for_model(Model::GFS).latest_change(),
+            gfs.latest_change()
+        );
+        assert!(both.for_model(Model::NAM).trend().is_some());
+    }
+}
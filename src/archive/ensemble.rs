@@ -0,0 +1,853 @@
+//! Groups of forecasts treated as the members of an ensemble.
+//!
+//! The forecasts of a member are either the regular files of a model, for multi-model ensembles
+//! like the GFS and NAM, or files added with `Archive::add_member_file` under a tag for the member,
+//! for ensembles like the SREF where every member has the same model.
+
+use crate::{
+    archive::{
+        Archive, ClimoParam, FileRecord, InternalSiteInfo, ParsedSounding, compare,
+        hourly_params::HourlyParams,
+    },
+    errors::BufkitDataErr,
+    models::Model,
+    site::StationNumber,
+};
+use chrono::NaiveDateTime;
+use metfor::{Celsius, HectoPascal, Knots, Meters, Quantity, WindSpdDir, WindUV};
+use rusqlite::{OptionalExtension, ToSql};
+use sounding_analysis::{DataRow, Sounding, StationInfo};
+use std::{collections::HashSet, io::Write, str::FromStr};
+
+/// The spacing of the levels in summary soundings.
+const SUMMARY_LEVEL_SPACING: f64 = 25.0;
+
+/// The top of summary soundings.
+const SUMMARY_TOP: f64 = 100.0;
+
+/// A member of an ensemble group.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EnsembleMember {
+    /// The name of the member.
+    pub name: String,
+    /// The model that produced the member's forecasts.
+    pub model: Model,
+    /// The tag of the member's files added with `Archive::add_member_file`, or `None` if the
+    /// member's forecasts are the regular files of `model`.
+    pub tag: Option<String>,
+    /// Whether this is the control member.
+    pub control: bool,
+}
+
+/// A named group of models treated as one ensemble.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EnsembleGroup {
+    /// The name of the group.
+    pub name: String,
+    /// The members of the group.
+    pub members: Vec<EnsembleMember>,
+}
+
+impl EnsembleGroup {
+    /// Create a group without any members.
+    pub fn new(name: &str) -> Self {
+        EnsembleGroup {
+            name: name.to_owned(),
+            members: vec![],
+        }
+    }
+
+    /// Builder method to add a member whose forecasts are the regular files of a model.
+    pub fn member(self, name: &str, model: Model) -> Self {
+        self.push_member(name, model, None, false)
+    }
+
+    /// Builder method to add the control member, whose forecasts are the regular files of a model.
+    pub fn control(self, name: &str, model: Model) -> Self {
+        self.push_member(name, model, None, true)
+    }
+
+    /// Builder method to add a member whose forecasts are the files added with
+    /// `Archive::add_member_file` under `tag`.
+    pub fn tagged_member(self, name: &str, model: Model, tag: &str) -> Self {
+        self.push_member(name, model, Some(tag), false)
+    }
+
+    /// Builder method to add the control member, whose forecasts are the files added with
+    /// `Archive::add_member_file` under `tag`.
+    pub fn tagged_control(self, name: &str, model: Model, tag: &str) -> Self {
+        self.push_member(name, model, Some(tag), true)
+    }
+
+    fn push_member(mut self, name: &str, model: Model, tag: Option<&str>, control: bool) -> Self {
+        self.members.push(EnsembleMember {
+            name: name.to_owned(),
+            model,
+            tag: tag.map(str::to_owned),
+            control,
+        });
+        self
+    }
+
+    /// Get the control member, if the group has one.
+    pub fn control_member(&self) -> Option<&EnsembleMember> {
+        self.members.iter().find(|mbr| mbr.control)
+    }
+}
+
+/// The members of one run of an ensemble lined up on the valid times they all have.
+#[derive(Clone, Debug)]
+pub struct EnsembleRun<T> {
+    /// The members in the archive for the run and the files they are in.
+    pub members: Vec<(EnsembleMember, FileRecord)>,
+    /// The valid times with one value per member, in the same order as `members`.
+    pub times: Vec<(NaiveDateTime, Vec<T>)>,
+}
+
+/// Summary statistics of the member values at one valid time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnsembleStats {
+    /// The mean of the members.
+    pub mean: f64,
+    /// The standard deviation of the members.
+    pub spread: f64,
+    /// The member values, sorted.
+    pub values: Vec<f64>,
+}
+
+impl EnsembleStats {
+    /// Compute the statistics of some member values, if there are any.
+    pub fn from_values(values: &[f64]) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+
+        let mut values = values.to_vec();
+        values.sort_by(f64::total_cmp);
+
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let spread = (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n).sqrt();
+
+        Some(EnsembleStats {
+            mean,
+            spread,
+            values,
+        })
+    }
+
+    /// Get the value at a percentile, 0 - 100, interpolating between members.
+    pub fn percentile(&self, percentile: f64) -> f64 {
+        percentile_of_sorted(&self.values, percentile)
+    }
+}
+
+/// A statistic used to combine the member soundings into one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EnsembleStat {
+    /// The mean of the members.
+    Mean,
+    /// A percentile, 0 - 100, of the members.
+    Percentile(f64),
+}
+
+impl EnsembleStat {
+    fn combine(self, mut values: Vec<f64>) -> Option<f64> {
+        if values.is_empty() {
+            return None;
+        }
+
+        match self {
+            EnsembleStat::Mean => Some(values.iter().sum::<f64>() / values.len() as f64),
+            EnsembleStat::Percentile(pct) => {
+                values.sort_by(f64::total_cmp);
+                Some(percentile_of_sorted(&values, pct))
+            }
+        }
+    }
+}
+
+impl EnsembleRun<f64> {
+    /// Get the statistics of the members at each valid time.
+    pub fn stats(&self) -> Vec<(NaiveDateTime, EnsembleStats)> {
+        self.times
+            .iter()
+            .filter_map(|(vt, vals)| EnsembleStats::from_values(vals).map(|st| (*vt, st)))
+            .collect()
+    }
+}
+
+impl EnsembleRun<ParsedSounding> {
+    /// Get the statistics of a parameter computed from the member soundings at each valid time.
+    /// Members the parameter cannot be computed for are left out.
+    pub fn param_stats(&self, param: ClimoParam) -> Vec<(NaiveDateTime, EnsembleStats)> {
+        self.times
+            .iter()
+            .filter_map(|(vt, snds)| {
+                let vals: Vec<f64> = snds
+                    .iter()
+                    .filter_map(|(snd, _)| param.value(snd))
+                    .collect();
+                EnsembleStats::from_values(&vals).map(|st| (*vt, st))
+            })
+            .collect()
+    }
+
+    /// Combine the member soundings at each valid time into one sounding.
+    ///
+    /// The members are interpolated to levels every 25 hPa from the highest surface up to 100
+    /// hPa and combined level by level. The wind components are combined separately.
+    pub fn summary_soundings(&self, stat: EnsembleStat) -> Vec<(NaiveDateTime, Sounding)> {
+        self.times
+            .iter()
+            .map(|(vt, snds)| (*vt, summary_sounding(*vt, snds, stat)))
+            .collect()
+    }
+}
+
+impl Archive {
+    /// Add an ensemble group to the archive, replacing any group with the same name.
+    ///
+    /// Every member must have a unique name and a unique model and tag, and there can be at most
+    /// one control. Members without a tag use the regular files of their model, so only one of
+    /// them can have each model.
+    pub fn add_ensemble_group(&self, group: &EnsembleGroup) -> Result<(), BufkitDataErr> {
+        let mut names = HashSet::new();
+        let mut sources = HashSet::new();
+        for mbr in &group.members {
+            if !names.insert(&mbr.name) || !sources.insert((mbr.model, &mbr.tag)) {
+                return Err(BufkitDataErr::GeneralError(format!(
+                    "duplicate member {} ({}) in ensemble {}",
+                    mbr.name, mbr.model, group.name
+                )));
+            }
+        }
+
+        if group.members.is_empty() || group.members.iter().filter(|m| m.control).count() > 1 {
+            return Err(BufkitDataErr::GeneralError(format!(
+                "ensemble {} needs at least one member and at most one control",
+                group.name
+            )));
+        }
+
+        let tx = self.db_conn.unchecked_transaction()?;
+        self.remove_ensemble_group(&group.name)?;
+
+        let mut stmt = self.db_conn.prepare(
+            "
+                INSERT INTO ensemble_members (ensemble, member, model, is_control, tag)
+                VALUES (?1, ?2, ?3, ?4, ?5)
+            ",
+        )?;
+        for mbr in &group.members {
+            stmt.execute([
+                &group.name as &dyn ToSql,
+                &mbr.name,
+                &mbr.model.as_static_str(),
+                &mbr.control,
+                &mbr.tag,
+            ])?;
+        }
+
+        drop(stmt);
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Remove an ensemble group, the files of its members are not affected.
+    pub fn remove_ensemble_group(&self, name: &str) -> Result<(), BufkitDataErr> {
+        self.db_conn
+            .execute("DELETE FROM ensemble_members WHERE ensemble = ?1", [name])?;
+
+        Ok(())
+    }
+
+    /// Get an ensemble group by name.
+    pub fn ensemble_group(&self, name: &str) -> Result<EnsembleGroup, BufkitDataErr> {
+        self.ensemble_groups()?
+            .into_iter()
+            .find(|group| group.name == name)
+            .ok_or(BufkitDataErr::NotInIndex)
+    }
+
+    /// Get all the ensemble groups, sorted by name.
+    pub fn ensemble_groups(&self) -> Result<Vec<EnsembleGroup>, BufkitDataErr> {
+        let mut stmt = self.db_conn.prepare(
+            "
+                SELECT ensemble, member, model, is_control, tag
+                FROM ensemble_members
+                ORDER BY ensemble, is_control DESC, member
+            ",
+        )?;
+
+        let mut groups: Vec<EnsembleGroup> = vec![];
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let name: String = row.get(0)?;
+            let member = EnsembleMember {
+                name: row.get(1)?,
+                model: Model::from_str(&row.get::<_, String>(2)?)?,
+                tag: row.get(4)?,
+                control: row.get(3)?,
+            };
+
+            match groups.last_mut() {
+                Some(group) if group.name == name => group.members.push(member),
+                _ => groups.push(EnsembleGroup {
+                    name,
+                    members: vec![member],
+                }),
+            }
+        }
+
+        Ok(groups)
+    }
+
+    /// Get the files for the members of an ensemble run. Members without a file for the run are
+    /// left out, and if none of them have one this returns `NotInIndex`.
+    pub fn ensemble_members(
+        &self,
+        name: &str,
+        station_num: StationNumber,
+        init_time: NaiveDateTime,
+    ) -> Result<Vec<(EnsembleMember, FileRecord)>, BufkitDataErr> {
+        let mut members = vec![];
+        for mbr in self.ensemble_group(name)?.members {
+            let record = match &mbr.tag {
+                Some(tag) => self.member_file_record(tag, station_num, mbr.model, init_time),
+                None => self.file_record(station_num, mbr.model, init_time),
+            };
+
+            match record {
+                Ok(record) => members.push((mbr, record)),
+                Err(BufkitDataErr::NotInIndex) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+
+        if members.is_empty() {
+            return Err(BufkitDataErr::NotInIndex);
+        }
+
+        Ok(members)
+    }
+
+    /// Get the member soundings of an ensemble run at the valid times all the members have.
+    pub fn ensemble_soundings(
+        &self,
+        name: &str,
+        station_num: StationNumber,
+        init_time: NaiveDateTime,
+    ) -> Result<EnsembleRun<ParsedSounding>, BufkitDataErr> {
+        let members = self.ensemble_members(name, station_num, init_time)?;
+
+        let mut series = Vec::with_capacity(members.len());
+        for (_, record) in &members {
+            let snds: Vec<_> = self
+                .record_soundings(record)?
+                .into_iter()
+                .filter_map(|parsed| parsed.0.valid_time().map(|vt| (vt, parsed)))
+                .collect();
+
+            series.push(snds);
+        }
+
+        Ok(EnsembleRun {
+            members,
+            times: compare::shared_times(series),
+        })
+    }
+
+    /// Get the member values of a surface or station parameter for an ensemble run at the valid
+    /// times all the members have it. See `param_time_series` for the parameter names.
+    pub fn ensemble_params(
+        &self,
+        name: &str,
+        station_num: StationNumber,
+        init_time: NaiveDateTime,
+        param: &str,
+    ) -> Result<EnsembleRun<f64>, BufkitDataErr> {
+        let param = param.to_uppercase();
+        let members = self.ensemble_members(name, station_num, init_time)?;
+
+        let mut series = Vec::with_capacity(members.len());
+        for (_, record) in &members {
+            let text = self.load_file(&record.file_name)?;
+            let vals: Vec<_> = HourlyParams::parse(&text)?
+                .hours
+                .into_iter()
+                .filter_map(|(vt, vals)| vals.get(&param).map(|&val| (vt, val)))
+                .collect();
+
+            series.push(vals);
+        }
+
+        Ok(EnsembleRun {
+            members,
+            times: compare::shared_times(series),
+        })
+    }
+}
+
+impl Archive {
+    /// Add a file for an ensemble member that has the same model as other members, e.g. one of
+    /// the SREF members. The tag names the member's files, e.g. `arw_ctl`, and may only use
+    /// letters, digits, `_`, and `-`. A file with the same tag, model, station, and
+    /// initialization time is replaced.
+    ///
+    /// Member files are kept apart from the regular files of the model, so they are not found by
+    /// `retrieve`, `inventory`, or a `FileQuery`. Use a group with `EnsembleGroup::tagged_member`
+    /// to get them.
+    pub fn add_member_file(
+        &self,
+        tag: &str,
+        site_id_hint: &str,
+        model: Model,
+        text_data: &str,
+    ) -> Result<FileRecord, BufkitDataErr> {
+        if tag.is_empty()
+            || !tag
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(BufkitDataErr::GeneralError(format!(
+                "invalid member tag: {:?}",
+                tag
+            )));
+        }
+
+        let InternalSiteInfo {
+            station_num,
+            id,
+            init_time,
+            end_time,
+            coords,
+            elevation,
+        } = Self::parse_site_info(text_data)?;
+        let station_num = self.resolve_station_num(station_num)?;
+
+        if self.site(station_num).is_none() {
+            self.add_site(&Self::new_site_at(station_num, coords))?;
+        }
+        self.fill_site_from_file(station_num, id.as_deref(), coords, elevation)?;
+
+        let id = id.unwrap_or_else(|| site_id_hint.to_uppercase());
+        let record = FileRecord {
+            station_num,
+            model,
+            init_time,
+            end_time,
+            file_name: format!(
+                "{}_{}_{}_{}.buf.gz",
+                init_time.format("%Y%m%d%HZ"),
+                model.as_static_str(),
+                id,
+                tag
+            ),
+            id: Some(id),
+            coords,
+            elevation,
+        };
+
+        // The id in the file may have changed since the file being replaced was added.
+        if let Ok(old) = self.member_file_record(tag, station_num, model, init_time)
+            && old.file_name != record.file_name
+        {
+            self.remove_member_file(tag, station_num, model, init_time)?;
+        }
+
+        let file = std::fs::File::create(self.data_root().join(&record.file_name))?;
+        let mut encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        encoder.write_all(text_data.as_bytes())?;
+        encoder.finish()?;
+
+        self.db_conn.execute(
+            "
+                INSERT OR REPLACE INTO member_files (
+                    tag, station_num, model, init_time, end_time, file_name, id, lat, lon,
+                    elevation_m)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+            ",
+            [
+                &tag as &dyn ToSql,
+                &Into::<u32>::into(station_num),
+                &model.as_static_str(),
+                &init_time,
+                &end_time,
+                &record.file_name,
+                &record.id,
+                &coords.lat,
+                &coords.lon,
+                &elevation.unpack(),
+            ],
+        )?;
+        self.sounding_cache().remove(&record.file_name);
+
+        Ok(record)
+    }
+
+    /// Get the metadata for a file added with `add_member_file`.
+    pub fn member_file_record(
+        &self,
+        tag: &str,
+        station_num: StationNumber,
+        model: Model,
+        init_time: NaiveDateTime,
+    ) -> Result<FileRecord, BufkitDataErr> {
+        let station_num: u32 = self.resolve_station_num(station_num)?.into();
+
+        self.db_conn
+            .query_row(
+                "
+                    SELECT station_num, model, init_time, end_time, id, lat, lon, elevation_m,
+                        file_name
+                    FROM member_files
+                    WHERE tag = ?1 AND station_num = ?2 AND model = ?3 AND init_time = ?4
+                ",
+                [
+                    &tag as &dyn ToSql,
+                    &station_num,
+                    &model.as_static_str(),
+                    &init_time,
+                ],
+                Self::parse_row_to_file_record,
+            )
+            .optional()?
+            .ok_or(BufkitDataErr::NotInIndex)
+    }
+
+    /// Remove a file added with `add_member_file`.
+    pub fn remove_member_file(
+        &self,
+        tag: &str,
+        station_num: StationNumber,
+        model: Model,
+        init_time: NaiveDateTime,
+    ) -> Result<(), BufkitDataErr> {
+        let record = self.member_file_record(tag, station_num, model, init_time)?;
+
+        std::fs::remove_file(self.data_root().join(&record.file_name))?;
+        self.sounding_cache().remove(&record.file_name);
+        self.db_conn.execute(
+            "DELETE FROM member_files WHERE file_name = ?1",
+            [&record.file_name],
+        )?;
+
+        Ok(())
+    }
+}
+
+/// Interpolate in a sorted slice to get the value at a percentile, 0 - 100.
+fn percentile_of_sorted(values: &[f64], percentile: f64) -> f64 {
+    let idx = percentile.clamp(0.0, 100.0) / 100.0 * (values.len() - 1) as f64;
+    let (below, above) = (idx.floor() as usize, idx.ceil() as usize);
+
+    values[below] + (idx - idx.floor()) * (values[above] - values[below])
+}
+
+fn summary_sounding(
+    valid_time: NaiveDateTime,
+    snds: &[ParsedSounding],
+    stat: EnsembleStat,
+) -> Sounding {
+    let combine = |f: &dyn Fn(&Sounding) -> Option<f64>| {
+        stat.combine(snds.iter().filter_map(|(snd, _)| f(snd)).collect())
+    };
+    let combine_rows = |rows: &[DataRow], f: &dyn Fn(&DataRow) -> Option<f64>| {
+        stat.combine(rows.iter().filter_map(f).collect())
+    };
+    let wind_uv = |wind: Option<WindSpdDir<Knots>>| wind.map(WindUV::<Knots>::from);
+    let combine_wind = |u: Option<f64>, v: Option<f64>| match (u, v) {
+        (Some(u), Some(v)) => Some(WindSpdDir::<Knots>::from(WindUV {
+            u: Knots(u),
+            v: Knots(v),
+        })),
+        _ => None,
+    };
+
+    // Start at the highest surface so every level is above ground in all the members.
+    let lowest_pressure_sfc = snds
+        .iter()
+        .filter_map(|(snd, _)| snd.station_pressure().into_option().map(|p| p.unpack()))
+        .reduce(f64::min);
+    let mut levels = vec![];
+    if let Some(sfc) = lowest_pressure_sfc {
+        let mut p = (sfc / SUMMARY_LEVEL_SPACING).floor() * SUMMARY_LEVEL_SPACING;
+        if p >= sfc {
+            p -= SUMMARY_LEVEL_SPACING;
+        }
+        while p >= SUMMARY_TOP {
+            levels.push(p);
+            p -= SUMMARY_LEVEL_SPACING;
+        }
+    }
+
+    let mut pressure = vec![];
+    let (mut height, mut temperature, mut dew_point, mut wind) = (vec![], vec![], vec![], vec![]);
+    for p in levels {
+        let rows: Vec<DataRow> = snds
+            .iter()
+            .filter_map(|(snd, _)| {
+                sounding_analysis::linear_interpolate_sounding(snd, HectoPascal(p)).ok()
+            })
+            .collect();
+
+        pressure.push(Some(HectoPascal(p)).into());
+        height.push(
+            combine_rows(&rows, &|r| r.height.into_option().map(|h| h.unpack()))
+                .map(Meters)
+                .into(),
+        );
+        temperature.push(
+            combine_rows(&rows, &|r| r.temperature.into_option().map(|t| t.unpack()))
+                .map(Celsius)
+                .into(),
+        );
+        dew_point.push(
+            combine_rows(&rows, &|r| r.dew_point.into_option().map(|t| t.unpack()))
+                .map(Celsius)
+                .into(),
+        );
+
+        let u = combine_rows(&rows, &|r| {
+            wind_uv(r.wind.into_option()).map(|w| w.u.unpack())
+        });
+        let v = combine_rows(&rows, &|r| {
+            wind_uv(r.wind.into_option()).map(|w| w.v.unpack())
+        });
+        wind.push(combine_wind(u, v).into());
+    }
+
+    let elevation = combine(&|snd| {
+        snd.station_info()
+            .elevation()
+            .into_option()
+            .map(|e| e.unpack())
+    });
+    let sfc_u = combine(&|snd| wind_uv(snd.sfc_wind().into_option()).map(|w| w.u.unpack()));
+    let sfc_v = combine(&|snd| wind_uv(snd.sfc_wind().into_option()).map(|w| w.v.unpack()));
+
+    // The profile setters put the surface values set here at the bottom of the profiles.
+    Sounding::new()
+        .with_station_info(StationInfo::new().with_elevation(elevation.map(Meters)))
+        .with_valid_time(valid_time)
+        .with_station_pressure(
+            combine(&|snd| snd.station_pressure().into_option().map(|p| p.unpack()))
+                .map(HectoPascal),
+        )
+        .with_sfc_temperature(
+            combine(&|snd| snd.sfc_temperature().into_option().map(|t| t.unpack())).map(Celsius),
+        )
+        .with_sfc_dew_point(
+            combine(&|snd| snd.sfc_dew_point().into_option().map(|t| t.unpack())).map(Celsius),
+        )
+        .with_sfc_wind(combine_wind(sfc_u, sfc_v))
+        .with_pressure_profile(pressure)
+        .with_height_profile(height)
+        .with_temperature_profile(temperature)
+        .with_dew_point_profile(dew_point)
+        .with_wind_profile(wind)
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                          Unit Tests
+--------------------------------------------------------------------------------------------------*/
+#[cfg(test)]
+mod unit {
+    use super::*;
+    use crate::archive::unit::*; // test helpers.
+
+    use chrono::NaiveDate;
+
+    fn time(day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2017, 4, day)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+
+    fn test_group() -> EnsembleGroup {
+        EnsembleGroup::new("multi")
+            .control("gfs", Model::GFS)
+            .member("nam", Model::NAM)
+            .member("nam4km", Model::NAM4KM)
+    }
+
+    #[test]
+    fn test_ensemble_stats() {
+        let stats = EnsembleStats::from_values(&[3.0, 1.0, 2.0, 6.0]).unwrap();
+        assert_eq!(stats.values, vec![1.0, 2.0, 3.0, 6.0]);
+        assert_eq!(stats.mean, 3.0);
+        assert!((stats.spread - 3.5f64.sqrt()).abs() < 1.0e-9);
+        assert_eq!(stats.percentile(0.0), 1.0);
+        assert_eq!(stats.percentile(50.0), 2.5);
+        assert_eq!(stats.percentile(100.0), 6.0);
+
+        assert!(EnsembleStats::from_values(&[]).is_none());
+        assert_eq!(
+            EnsembleStat::Percentile(50.0).combine(vec![4.0, 1.0, 2.0]),
+            Some(2.0)
+        );
+        assert_eq!(EnsembleStat::Mean.combine(vec![]), None);
+    }
+
+    #[test]
+    fn test_ensemble_groups() {
+        let TestArchive { tmp: _tmp, arch } =
+            create_test_archive().expect("Failed to create test archive.");
+
+        let group = test_group();
+        arch.add_ensemble_group(&group).unwrap();
+        arch.add_ensemble_group(&EnsembleGroup::new("other").member("gfs", Model::GFS))
+            .unwrap();
+
+        let stored = arch.ensemble_group("multi").unwrap();
+        assert_eq!(stored.control_member().unwrap().name, "gfs");
+        assert_eq!(stored.members.len(), 3);
+        assert_eq!(arch.ensemble_groups().unwrap().len(), 2);
+
+        // Adding again replaces the group.
+        arch.add_ensemble_group(&EnsembleGroup::new("multi").member("nam", Model::NAM))
+            .unwrap();
+        assert_eq!(arch.ensemble_group("multi").unwrap().members.len(), 1);
+
+        arch.remove_ensemble_group("multi").unwrap();
+        assert!(matches!(
+            arch.ensemble_group("multi"),
+            Err(BufkitDataErr::NotInIndex)
+        ));
+
+        // Invalid groups.
+        assert!(
+            arch.add_ensemble_group(&EnsembleGroup::new("empty"))
+                .is_err()
+        );
+        let dup = EnsembleGroup::new("dup")
+            .member("a", Model::GFS)
+            .member("b", Model::GFS);
+        assert!(arch.add_ensemble_group(&dup).is_err());
+        let two_controls = EnsembleGroup::new("two")
+            .control("a", Model::GFS)
+            .control("b", Model::NAM);
+        assert!(arch.add_ensemble_group(&two_controls).is_err());
+        let dup_tags = EnsembleGroup::new("dup_tags")
+            .tagged_member("a", Model::SREF, "arw_p1")
+            .tagged_member("b", Model::SREF, "arw_p1");
+        assert!(arch.add_ensemble_group(&dup_tags).is_err());
+    }
+
+    #[test]
+    fn test_tagged_members() {
+        let TestArchive { tmp: _tmp, arch } =
+            create_test_archive().expect("Failed to create test archive.");
+
+        let (site_id, _, text) = get_test_data()
+            .into_iter()
+            .find(|(_, model, _)| *model == Model::GFS)
+            .unwrap();
+
+        // More members than there are models, all with the same model.
+        let tags = ["arw_ctl", "arw_n1", "arw_p1", "nmb_ctl", "nmb_n1"];
+        let mut group = EnsembleGroup::new("sref").tagged_control("arw_ctl", Model::SREF, tags[0]);
+        for tag in &tags[1..] {
+            group = group.tagged_member(tag, Model::SREF, tag);
+        }
+        arch.add_ensemble_group(&group).unwrap();
+        assert_eq!(arch.ensemble_group("sref").unwrap(), group);
+
+        let mut init_time = None;
+        for tag in tags {
+            let record = arch
+                .add_member_file(tag, &site_id, Model::SREF, &text)
+                .unwrap();
+            assert!(record.file_name.ends_with(&format!("_{}.buf.gz", tag)));
+            init_time = Some(record.init_time);
+        }
+        let init_time = init_time.unwrap();
+        assert!(
+            arch.add_member_file("bad tag", &site_id, Model::SREF, &text)
+                .is_err()
+        );
+
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+
+        // Member files are not regular files.
+        assert!(arch.inventory(kmso, Model::SREF).unwrap().is_empty());
+
+        let members = arch.ensemble_members("sref", kmso, init_time).unwrap();
+        assert_eq!(members.len(), tags.len());
+        let names: HashSet<_> = members.iter().map(|(_, rec)| &rec.file_name).collect();
+        assert_eq!(names.len(), tags.len());
+
+        // Every member has the same data.
+        let params = arch
+            .ensemble_params("sref", kmso, init_time, "T2MS")
+            .unwrap();
+        for (_, stats) in params.stats() {
+            assert_eq!(stats.values.len(), tags.len());
+            assert!(stats.spread < 1.0e-9);
+        }
+
+        arch.remove_member_file("nmb_n1", kmso, Model::SREF, init_time)
+            .unwrap();
+        let members = arch.ensemble_members("sref", kmso, init_time).unwrap();
+        assert_eq!(members.len(), tags.len() - 1);
+
+        arch.remove_site(kmso).unwrap();
+        assert!(matches!(
+            arch.member_file_record("arw_ctl", kmso, Model::SREF, init_time),
+            Err(BufkitDataErr::NotInIndex)
+        ));
+    }
+
+    #[test]
+    fn test_ensemble_run() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+        arch.add_ensemble_group(&test_group()).unwrap();
+
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+        let init_time = time(1, 12);
+
+        // There are no NAM4KM files, so that member is left out.
+        let members = arch.ensemble_members("multi", kmso, init_time).unwrap();
+        let names: Vec<_> = members.iter().map(|(mbr, _)| mbr.name.as_str()).collect();
+        assert_eq!(names, vec!["gfs", "nam"]);
+        assert!(matches!(
+            arch.ensemble_members("multi", kmso, time(3, 0)),
+            Err(BufkitDataErr::NotInIndex)
+        ));
+
+        let params = arch
+            .ensemble_params("multi", kmso, init_time, "t2ms")
+            .unwrap();
+        assert!(!params.times.is_empty());
+        let stats = params.stats();
+        assert_eq!(stats.len(), params.times.len());
+        assert!(stats.iter().all(|(_, st)| st.values.len() == 2));
+
+        let snds = arch.ensemble_soundings("multi", kmso, init_time).unwrap();
+        assert!(!snds.times.is_empty());
+        let pwat = snds.param_stats(ClimoParam::Pwat);
+        assert_eq!(pwat.len(), snds.times.len());
+
+        let mean = snds.summary_soundings(EnsembleStat::Mean);
+        let median = snds.summary_soundings(EnsembleStat::Percentile(50.0));
+        assert_eq!(mean.len(), snds.times.len());
+        for ((vt, mean), (_, median)) in mean.iter().zip(&median) {
+            assert_eq!(mean.valid_time(), Some(*vt));
+            // With two members the median is the mean.
+            let (pw_mean, pw_median) = (
+                ClimoParam::Pwat.value(mean).unwrap(),
+                ClimoParam::Pwat.value(median).unwrap(),
+            );
+            assert!((pw_mean - pw_median).abs() < 1.0e-6);
+            assert!(ClimoParam::T500.value(mean).is_some());
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! Lazily load files from the archive.
+
+use crate::{
+    archive::{Archive, FileRecord},
+    errors::BufkitDataErr,
+    models::Model,
+    site::StationNumber,
+};
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+    sync::mpsc::{Receiver, sync_channel},
+};
+
+/// An iterator over files in the archive that loads each one as it is needed.
+///
+/// Only the metadata for the files is kept in memory, the contents are read and decompressed one
+/// file at a time. Use `prefetch` to load upcoming files on a background thread while the current
+/// one is being processed. Errors loading a file are returned for that file and the iterator moves
+/// on to the next one.
+#[derive(Debug)]
+pub struct FileIter {
+    data_root: PathBuf,
+    records: std::vec::IntoIter<FileRecord>,
+    prefetch: usize,
+    reader: Option<Receiver<Result<String, BufkitDataErr>>>,
+}
+
+impl FileIter {
+    pub(crate) fn new(arch: &Archive, records: Vec<FileRecord>) -> Self {
+        FileIter {
+            data_root: arch.data_root(),
+            records: records.into_iter(),
+            prefetch: 0,
+            reader: None,
+        }
+    }
+
+    /// Load up to `n` files ahead on a background thread, so at most `n` files are held in memory
+    /// waiting to be used. The default, zero, loads each file when `next` is called.
+    pub fn prefetch(mut self, n: usize) -> Self {
+        self.prefetch = n;
+        self
+    }
+
+    /// The metadata of the files that have not been returned yet.
+    pub fn remaining(&self) -> impl Iterator<Item = &FileRecord> {
+        self.records.as_slice().iter()
+    }
+
+    /// Start the thread that reads the remaining files in order, if prefetching and it has not
+    /// been started yet. The thread stops when the iterator is dropped.
+    fn start_prefetch(&mut self) {
+        if self.prefetch == 0 || self.reader.is_some() {
+            return;
+        }
+
+        let paths: Vec<PathBuf> = self
+            .remaining()
+            .map(|record| self.data_root.join(&record.file_name))
+            .collect();
+
+        // The reader holds one more file while it waits to send it.
+        let (tx, rx) = sync_channel(self.prefetch - 1);
+        std::thread::spawn(move || {
+            for path in paths {
+                if tx.send(read_to_string(&path)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        self.reader = Some(rx);
+    }
+}
+
+impl Iterator for FileIter {
+    type Item = Result<(FileRecord, String), BufkitDataErr>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.start_prefetch();
+
+        let record = self.records.next()?;
+        let text = match &self.reader {
+            Some(rx) => rx
+                .recv()
+                .unwrap_or(Err(BufkitDataErr::LogicError("prefetch thread panicked"))),
+            None => read_to_string(&self.data_root.join(&record.file_name)),
+        };
+
+        Some(text.map(|text| (record, text)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.records.len();
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for FileIter {}
+
+impl Archive {
+    /// Open a file in the archive for streaming, decompressing it as it is read.
+    ///
+    /// Use this instead of loading the whole file into a `String` for very large files.
+    pub fn open_file(&self, record: &FileRecord) -> Result<impl Read + use<>, BufkitDataErr> {
+        open(&self.data_root().join(&record.file_name))
+    }
+
+    /// Open a file in the archive for streaming along with its metadata.
+    pub fn retrieve_reader(
+        &self,
+        station_num: StationNumber,
+        model: Model,
+        init_time: chrono::NaiveDateTime,
+    ) -> Result<(FileRecord, impl Read + use<>), BufkitDataErr> {
+        let record = self.file_record(station_num, model, init_time)?;
+        let reader = self.open_file(&record)?;
+
+        Ok((record, reader))
+    }
+}
+
+fn open(path: &Path) -> Result<impl Read + use<>, BufkitDataErr> {
+    let file = std::fs::File::open(path)?;
+    Ok(flate2::read::GzDecoder::new(std::io::BufReader::new(file)))
+}
+
+/// Read and decompress a whole file.
+pub(crate) fn read_to_string(path: &Path) -> Result<String, BufkitDataErr> {
+    let mut s = String::new();
+    open(path)?.read_to_string(&mut s)?;
+    Ok(s)
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                          Unit Tests
+--------------------------------------------------------------------------------------------------*/
+#[cfg(test)]
+mod unit {
+    use super::*;
+    use crate::archive::{FileQuery, unit::*}; // test helpers.
+
+    #[test]
+    fn test_file_iter() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+
+        let query = FileQuery::new().model(Model::GFS);
+        let expected: Vec<(FileRecord, String)> = arch
+            .file_records(&query)
+            .unwrap()
+            .into_iter()
+            .map(|rec| {
+                let text = arch
+                    .retrieve(rec.station_num, rec.model, rec.init_time)
+                    .unwrap();
+                (rec, text)
+            })
+            .collect();
+        assert_eq!(expected.len(), 3);
+
+        let iter = arch.retrieve_files(&query).unwrap();
+        assert_eq!(iter.len(), 3);
+        let found: Vec<_> = iter.map(Result::unwrap).collect();
+        assert_eq!(found, expected);
+
+        for n in 1..5 {
+            let mut iter = arch.retrieve_files(&query).unwrap().prefetch(n);
+            assert_eq!(iter.remaining().count(), 3);
+            assert_eq!(iter.next().unwrap().unwrap(), expected[0]);
+            assert_eq!(iter.remaining().count(), 2);
+            let rest: Vec<_> = iter.map(Result::unwrap).collect();
+            assert_eq!(rest, expected[1..]);
+        }
+    }
+
+    #[test]
+    fn test_file_iter_reports_errors() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+
+        let query = FileQuery::new().model(Model::GFS);
+        let records = arch.file_records(&query).unwrap();
+        std::fs::remove_file(arch.data_root().join(&records[1].file_name)).unwrap();
+
+        for n in [0, 2] {
+            let results: Vec<_> = arch.retrieve_files(&query).unwrap().prefetch(n).collect();
+            assert_eq!(results.len(), 3);
+            assert!(results[0].is_ok());
+            assert!(matches!(results[1], Err(BufkitDataErr::IO(_))));
+            assert!(results[2].is_ok());
+        }
+    }
+
+    #[test]
+    fn test_open_file() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+        let init_time = arch.inventory(kmso, Model::NAM).unwrap()[0];
+
+        let (record, mut reader) = arch.retrieve_reader(kmso, Model::NAM, init_time).unwrap();
+        assert_eq!(record.init_time, init_time);
+
+        let mut text = String::new();
+        reader.read_to_string(&mut text).unwrap();
+        assert_eq!(text, arch.retrieve(kmso, Model::NAM, init_time).unwrap());
+    }
+}
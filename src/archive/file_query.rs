@@ -0,0 +1,435 @@
+//! Build queries for files in the index.
+
+use crate::{
+    archive::{Archive, FileIter, FileRecord},
+    errors::BufkitDataErr,
+    models::Model,
+    region::Region,
+    site::{StateProv, StationNumber},
+};
+use chrono::NaiveDateTime;
+use rusqlite::ToSql;
+
+/// The order to return files from a query.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FileOrder {
+    /// The oldest initialization time first.
+    #[default]
+    OldestFirst,
+    /// The most recent initialization time first.
+    NewestFirst,
+}
+
+/// A query for files in the archive.
+///
+/// Start with `FileQuery::new()`, which matches every file, and add filters. Filters that are
+/// never set match all files, and setting a list filter like `station` or `model` more than once
+/// adds to the list of values it accepts. The time ranges and the region hold a single value, so
+/// setting them again replaces the old one. Each `param_range` is another condition files must
+/// meet. Run the query with `Archive::file_records`, `Archive::count_files`, or
+/// `Archive::retrieve_files`.
+#[derive(Clone, Debug, Default)]
+pub struct FileQuery {
+    stations: Vec<StationNumber>,
+    ids: Vec<String>,
+    models: Vec<Model>,
+    init_times: Option<(NaiveDateTime, NaiveDateTime)>,
+    valid_times: Option<(NaiveDateTime, NaiveDateTime)>,
+    cycle_hours: Vec<u32>,
+    states: Vec<StateProv>,
+    region: Option<Region>,
+    param_ranges: Vec<(String, f64, f64)>,
+    order: FileOrder,
+    limit: Option<usize>,
+}
+
+impl FileQuery {
+    /// Create a query that matches all the files in the archive.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only match files for this station.
+    pub fn station(mut self, station_num: StationNumber) -> Self {
+        self.stations.push(station_num);
+        self
+    }
+
+    /// Only match files for these stations.
+    pub fn stations(mut self, station_nums: impl IntoIterator<Item = StationNumber>) -> Self {
+        self.stations.extend(station_nums);
+        self
+    }
+
+    /// Only match files that used this station id, ignoring case.
+    pub fn id(mut self, id: &str) -> Self {
+        self.ids.push(id.to_uppercase());
+        self
+    }
+
+    /// Only match files that used these station ids, ignoring case.
+    pub fn ids<S: AsRef<str>>(mut self, ids: impl IntoIterator<Item = S>) -> Self {
+        self.ids
+            .extend(ids.into_iter().map(|id| id.as_ref().to_uppercase()));
+        self
+    }
+
+    /// Only match files from this model.
+    pub fn model(mut self, model: Model) -> Self {
+        self.models.push(model);
+        self
+    }
+
+    /// Only match files from these models.
+    pub fn models(mut self, models: impl IntoIterator<Item = Model>) -> Self {
+        self.models.extend(models);
+        self
+    }
+
+    /// Only match files initialized between `start` and `end`, inclusive.
+    ///
+    /// This replaces any range set earlier.
+    pub fn init_times(mut self, start: NaiveDateTime, end: NaiveDateTime) -> Self {
+        self.init_times = Some((start, end));
+        self
+    }
+
+    /// Only match files with any data valid between `start` and `end`, inclusive.
+    ///
+    /// This replaces any range set earlier.
+    pub fn valid_times(mut self, start: NaiveDateTime, end: NaiveDateTime) -> Self {
+        self.valid_times = Some((start, end));
+        self
+    }
+
+    /// Only match files initialized at these hours of the day (UTC), e.g. 0 and 12.
+    pub fn cycle_hours(mut self, hours: impl IntoIterator<Item = u32>) -> Self {
+        self.cycle_hours.extend(hours);
+        self
+    }
+
+    /// Only match files for sites in these states or providences.
+    pub fn states(mut self, states: impl IntoIterator<Item = StateProv>) -> Self {
+        self.states.extend(states);
+        self
+    }
+
+    /// Only match files located inside a region.
+    ///
+    /// This replaces any region set earlier.
+    pub fn region(mut self, region: Region) -> Self {
+        self.region = Some(region);
+        self
+    }
+
+    /// Only match files with a derived parameter between `min` and `max`, inclusive, at any
+    /// forecast hour. Files without the parameter in the index never match, see
+    /// `Archive::index_derived_params`.
+    pub fn param_range(mut self, param: &str, min: f64, max: f64) -> Self {
+        self.param_ranges.push((param.to_owned(), min, max));
+        self
+    }
+
+    /// Set the order files are returned in.
+    pub fn order(mut self, order: FileOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Return at most `limit` files.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Build the SQL and its parameters.
+    fn to_sql(
+        &self,
+        arch: &Archive,
+        select: &str,
+    ) -> Result<(String, Vec<Box<dyn ToSql>>), BufkitDataErr> {
+        let mut conditions: Vec<String> = vec![];
+        let mut params: Vec<Box<dyn ToSql>> = vec![];
+
+        fn add_list<T: ToSql + 'static>(
+            column: &str,
+            vals: impl IntoIterator<Item = T>,
+            conditions: &mut Vec<String>,
+            params: &mut Vec<Box<dyn ToSql>>,
+        ) {
+            let mut placeholders = vec![];
+            for val in vals {
+                params.push(Box::new(val));
+                placeholders.push(format!("?{}", params.len()));
+            }
+
+            if !placeholders.is_empty() {
+                conditions.push(format!("{} IN ({})", column, placeholders.join(", ")));
+            }
+        }
+
+        let stations = self
+            .stations
+            .iter()
+            .map(|&stn| arch.resolve_station_num(stn).map(Into::<u32>::into))
+            .collect::<Result<Vec<u32>, _>>()?;
+        add_list("files.station_num", stations, &mut conditions, &mut params);
+        add_list("files.id", self.ids.clone(), &mut conditions, &mut params);
+        add_list(
+            "files.model",
+            self.models.iter().map(|model| model.as_static_str()),
+            &mut conditions,
+            &mut params,
+        );
+        add_list(
+            "CAST(strftime('%H', files.init_time) AS INTEGER)",
+            self.cycle_hours.clone(),
+            &mut conditions,
+            &mut params,
+        );
+        add_list(
+            "sites.state",
+            self.states.iter().map(|state| state.as_static_str()),
+            &mut conditions,
+            &mut params,
+        );
+
+        if let Some((start, end)) = self.init_times {
+            params.push(Box::new(start));
+            params.push(Box::new(end));
+            conditions.push(format!(
+                "files.init_time >= ?{} AND files.init_time <= ?{}",
+                params.len() - 1,
+                params.len()
+            ));
+        }
+
+        if let Some((start, end)) = self.valid_times {
+            params.push(Box::new(start));
+            params.push(Box::new(end));
+            conditions.push(format!(
+                "files.end_time >= ?{} AND files.init_time <= ?{}",
+                params.len() - 1,
+                params.len()
+            ));
+        }
+
+        if let Some(region) = self.region.as_ref() {
+            let (min_lat, min_lon, max_lat, max_lon) = region.bounds();
+            for val in [min_lat, min_lon, max_lat, max_lon] {
+                params.push(Box::new(val));
+            }
+            let n = params.len();
+            conditions.push(format!(
+                "files.lat >= ?{} AND files.lon >= ?{} AND files.lat <= ?{} AND files.lon <= ?{}",
+                n - 3,
+                n - 2,
+                n - 1,
+                n
+            ));
+        }
+
+        for (param, min, max) in &self.param_ranges {
+            params.push(Box::new(param.clone()));
+            params.push(Box::new(*min));
+            params.push(Box::new(*max));
+            let n = params.len();
+            conditions.push(format!(
+                "EXISTS (SELECT 1 FROM derived_params WHERE derived_params.file_name = files.file_name \
+                    AND derived_params.param = ?{} AND derived_params.value >= ?{} \
+                    AND derived_params.value <= ?{})",
+                n - 2,
+                n - 1,
+                n
+            ));
+        }
+
+        let mut sql = format!(
+            "SELECT {} FROM files LEFT JOIN sites ON sites.station_num = files.station_num",
+            select
+        );
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+
+        Ok((sql, params))
+    }
+}
+
+impl Archive {
+    /// Get the metadata for all the files matching a query.
+    pub fn file_records(&self, query: &FileQuery) -> Result<Vec<FileRecord>, BufkitDataErr> {
+        let (mut sql, params) = query.to_sql(self, Self::FILE_RECORD_COLUMNS)?;
+
+        sql.push_str(match query.order {
+            FileOrder::OldestFirst => {
+                " ORDER BY files.init_time ASC, files.station_num ASC, files.model ASC"
+            }
+            FileOrder::NewestFirst => {
+                " ORDER BY files.init_time DESC, files.station_num ASC, files.model ASC"
+            }
+        });
+
+        // The region is checked after the query, so limit after that.
+        if let (Some(limit), None) = (query.limit, query.region.as_ref()) {
+            sql.push_str(&format!(" LIMIT {}", limit));
+        }
+
+        let mut stmt = self.db_conn.prepare(&sql)?;
+        let records = stmt.query_map(
+            rusqlite::params_from_iter(params.iter()),
+            Self::parse_row_to_file_record,
+        )?;
+
+        let mut vals = vec![];
+        for record in records {
+            let record = record?;
+
+            if let Some(region) = query.region.as_ref()
+                && !region.contains(record.coords)
+            {
+                continue;
+            }
+
+            vals.push(record);
+            if query.limit.is_some_and(|limit| vals.len() >= limit) {
+                break;
+            }
+        }
+
+        Ok(vals)
+    }
+
+    /// Count the files matching a query.
+    pub fn count_files(&self, query: &FileQuery) -> Result<usize, BufkitDataErr> {
+        if query.region.is_some() {
+            return Ok(self.file_records(query)?.len());
+        }
+
+        let (sql, params) = query.to_sql(self, "COUNT(*)")?;
+        let count: i64 =
+            self.db_conn
+                .query_row(&sql, rusqlite::params_from_iter(params.iter()), |row| {
+                    row.get(0)
+                })?;
+        let count = count as usize;
+
+        Ok(query.limit.map(|limit| limit.min(count)).unwrap_or(count))
+    }
+
+    /// Retrieve the metadata and contents of all the files matching a query.
+    ///
+    /// The files are loaded lazily as the iterator is used.
+    pub fn retrieve_files(&self, query: &FileQuery) -> Result<FileIter, BufkitDataErr> {
+        Ok(FileIter::new(self, self.file_records(query)?))
+    }
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                          Unit Tests
+--------------------------------------------------------------------------------------------------*/
+#[cfg(test)]
+mod unit {
+    use super::*;
+    use crate::archive::unit::*; // test helpers.
+
+    use chrono::NaiveDate;
+
+    fn time(day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2017, 4, day)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_file_query_filters() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+
+        let all = arch.file_records(&FileQuery::new()).unwrap();
+        assert_eq!(all.len(), 6);
+        assert!(all.windows(2).all(|w| w[0].init_time <= w[1].init_time));
+        assert_eq!(all[0].station_num, kmso);
+        assert_eq!(all[0].model, Model::NAM);
+        assert_eq!(all[0].init_time, time(1, 0));
+        assert_eq!(all[0].id.as_deref(), Some("KMSO"));
+
+        let query = FileQuery::new().station(kmso).model(Model::GFS);
+        assert_eq!(
+            arch.count_files(&query).unwrap(),
+            arch.count(kmso, Model::GFS).unwrap() as usize
+        );
+
+        let query = FileQuery::new().id("kmso").cycle_hours([0, 12]);
+        let records = arch.file_records(&query).unwrap();
+        assert_eq!(records.len(), 3);
+        assert!(
+            records
+                .iter()
+                .all(|rec| rec.init_time.format("%H").to_string() != "06")
+        );
+
+        let query = FileQuery::new().init_times(time(1, 6), time(1, 12));
+        assert_eq!(arch.count_files(&query).unwrap(), 3);
+
+        // All the files have data valid on the 2nd, none on the 20th.
+        let query = FileQuery::new().valid_times(time(2, 0), time(2, 0));
+        assert_eq!(arch.count_files(&query).unwrap(), 6);
+        let query = FileQuery::new().valid_times(time(20, 0), time(21, 0));
+        assert_eq!(arch.count_files(&query).unwrap(), 0);
+
+        let query = FileQuery::new().states([StateProv::MT]);
+        assert_eq!(arch.count_files(&query).unwrap(), 6);
+        let query = FileQuery::new().states([StateProv::ID, StateProv::WA]);
+        assert_eq!(arch.count_files(&query).unwrap(), 0);
+
+        let query = FileQuery::new().region(Region::from_bounds(46.0, -115.0, 48.0, -113.0));
+        assert_eq!(arch.count_files(&query).unwrap(), 6);
+        let query = FileQuery::new().region(Region::from_bounds(40.0, -115.0, 42.0, -113.0));
+        assert_eq!(arch.count_files(&query).unwrap(), 0);
+
+        let query = FileQuery::new().stations([StationNumber::from(1)]);
+        assert_eq!(arch.count_files(&query).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_file_query_order_and_limit() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+
+        let query = FileQuery::new()
+            .model(Model::GFS)
+            .order(FileOrder::NewestFirst)
+            .limit(2);
+        let records = arch.file_records(&query).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].init_time, time(1, 18));
+        assert_eq!(records[1].init_time, time(1, 12));
+        assert_eq!(arch.count_files(&query).unwrap(), 2);
+
+        let query = query.region(Region::from_bounds(46.0, -115.0, 48.0, -113.0));
+        assert_eq!(arch.file_records(&query).unwrap(), records);
+
+        let files = arch.retrieve_files(&query).unwrap();
+        assert_eq!(files.len(), 2);
+        for file in files {
+            let (record, text) = file.unwrap();
+            assert_eq!(
+                text,
+                arch.retrieve(record.station_num, record.model, record.init_time)
+                    .unwrap()
+            );
+        }
+    }
+}
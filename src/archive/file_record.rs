@@ -0,0 +1,258 @@
+//! Metadata about a file in the archive.
+
+use crate::{
+    archive::{Archive, FileOrder, FileQuery},
+    coords::Coords,
+    errors::BufkitDataErr,
+    models::Model,
+    site::StationNumber,
+};
+use chrono::NaiveDateTime;
+use std::str::FromStr;
+
+#[cfg(feature = "pylib")]
+use pyo3::prelude::*;
+
+/// The information about a file stored in the index.
+#[cfg_attr(feature = "pylib", pyclass(module = "bufkit_data"))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileRecord {
+    /// The station number of the site the file belongs to.
+    pub station_num: StationNumber,
+    /// The model that produced the file.
+    pub model: Model,
+    /// The initialization time of the model run.
+    pub init_time: NaiveDateTime,
+    /// The last valid time in the file.
+    pub end_time: NaiveDateTime,
+    /// The station id used in the file, if it had one.
+    pub id: Option<String>,
+    /// The location of the station in the file.
+    pub coords: Coords,
+    /// The elevation of the station in the file.
+    pub elevation: metfor::Meters,
+    /// The name of the file in the archive's data directory.
+    pub file_name: String,
+}
+
+impl FileRecord {
+    /// Check if the file has data valid at a time.
+    pub fn covers(&self, valid_time: NaiveDateTime) -> bool {
+        self.init_time <= valid_time && valid_time <= self.end_time
+    }
+
+    /// The time from the initialization time to the last valid time in the file.
+    pub fn forecast_length(&self) -> chrono::Duration {
+        self.end_time - self.init_time
+    }
+}
+
+impl Archive {
+    /// Get the metadata for a file in the archive.
+    pub fn file_record(
+        &self,
+        station_num: StationNumber,
+        model: Model,
+        init_time: NaiveDateTime,
+    ) -> Result<FileRecord, BufkitDataErr> {
+        let query = FileQuery::new()
+            .station(station_num)
+            .model(model)
+            .init_times(init_time, init_time);
+
+        self.file_records(&query)?
+            .pop()
+            .ok_or(BufkitDataErr::NotInIndex)
+    }
+
+    /// Get the metadata for all the files for a site & model, oldest first.
+    ///
+    /// This is like `inventory`, but with all the information in the index about each file.
+    pub fn inventory_records(
+        &self,
+        station_num: StationNumber,
+        model: Model,
+    ) -> Result<Vec<FileRecord>, BufkitDataErr> {
+        self.file_records(&FileQuery::new().station(station_num).model(model))
+    }
+
+    /// Retrieve a file from the archive along with its metadata.
+    pub fn retrieve_record(
+        &self,
+        station_num: StationNumber,
+        model: Model,
+        init_time: NaiveDateTime,
+    ) -> Result<(FileRecord, String), BufkitDataErr> {
+        let record = self.file_record(station_num, model, init_time)?;
+        let text = self.load_file(&record.file_name)?;
+
+        Ok((record, text))
+    }
+
+    /// Retrieve the most recent file along with its metadata.
+    pub fn retrieve_most_recent_record(
+        &self,
+        station_num: StationNumber,
+        model: Model,
+    ) -> Result<(FileRecord, String), BufkitDataErr> {
+        let query = FileQuery::new()
+            .station(station_num)
+            .model(model)
+            .order(FileOrder::NewestFirst)
+            .limit(1);
+
+        let record = self
+            .file_records(&query)?
+            .pop()
+            .ok_or(BufkitDataErr::NotInIndex)?;
+        let text = self.load_file(&record.file_name)?;
+
+        Ok((record, text))
+    }
+
+    /// The columns of the files table in the order `parse_row_to_file_record` expects them.
+    pub(crate) const FILE_RECORD_COLUMNS: &'static str = "
+        files.station_num,
+        files.model,
+        files.init_time,
+        files.end_time,
+        files.id,
+        files.lat,
+        files.lon,
+        files.elevation_m,
+        files.file_name";
+
+    pub(crate) fn parse_row_to_file_record(
+        row: &rusqlite::Row,
+    ) -> Result<FileRecord, rusqlite::Error> {
+        let model: String = row.get(1)?;
+        let model = Model::from_str(&model).map_err(|err| {
+            rusqlite::Error::FromSqlConversionFailure(1, rusqlite::types::Type::Text, Box::new(err))
+        })?;
+
+        Ok(FileRecord {
+            station_num: StationNumber::from(row.get::<_, u32>(0)?),
+            model,
+            init_time: row.get(2)?,
+            end_time: row.get(3)?,
+            id: row.get(4)?,
+            coords: Coords {
+                lat: row.get(5)?,
+                lon: row.get(6)?,
+            },
+            elevation: metfor::Meters(row.get(7)?),
+            file_name: row.get(8)?,
+        })
+    }
+
+    /// Read and decompress a file from the data directory.
+    pub(crate) fn load_file(&self, file_name: &str) -> Result<String, BufkitDataErr> {
+        super::file_iter::read_to_string(&self.data_root().join(file_name))
+    }
+}
+
+#[cfg(feature = "pylib")]
+#[cfg_attr(feature = "pylib", pymethods)]
+impl FileRecord {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!(
+            "FileRecord({}, {}, {})",
+            self.station_num, self.model, self.init_time
+        ))
+    }
+
+    #[getter]
+    fn get_station_num(&self) -> StationNumber {
+        self.station_num
+    }
+
+    #[getter]
+    fn get_model(&self) -> String {
+        self.model.as_static_str().to_owned()
+    }
+
+    #[getter]
+    fn get_init_time(&self) -> NaiveDateTime {
+        self.init_time
+    }
+
+    #[getter]
+    fn get_end_time(&self) -> NaiveDateTime {
+        self.end_time
+    }
+
+    #[getter]
+    fn get_id(&self) -> Option<String> {
+        self.id.clone()
+    }
+
+    #[getter]
+    fn get_coords(&self) -> (f64, f64) {
+        self.coords.into()
+    }
+
+    #[getter]
+    fn get_elevation_m(&self) -> f64 {
+        self.elevation.0
+    }
+
+    #[getter]
+    fn get_file_name(&self) -> String {
+        self.file_name.clone()
+    }
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                          Unit Tests
+--------------------------------------------------------------------------------------------------*/
+#[cfg(test)]
+mod unit {
+    use super::*;
+    use crate::archive::unit::*; // test helpers.
+
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_file_records() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+        let init_time = NaiveDate::from_ymd_opt(2017, 4, 1)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap();
+
+        let records = arch.inventory_records(kmso, Model::GFS).unwrap();
+        let times: Vec<_> = records.iter().map(|rec| rec.init_time).collect();
+        assert_eq!(times, arch.inventory(kmso, Model::GFS).unwrap());
+
+        let record = arch.file_record(kmso, Model::GFS, init_time).unwrap();
+        assert_eq!(record, records[1]);
+        assert_eq!(record.id.as_deref(), Some("KMSO"));
+        assert!((record.coords.lat - 46.92).abs() < 0.1);
+        assert!((record.coords.lon + 114.08).abs() < 0.1);
+        assert!(record.elevation.0 > 900.0 && record.elevation.0 < 1000.0);
+        assert!(record.covers(init_time + chrono::Duration::hours(24)));
+        assert!(!record.covers(init_time - chrono::Duration::hours(1)));
+        assert!(record.forecast_length() > chrono::Duration::zero());
+        assert!(arch.data_root().join(&record.file_name).exists());
+
+        let (rec, text) = arch.retrieve_record(kmso, Model::GFS, init_time).unwrap();
+        assert_eq!(rec, record);
+        assert_eq!(text, arch.retrieve(kmso, Model::GFS, init_time).unwrap());
+
+        let (rec, text) = arch.retrieve_most_recent_record(kmso, Model::GFS).unwrap();
+        assert_eq!(&rec, records.last().unwrap());
+        assert_eq!(text, arch.retrieve_most_recent(kmso, Model::GFS).unwrap());
+
+        match arch.file_record(kmso, Model::NAM4KM, init_time) {
+            Err(BufkitDataErr::NotInIndex) => {}
+            x => panic!("Should not be found: {:?}", x),
+        }
+    }
+}
@@ -0,0 +1,385 @@
+//! Fire weather and winter weather hazard indices for each forecast hour of a file.
+
+use crate::{
+    archive::{Archive, FileQuery, FileRecord, derived, hourly_params::HourlyParams},
+    errors::BufkitDataErr,
+};
+use chrono::NaiveDateTime;
+use metfor::Quantity;
+use rusqlite::ToSql;
+use serde::Serialize;
+use sounding_analysis::Sounding;
+use std::{collections::HashMap, io::Write};
+use strum_macros::{EnumString, IntoStaticStr};
+
+/// The names of the hazard indices in the derived parameter index. HAINES is also stored by
+/// `DerivedIndexing::Full`.
+pub(crate) const HAZARD_PARAMS: [&str; 4] = ["HAINES", "HDW", "PTYPE", "SNRAT"];
+
+/// The precipitation type forecast by the model.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, EnumString, IntoStaticStr, Hash)]
+#[strum(ascii_case_insensitive)]
+pub enum PrecipType {
+    /// No precipitation.
+    #[strum(to_string = "none")]
+    None,
+    /// Rain.
+    #[strum(to_string = "rain")]
+    Rain,
+    /// Snow.
+    #[strum(to_string = "snow")]
+    Snow,
+    /// Ice pellets, a.k.a. sleet.
+    #[strum(to_string = "ice_pellets")]
+    IcePellets,
+    /// Freezing rain.
+    #[strum(to_string = "freezing_rain")]
+    FreezingRain,
+    /// More than one type at the same time.
+    #[strum(to_string = "mixed")]
+    Mixed,
+}
+
+impl PrecipType {
+    /// Get the name of the precipitation type.
+    pub fn as_static_str(self) -> &'static str {
+        self.into()
+    }
+
+    /// Get the precipitation type from the categorical WXTS, WXTP, WXTZ, and WXTR values in the
+    /// surface section of a file, if they are all there.
+    pub fn from_surface(vals: &HashMap<String, f64>) -> Option<Self> {
+        let flags = [
+            (PrecipType::Snow, *vals.get("WXTS")?),
+            (PrecipType::IcePellets, *vals.get("WXTP")?),
+            (PrecipType::FreezingRain, *vals.get("WXTZ")?),
+            (PrecipType::Rain, *vals.get("WXTR")?),
+        ];
+
+        let mut types = flags
+            .iter()
+            .filter(|(_, flag)| *flag > 0.0)
+            .map(|(tp, _)| *tp);
+        Some(match (types.next(), types.next()) {
+            (None, _) => PrecipType::None,
+            (Some(tp), None) => tp,
+            (Some(_), Some(_)) => PrecipType::Mixed,
+        })
+    }
+
+    /// The number used for the type in the derived parameter index.
+    pub fn code(self) -> u8 {
+        match self {
+            PrecipType::None => 0,
+            PrecipType::Rain => 1,
+            PrecipType::Snow => 2,
+            PrecipType::IcePellets => 3,
+            PrecipType::FreezingRain => 4,
+            PrecipType::Mixed => 5,
+        }
+    }
+
+    /// Get the type from the number used in the derived parameter index.
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(PrecipType::None),
+            1 => Some(PrecipType::Rain),
+            2 => Some(PrecipType::Snow),
+            3 => Some(PrecipType::IcePellets),
+            4 => Some(PrecipType::FreezingRain),
+            5 => Some(PrecipType::Mixed),
+            _ => None,
+        }
+    }
+}
+
+/// The hazard indices for one forecast hour, values that can't be computed are `None`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HazardIndices {
+    /// The initialization time of the model run.
+    pub init_time: NaiveDateTime,
+    /// The valid time of the forecast hour.
+    pub valid_time: NaiveDateTime,
+    /// The hours since the model initialization time.
+    pub lead_hours: i32,
+    /// The Haines index, the low, mid, or high elevation variant depending on the surface
+    /// pressure.
+    pub haines: Option<f64>,
+    /// The hot-dry-windy index.
+    pub hot_dry_windy: Option<f64>,
+    /// The precipitation type from the surface section of the file.
+    pub precip_type: Option<PrecipType>,
+    /// The snow to liquid ratio from the Kuchera method. This is computed whether or not snow is
+    /// forecast.
+    pub snow_ratio: Option<f64>,
+}
+
+/// A row of the CSV output.
+#[derive(Serialize)]
+struct HazardRow {
+    init_time: String,
+    valid_time: String,
+    lead_hours: i32,
+    haines: Option<f64>,
+    hot_dry_windy: Option<f64>,
+    precip_type: Option<&'static str>,
+    snow_ratio: Option<f64>,
+}
+
+impl HazardIndices {
+    /// Write the indices as CSV with a header row.
+    pub fn write_csv(hours: &[HazardIndices], writer: impl Write) -> Result<(), BufkitDataErr> {
+        const TIME_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+        let mut wtr = csv::Writer::from_writer(writer);
+        for hour in hours {
+            wtr.serialize(HazardRow {
+                init_time: hour.init_time.format(TIME_FORMAT).to_string(),
+                valid_time: hour.valid_time.format(TIME_FORMAT).to_string(),
+                lead_hours: hour.lead_hours,
+                haines: hour.haines,
+                hot_dry_windy: hour.hot_dry_windy,
+                precip_type: hour.precip_type.map(PrecipType::as_static_str),
+                snow_ratio: hour.snow_ratio,
+            })?;
+        }
+        wtr.flush()?;
+
+        Ok(())
+    }
+
+    /// The values to store in the derived parameter index.
+    fn params(&self) -> Vec<(&'static str, f64)> {
+        [
+            ("HAINES", self.haines),
+            ("HDW", self.hot_dry_windy),
+            ("PTYPE", self.precip_type.map(|tp| tp.code() as f64)),
+            ("SNRAT", self.snow_ratio),
+        ]
+        .into_iter()
+        .filter_map(|(name, val)| val.map(|val| (name, val)))
+        .collect()
+    }
+}
+
+impl Archive {
+    /// Compute the hazard indices for each forecast hour in a file, in order of valid time.
+    pub fn hazard_indices(&self, record: &FileRecord) -> Result<Vec<HazardIndices>, BufkitDataErr> {
+        let text = self.load_file(&record.file_name)?;
+        let surface: HashMap<NaiveDateTime, HashMap<String, f64>> =
+            HourlyParams::parse(&text)?.hours.into_iter().collect();
+
+        let mut hours: Vec<HazardIndices> = self
+            .shared_soundings(record)?
+            .iter()
+            .filter_map(|(snd, _)| {
+                let valid_time = snd.valid_time()?;
+
+                Some(HazardIndices {
+                    init_time: record.init_time,
+                    valid_time,
+                    lead_hours: (valid_time - record.init_time).num_hours() as i32,
+                    haines: derived::haines(snd),
+                    hot_dry_windy: sounding_analysis::hot_dry_windy(snd).ok(),
+                    precip_type: surface.get(&valid_time).and_then(PrecipType::from_surface),
+                    snow_ratio: kuchera_snow_ratio(snd),
+                })
+            })
+            .collect();
+
+        hours.sort_by_key(|hour| hour.valid_time);
+
+        Ok(hours)
+    }
+
+    /// Compute the hazard indices for all the files matching a query and store them in the
+    /// derived parameter index as HAINES, HDW, PTYPE (see `PrecipType::code`), and SNRAT,
+    /// replacing any that were already stored. Returns the number of files indexed.
+    ///
+    /// Once stored they can be queried like the other derived parameters, e.g. with
+    /// `derived_param_values` or `FileQuery::param_range`.
+    pub fn index_hazards(&self, query: &FileQuery) -> Result<usize, BufkitDataErr> {
+        let records = self.file_records(query)?;
+
+        let tx = self.db_conn.unchecked_transaction()?;
+        let mut del_stmt = self.db_conn.prepare(
+            "
+                DELETE FROM derived_params
+                WHERE file_name = ?1 AND param IN (?2, ?3, ?4, ?5)
+            ",
+        )?;
+        let mut ins_stmt = self.db_conn.prepare(
+            "
+                INSERT OR REPLACE INTO derived_params
+                    (file_name, valid_time, lead_hours, param, value)
+                VALUES (?1, ?2, ?3, ?4, ?5)
+            ",
+        )?;
+
+        for record in &records {
+            let [haines, hdw, ptype, snrat] = HAZARD_PARAMS;
+            del_stmt.execute([&record.file_name, haines, hdw, ptype, snrat])?;
+
+            for hour in self.hazard_indices(record)? {
+                for (param, value) in hour.params() {
+                    if value.is_finite() {
+                        ins_stmt.execute([
+                            &record.file_name as &dyn ToSql,
+                            &hour.valid_time,
+                            &hour.lead_hours,
+                            &param,
+                            &value,
+                        ])?;
+                    }
+                }
+            }
+        }
+
+        drop((del_stmt, ins_stmt));
+        tx.commit()?;
+
+        Ok(records.len())
+    }
+}
+
+/// The Kuchera snow ratio, from the warmest temperature between the surface and 500 hPa.
+fn kuchera_snow_ratio(snd: &Sounding) -> Option<f64> {
+    let max_t = snd
+        .pressure_profile()
+        .iter()
+        .zip(snd.temperature_profile())
+        .filter_map(|(p, t)| Some((p.into_option()?.unpack(), t.into_option()?.unpack())))
+        .filter(|&(p, _)| p >= 500.0)
+        .map(|(_, t)| t + 273.15)
+        .reduce(f64::max)?;
+
+    let ratio = if max_t > 271.16 {
+        12.0 + 2.0 * (271.16 - max_t)
+    } else {
+        12.0 + (271.16 - max_t)
+    };
+
+    Some(ratio.max(0.0))
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                          Unit Tests
+--------------------------------------------------------------------------------------------------*/
+#[cfg(test)]
+mod unit {
+    use super::*;
+    use crate::archive::unit::*; // test helpers.
+    use crate::{archive::DerivedIndexing, models::Model, site::StationNumber};
+
+    #[test]
+    fn test_precip_type() {
+        let surface = |flags: [f64; 4]| -> HashMap<String, f64> {
+            ["WXTS", "WXTP", "WXTZ", "WXTR"]
+                .iter()
+                .map(|name| name.to_string())
+                .zip(flags)
+                .collect()
+        };
+
+        assert_eq!(
+            PrecipType::from_surface(&surface([0.0, 0.0, 0.0, 0.0])),
+            Some(PrecipType::None)
+        );
+        assert_eq!(
+            PrecipType::from_surface(&surface([1.0, 0.0, 0.0, 0.0])),
+            Some(PrecipType::Snow)
+        );
+        assert_eq!(
+            PrecipType::from_surface(&surface([0.0, 0.0, 1.0, 0.0])),
+            Some(PrecipType::FreezingRain)
+        );
+        assert_eq!(
+            PrecipType::from_surface(&surface([1.0, 0.0, 0.0, 1.0])),
+            Some(PrecipType::Mixed)
+        );
+        assert_eq!(PrecipType::from_surface(&HashMap::new()), None);
+
+        for code in 0..6 {
+            assert_eq!(PrecipType::from_code(code).unwrap().code(), code);
+        }
+        assert_eq!(PrecipType::from_code(6), None);
+        assert_eq!(
+            "Freezing_Rain".parse::<PrecipType>().unwrap(),
+            PrecipType::FreezingRain
+        );
+    }
+
+    #[test]
+    fn test_hazard_indices() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+
+        for model in [Model::GFS, Model::NAM] {
+            let record = arch.inventory_records(kmso, model).unwrap().remove(0);
+            let hours = arch.hazard_indices(&record).unwrap();
+
+            assert!(!hours.is_empty());
+            assert_eq!(hours[0].valid_time, record.init_time);
+            assert!(hours.windows(2).all(|w| w[0].valid_time < w[1].valid_time));
+            assert!(hours.iter().all(|hr| hr.precip_type.is_some()));
+            assert!(hours.iter().any(|hr| hr.hot_dry_windy.is_some()));
+            for hr in &hours {
+                if let Some(haines) = hr.haines {
+                    assert!((2.0..=6.0).contains(&haines));
+                }
+                if let Some(ratio) = hr.snow_ratio {
+                    assert!(ratio >= 0.0);
+                }
+            }
+
+            let mut buf = vec![];
+            HazardIndices::write_csv(&hours, &mut buf).unwrap();
+            let text = String::from_utf8(buf).unwrap();
+            assert_eq!(
+                text.lines().next().unwrap(),
+                "init_time,valid_time,lead_hours,haines,hot_dry_windy,precip_type,snow_ratio"
+            );
+            assert_eq!(text.lines().count(), hours.len() + 1);
+        }
+    }
+
+    #[test]
+    fn test_index_hazards() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+
+        let query = FileQuery::new().model(Model::GFS);
+        assert_eq!(arch.index_hazards(&query).unwrap(), 3);
+        assert_eq!(arch.index_hazards(&query).unwrap(), 3);
+
+        let record = arch.file_records(&query).unwrap().remove(0);
+        let hours = arch.derived_params(&record).unwrap();
+        assert!(!hours.is_empty());
+        assert!(hours.iter().all(|hr| hr.values.contains_key("PTYPE")));
+        assert!(hours.iter().any(|hr| hr.values.contains_key("HDW")));
+
+        let ptypes = arch.derived_param_values(&query, "PTYPE").unwrap();
+        assert!(
+            ptypes
+                .iter()
+                .all(|(_, _, code)| PrecipType::from_code(*code as u8).is_some())
+        );
+
+        // Indexing the other derived parameters keeps the hazard indices.
+        arch.index_derived_params(&query, DerivedIndexing::StationParams)
+            .unwrap();
+        let hours = arch.derived_params(&record).unwrap();
+        assert!(hours.iter().all(|hr| hr.values.contains_key("PTYPE")));
+        assert!(hours[0].values.contains_key("PWAT"));
+    }
+}
@@ -0,0 +1,134 @@
+//! Parse the surface and station parameters for each forecast hour directly from the text of a
+//! bufkit file, without parsing the profiles.
+
+use crate::errors::BufkitDataErr;
+use chrono::NaiveDateTime;
+use std::collections::HashMap;
+
+/// The value bufkit files use for missing data.
+const MISSING: f64 = -9999.0;
+
+/// The surface and station parameters from each forecast hour of a file, keyed by their names in
+/// the file, e.g. T2MS, P01M, or PWAT. Missing values are left out.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct HourlyParams {
+    pub(crate) hours: Vec<(NaiveDateTime, HashMap<String, f64>)>,
+}
+
+impl HourlyParams {
+    pub(crate) fn parse(text: &str) -> Result<Self, BufkitDataErr> {
+        let break_point = text
+            .find("STN YYMMDD/HHMM")
+            .ok_or(BufkitDataErr::NotEnoughData)?;
+        let (upper_air, surface) = text.split_at(break_point);
+
+        let mut hours: Vec<(NaiveDateTime, HashMap<String, f64>)> = vec![];
+
+        // Surface section, a header of column names followed by rows of values.
+        let tokens: Vec<&str> = surface.split_whitespace().collect();
+        let num_cols = tokens
+            .iter()
+            .position(|tok| tok.parse::<f64>().is_ok())
+            .ok_or(BufkitDataErr::NotEnoughData)?;
+        let (header, values) = tokens.split_at(num_cols);
+
+        let rows = values.chunks_exact(num_cols);
+        if !rows.remainder().is_empty() {
+            return Err(BufkitDataErr::GeneralError(format!(
+                "surface section has {} values, not a multiple of its {} columns",
+                values.len(),
+                num_cols
+            )));
+        }
+
+        for row in rows {
+            let valid_time = parse_time(row[1])?;
+            let vals = header
+                .iter()
+                .zip(row)
+                .skip(2)
+                .filter_map(|(&name, val)| parse_value(val).map(|val| (name.to_owned(), val)))
+                .collect();
+
+            hours.push((valid_time, vals));
+        }
+
+        // Station parameters, `KEY = VALUE` pairs after the time of each upper air sounding.
+        let mut station_params: Vec<&str> = vec![];
+        let mut valid_time = None;
+        for line in upper_air.lines() {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+
+            if let ["STNPRM", "=", names] = tokens.as_slice() {
+                station_params = names.split(';').collect();
+                continue;
+            }
+
+            for pair in tokens.windows(3).filter(|pair| pair[1] == "=") {
+                if pair[0] == "TIME" {
+                    valid_time = Some(parse_time(pair[2])?);
+                } else if station_params.contains(&pair[0])
+                    && let (Some(valid_time), Some(val)) = (valid_time, parse_value(pair[2]))
+                {
+                    let hour = match hours.iter().position(|(time, _)| *time == valid_time) {
+                        Some(idx) => &mut hours[idx].1,
+                        None => {
+                            hours.push((valid_time, HashMap::new()));
+                            &mut hours.last_mut().unwrap().1
+                        }
+                    };
+                    hour.insert(pair[0].to_owned(), val);
+                }
+            }
+        }
+
+        hours.sort_by_key(|(time, _)| *time);
+
+        Ok(HourlyParams { hours })
+    }
+}
+
+/// Parse a bufkit time, e.g. 170401/1200.
+fn parse_time(token: &str) -> Result<NaiveDateTime, BufkitDataErr> {
+    NaiveDateTime::parse_from_str(token, "%y%m%d/%H%M")
+        .map_err(|err| BufkitDataErr::GeneralError(format!("invalid time {}: {}", token, err)))
+}
+
+fn parse_value(token: &str) -> Option<f64> {
+    token.parse().ok().filter(|&val| val != MISSING)
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                          Unit Tests
+--------------------------------------------------------------------------------------------------*/
+#[cfg(test)]
+mod unit {
+    use super::*;
+    use crate::archive::unit::*; // test helpers.
+
+    #[test]
+    fn test_parse_hourly_params() {
+        let text = get_test_data()
+            .into_iter()
+            .find(|(_, model, _)| *model == crate::models::Model::GFS)
+            .map(|(_, _, text)| text)
+            .unwrap();
+
+        let params = HourlyParams::parse(&text).unwrap();
+        assert!(params.hours.len() > 1);
+        assert!(params.hours.windows(2).all(|w| w[0].0 < w[1].0));
+
+        let (_, first) = &params.hours[0];
+        for name in ["PMSL", "T2MS", "P03M", "TD2M", "SHOW", "PWAT", "BRCH"] {
+            assert!(first.contains_key(name), "missing {}", name);
+        }
+        assert!(!first.contains_key("S03M")); // Missing in the file.
+        assert!(!first.contains_key("STN"));
+        assert!(!first.contains_key("STIM"));
+
+        // A truncated surface section is an error, not a missing row.
+        let truncated = text.trim_end();
+        let truncated = &truncated[..truncated.rfind(char::is_whitespace).unwrap()];
+        assert!(HourlyParams::parse(truncated).is_err());
+    }
+}
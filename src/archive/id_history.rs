@@ -0,0 +1,401 @@
+//! Track which ids have been used with which station numbers over time.
+//!
+//! Station ids get reused and reassigned, so the same id may refer to different stations at
+//! different times or for different models. The history is derived from the files as they are
+//! added, and manual aliases can be added for ids that never appear in any file.
+
+use crate::{archive::Archive, errors::BufkitDataErr, models::Model, site::StationNumber};
+use chrono::NaiveDateTime;
+use rusqlite::{OptionalExtension, ToSql};
+use std::str::FromStr;
+
+/// A range of time an id was used with a station.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IdHistoryEntry {
+    /// The station number the id refers to.
+    pub station_num: StationNumber,
+    /// The station id.
+    pub id: String,
+    /// The model the id was used with, `None` for aliases that apply to all models.
+    pub model: Option<Model>,
+    /// The first initialization time the id was used.
+    pub start: NaiveDateTime,
+    /// The last initialization time the id was used, `None` if there is no end to the range.
+    pub end: Option<NaiveDateTime>,
+    /// Whether this was entered manually as an alias instead of derived from the files.
+    pub is_alias: bool,
+}
+
+impl IdHistoryEntry {
+    /// Check if this entry covers a time.
+    pub fn contains(&self, time: NaiveDateTime) -> bool {
+        self.start <= time && self.end.map(|end| time <= end).unwrap_or(true)
+    }
+}
+
+impl Archive {
+    /// Get the history of ids used with a station, in chronological order.
+    pub fn id_history(
+        &self,
+        station_num: StationNumber,
+    ) -> Result<Vec<IdHistoryEntry>, BufkitDataErr> {
+        let mut stmt = self.db_conn.prepare(
+            "
+                SELECT station_num, id, model, start_time, end_time, is_alias
+                FROM id_history
+                WHERE station_num = ?1
+                ORDER BY start_time ASC, id ASC
+            ",
+        )?;
+
+        let vals: Result<Vec<IdHistoryEntry>, BufkitDataErr> = stmt
+            .query_and_then(
+                [Into::<u32>::into(self.resolve_station_num(station_num)?)],
+                Self::parse_row_to_id_history,
+            )?
+            .collect();
+
+        vals
+    }
+
+    /// Get the history of stations an id has been used with, in chronological order.
+    pub fn id_history_for_id(&self, id: &str) -> Result<Vec<IdHistoryEntry>, BufkitDataErr> {
+        let mut stmt = self.db_conn.prepare(
+            "
+                SELECT station_num, id, model, start_time, end_time, is_alias
+                FROM id_history
+                WHERE id = ?1
+                ORDER BY start_time ASC, station_num ASC
+            ",
+        )?;
+
+        let vals: Result<Vec<IdHistoryEntry>, BufkitDataErr> = stmt
+            .query_and_then([id.to_uppercase()], Self::parse_row_to_id_history)?
+            .collect();
+
+        vals
+    }
+
+    /// Find the station number an id referred to at a given time.
+    ///
+    /// If `model` is `None`, the id may have been used with any model. The station the id was used
+    /// with most recently wins, and aliases win ties with ids derived from the files. This is the
+    /// same precedence as `station_num_for_id`.
+    pub fn station_num_for_id_at(
+        &self,
+        id: &str,
+        time: NaiveDateTime,
+        model: Option<Model>,
+    ) -> Result<StationNumber, BufkitDataErr> {
+        let station_num: Option<u32> = self
+            .db_conn
+            .query_row(
+                "
+                    SELECT station_num
+                    FROM id_history
+                    WHERE id = ?1
+                        AND (?3 IS NULL OR model IS NULL OR model = ?3)
+                        AND start_time <= ?2
+                        AND (end_time IS NULL OR end_time >= ?2)
+                    ORDER BY COALESCE(end_time, '9999') DESC, is_alias DESC
+                    LIMIT 1
+                ",
+                [
+                    &id.to_uppercase() as &dyn ToSql,
+                    &time,
+                    &model.map(Model::as_static_str),
+                ],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        station_num
+            .map(StationNumber::from)
+            .ok_or(BufkitDataErr::NotInIndex)
+    }
+
+    /// Add an alias so lookups of `id` resolve to this station.
+    ///
+    /// If `model` is `None` the alias applies to all models. If `end` is `None` the alias has no
+    /// end date.
+    pub fn add_id_alias(
+        &self,
+        station_num: StationNumber,
+        id: &str,
+        model: Option<Model>,
+        start: NaiveDateTime,
+        end: Option<NaiveDateTime>,
+    ) -> Result<(), BufkitDataErr> {
+        if self.site(station_num).is_none() {
+            return Err(BufkitDataErr::NotInIndex);
+        }
+
+        self.db_conn.execute(
+            "
+                INSERT INTO id_history (station_num, id, model, start_time, end_time, is_alias)
+                VALUES (?1, ?2, ?3, ?4, ?5, 1)
+            ",
+            [
+                &Into::<u32>::into(station_num) as &dyn ToSql,
+                &id.to_uppercase(),
+                &model.map(Model::as_static_str),
+                &start,
+                &end,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Remove all the aliases for an id with a station.
+    pub fn remove_id_alias(
+        &self,
+        station_num: StationNumber,
+        id: &str,
+    ) -> Result<(), BufkitDataErr> {
+        self.db_conn.execute(
+            "DELETE FROM id_history WHERE station_num = ?1 AND id = ?2 AND is_alias = 1",
+            [
+                &Into::<u32>::into(station_num) as &dyn ToSql,
+                &id.to_uppercase(),
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Rebuild the part of the id history derived from the files, leaving aliases alone.
+    ///
+    /// This is only needed if the files were modified outside of this crate, `clean` calls it.
+    pub fn rebuild_id_history(&self) -> Result<(), BufkitDataErr> {
+        Self::rebuild_file_id_history(&self.db_conn)
+    }
+
+    pub(crate) fn rebuild_file_id_history(
+        db_conn: &rusqlite::Connection,
+    ) -> Result<(), BufkitDataErr> {
+        db_conn.execute_batch(
+            "
+                BEGIN;
+                DELETE FROM id_history WHERE is_alias = 0;
+                INSERT INTO id_history (station_num, id, model, start_time, end_time, is_alias)
+                SELECT station_num, id, model, MIN(init_time), MAX(init_time), 0
+                FROM files
+                WHERE id IS NOT NULL
+                GROUP BY station_num, id, model;
+                COMMIT;
+            ",
+        )?;
+
+        Ok(())
+    }
+
+    /// Recompute the ids derived from the files for a station and model, e.g. after a file is
+    /// removed. Ids no longer used by any file are dropped.
+    pub(crate) fn refresh_file_ids(
+        &self,
+        station_num: StationNumber,
+        model: Model,
+    ) -> Result<(), BufkitDataErr> {
+        let params = [
+            &Into::<u32>::into(station_num) as &dyn ToSql,
+            &model.as_static_str(),
+        ];
+
+        self.db_conn.execute(
+            "DELETE FROM id_history WHERE station_num = ?1 AND model = ?2 AND is_alias = 0",
+            params,
+        )?;
+        self.db_conn.execute(
+            "
+                INSERT INTO id_history (station_num, id, model, start_time, end_time, is_alias)
+                SELECT station_num, id, model, MIN(init_time), MAX(init_time), 0
+                FROM files
+                WHERE station_num = ?1 AND model = ?2 AND id IS NOT NULL
+                GROUP BY station_num, id, model
+            ",
+            params,
+        )?;
+
+        Ok(())
+    }
+
+    /// Record that an id was used with a station by a file.
+    pub(crate) fn record_file_id(
+        &self,
+        station_num: StationNumber,
+        id: &str,
+        model: Model,
+        init_time: NaiveDateTime,
+    ) -> Result<(), BufkitDataErr> {
+        self.db_conn.execute(
+            "
+                INSERT INTO id_history (station_num, id, model, start_time, end_time, is_alias)
+                VALUES (?1, ?2, ?3, ?4, ?4, 0)
+                ON CONFLICT (station_num, id, model) WHERE is_alias = 0 DO UPDATE SET
+                    start_time = MIN(start_time, excluded.start_time),
+                    end_time = MAX(end_time, excluded.end_time)
+            ",
+            [
+                &Into::<u32>::into(station_num) as &dyn ToSql,
+                &id,
+                &model.as_static_str(),
+                &init_time,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    fn parse_row_to_id_history(row: &rusqlite::Row) -> Result<IdHistoryEntry, BufkitDataErr> {
+        let model: Option<Model> = row
+            .get::<_, Option<String>>(2)?
+            .map(|model| Model::from_str(&model))
+            .transpose()?;
+
+        Ok(IdHistoryEntry {
+            station_num: StationNumber::from(row.get::<_, u32>(0)?),
+            id: row.get(1)?,
+            model,
+            start: row.get(3)?,
+            end: row.get(4)?,
+            is_alias: row.get(5)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod unit {
+    use super::*;
+    use crate::archive::unit::*; // test helpers.
+
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_id_history_from_files() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+        let history = arch.id_history(kmso).expect("Database error.");
+        assert_eq!(history.len(), 2);
+
+        let gfs = history
+            .iter()
+            .find(|entry| entry.model == Some(Model::GFS))
+            .unwrap();
+        assert_eq!(gfs.id, "KMSO");
+        assert!(!gfs.is_alias);
+        assert_eq!(
+            gfs.start,
+            NaiveDate::from_ymd_opt(2017, 4, 1)
+                .unwrap()
+                .and_hms_opt(6, 0, 0)
+                .unwrap()
+        );
+        assert_eq!(
+            gfs.end,
+            Some(
+                NaiveDate::from_ymd_opt(2017, 4, 1)
+                    .unwrap()
+                    .and_hms_opt(18, 0, 0)
+                    .unwrap()
+            )
+        );
+
+        // Rebuilding gives the same thing.
+        arch.rebuild_id_history().expect("Database error.");
+        assert_eq!(arch.id_history(kmso).unwrap(), history);
+        assert_eq!(arch.id_history_for_id("kmso").unwrap(), history);
+    }
+
+    #[test]
+    fn test_station_num_for_id_at() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+        for site in &get_test_sites() {
+            arch.add_site(site).expect("Error adding site.");
+        }
+
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+        let time = NaiveDate::from_ymd_opt(2017, 4, 1)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap();
+        let before = NaiveDate::from_ymd_opt(2015, 6, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+
+        assert_eq!(
+            arch.station_num_for_id_at("KMSO", time, None).unwrap(),
+            kmso
+        );
+        assert_eq!(
+            arch.station_num_for_id_at("kmso", time, Some(Model::NAM))
+                .unwrap(),
+            kmso
+        );
+        match arch.station_num_for_id_at("KMSO", before, None) {
+            Err(BufkitDataErr::NotInIndex) => {}
+            x => panic!("Should not be found: {:?}", x),
+        }
+
+        // Before KMSO was in the archive, the id belonged to another station.
+        let other = StationNumber::from(3);
+        arch.add_id_alias(
+            other,
+            "kmso",
+            None,
+            before,
+            Some(time - chrono::Duration::days(30)),
+        )
+        .expect("Error adding alias.");
+        assert_eq!(
+            arch.station_num_for_id_at("KMSO", before, None).unwrap(),
+            other
+        );
+        assert_eq!(
+            arch.station_num_for_id_at("KMSO", time, None).unwrap(),
+            kmso
+        );
+
+        // A closed alias covering the same time as the files loses to the more recent use, the
+        // same as with station_num_for_id.
+        arch.add_id_alias(other, "KMSO", None, before, Some(time))
+            .expect("Error adding alias.");
+        assert_eq!(
+            arch.station_num_for_id_at("KMSO", time, None).unwrap(),
+            kmso
+        );
+        assert_eq!(arch.station_num_for_id("KMSO", Model::GFS).unwrap(), kmso);
+
+        // An open ended alias for an id never seen in a file.
+        arch.add_id_alias(kmso, "MSO", None, time, None)
+            .expect("Error adding alias.");
+        assert_eq!(arch.station_num_for_id("mso", Model::GFS).unwrap(), kmso);
+        assert!(
+            !arch
+                .ids(kmso, Model::GFS)
+                .unwrap()
+                .contains(&"MSO".to_owned())
+        );
+
+        arch.remove_id_alias(kmso, "mso")
+            .expect("Error removing alias.");
+        assert!(arch.station_num_for_id("MSO", Model::GFS).is_err());
+
+        assert!(
+            arch.add_id_alias(StationNumber::from(99), "KXYZ", None, time, None)
+                .is_err()
+        );
+    }
+}
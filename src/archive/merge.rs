@@ -0,0 +1,397 @@
+//! Merge duplicate sites and renumber sites.
+//!
+//! When a provider changes the station number it uses for a site, the archive ends up with two
+//! sites for the same place. Merging moves everything onto one station number and leaves an alias
+//! behind so the old number still works for lookups.
+
+use crate::{archive::Archive, errors::BufkitDataErr, site::SiteInfo, site::StationNumber};
+
+impl Archive {
+    /// Get the station number that `station_num` refers to.
+    ///
+    /// Station numbers that were merged into another site or renumbered resolve to the station
+    /// they were moved to. All other station numbers resolve to themselves.
+    pub fn resolve_station_num(
+        &self,
+        station_num: StationNumber,
+    ) -> Result<StationNumber, BufkitDataErr> {
+        let resolved: u32 = self.db_conn.query_row(
+            "
+                SELECT COALESCE(
+                    (SELECT station_num FROM sites WHERE station_num = ?1),
+                    (SELECT station_num FROM station_num_aliases WHERE old_num = ?1),
+                    ?1)
+            ",
+            [Into::<u32>::into(station_num)],
+            |row| row.get(0),
+        )?;
+
+        Ok(StationNumber::from(resolved))
+    }
+
+    /// Merge two sites that are really the same place.
+    ///
+    /// All the files for `from` are moved to `into`, and `from` becomes an alias for `into`. If
+    /// both sites have a file for the same model and initialization time, the one from `into` is
+    /// kept and the one from `from` is deleted. The site metadata from `into` is kept, and any
+    /// values it is missing are filled in from `from`. Returns the merged site.
+    pub fn merge_sites(
+        &self,
+        from: StationNumber,
+        into: StationNumber,
+    ) -> Result<SiteInfo, BufkitDataErr> {
+        if from == into {
+            return Err(BufkitDataErr::GeneralError(format!(
+                "cannot merge station {} into itself",
+                from
+            )));
+        }
+
+        let from_site = self.exact_site(from)?;
+        let into_site = self.exact_site(into)?;
+
+        let tx = self.db_conn.unchecked_transaction()?;
+        let (merged, duplicates) = self.move_site(from_site, into_site)?;
+        tx.commit()?;
+
+        for fname in duplicates {
+            std::fs::remove_file(self.data_root().join(fname))?;
+        }
+
+        Ok(merged)
+    }
+
+    /// Change the station number of a site.
+    ///
+    /// The old station number becomes an alias for the new one. If there is already a site using
+    /// the new station number, use `merge_sites` instead.
+    pub fn renumber_site(
+        &self,
+        old: StationNumber,
+        new: StationNumber,
+    ) -> Result<SiteInfo, BufkitDataErr> {
+        let old_site = self.exact_site(old)?;
+
+        if self.exact_site(new).is_ok() {
+            return Err(BufkitDataErr::GeneralError(format!(
+                "station {} already exists, merge the sites instead",
+                new
+            )));
+        }
+
+        let new_site = SiteInfo {
+            station_num: new,
+            ..SiteInfo::default()
+        };
+
+        let tx = self.db_conn.unchecked_transaction()?;
+        self.add_site(&new_site)?;
+        let (renumbered, duplicates) = self.move_site(old_site, new_site)?;
+        tx.commit()?;
+
+        debug_assert!(duplicates.is_empty());
+
+        Ok(renumbered)
+    }
+
+    /// Get a site without resolving aliases.
+    fn exact_site(&self, station_num: StationNumber) -> Result<SiteInfo, BufkitDataErr> {
+        self.site(station_num)
+            .filter(|site| site.station_num == station_num)
+            .ok_or(BufkitDataErr::NotInIndex)
+    }
+
+    /// Move everything from one site to another, returning the merged site and the names of the
+    /// duplicate files that should be removed from the data directory.
+    ///
+    /// This should be done inside a transaction.
+    fn move_site(
+        &self,
+        from: SiteInfo,
+        into: SiteInfo,
+    ) -> Result<(SiteInfo, Vec<String>), BufkitDataErr> {
+        let from_num: u32 = from.station_num.into();
+        let into_num: u32 = into.station_num.into();
+
+        let mut dup_stmt = self.db_conn.prepare(
+            "
+                SELECT dups.file_name
+                FROM files AS dups JOIN files AS keep
+                    ON keep.model = dups.model AND keep.init_time = dups.init_time
+                WHERE dups.station_num = ?1 AND keep.station_num = ?2
+            ",
+        )?;
+        let mut duplicates: Vec<String> = dup_stmt
+            .query_map([from_num, into_num], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+
+        let mut del_stmt = self
+            .db_conn
+            .prepare(include_str!("modify/delete_file_by_name.sql"))?;
+        for fname in &duplicates {
+            del_stmt.execute([fname])?;
+            self.sounding_cache().remove(fname);
+        }
+
+        self.db_conn.execute(
+            "UPDATE files SET station_num = ?2 WHERE station_num = ?1",
+            [from_num, into_num],
+        )?;
+
+        // Ensemble member files are handled the same way, matching on the tag too.
+        let mut member_dup_stmt = self.db_conn.prepare(
+            "
+                SELECT dups.file_name
+                FROM member_files AS dups JOIN member_files AS keep
+                    ON keep.tag = dups.tag AND keep.model = dups.model
+                        AND keep.init_time = dups.init_time
+                WHERE dups.station_num = ?1 AND keep.station_num = ?2
+            ",
+        )?;
+        let member_duplicates: Vec<String> = member_dup_stmt
+            .query_map([from_num, into_num], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        for fname in &member_duplicates {
+            self.db_conn
+                .execute("DELETE FROM member_files WHERE file_name = ?1", [fname])?;
+            self.sounding_cache().remove(fname);
+        }
+        self.db_conn.execute(
+            "UPDATE member_files SET station_num = ?2 WHERE station_num = ?1",
+            [from_num, into_num],
+        )?;
+        duplicates.extend(member_duplicates);
+
+        // Climatologies are out of date once the files are combined, so they must be rebuilt.
+        self.db_conn.execute(
+            "DELETE FROM climatology WHERE station_num IN (?1, ?2)",
+            [from_num, into_num],
+        )?;
+
+        // Observations follow the site, if both have one at the same time keep the one at `into`.
+        self.db_conn.execute(
+            "UPDATE OR IGNORE surface_obs SET station_num = ?2 WHERE station_num = ?1",
+            [from_num, into_num],
+        )?;
+        self.db_conn.execute(
+            "
+                DELETE FROM raob_levels
+                WHERE station_num = ?1 AND valid_time IN (
+                    SELECT valid_time FROM raob_levels WHERE station_num = ?2)
+            ",
+            [from_num, into_num],
+        )?;
+        self.db_conn.execute(
+            "UPDATE raob_levels SET station_num = ?2 WHERE station_num = ?1",
+            [from_num, into_num],
+        )?;
+        self.db_conn
+            .execute("DELETE FROM surface_obs WHERE station_num = ?1", [from_num])?;
+
+        // Aliases follow the site, the history derived from the files is rebuilt.
+        self.db_conn.execute(
+            "UPDATE id_history SET station_num = ?2 WHERE station_num = ?1 AND is_alias = 1",
+            [from_num, into_num],
+        )?;
+        self.db_conn.execute(
+            "DELETE FROM id_history WHERE is_alias = 0 AND station_num IN (?1, ?2)",
+            [from_num, into_num],
+        )?;
+        self.db_conn.execute(
+            "
+                INSERT INTO id_history (station_num, id, model, start_time, end_time, is_alias)
+                SELECT station_num, id, model, MIN(init_time), MAX(init_time), 0
+                FROM files
+                WHERE station_num = ?1 AND id IS NOT NULL
+                GROUP BY station_num, id, model
+            ",
+            [into_num],
+        )?;
+
+        // Keep the chain of aliases one link long so they always resolve in one step.
+        self.db_conn.execute(
+            "UPDATE station_num_aliases SET station_num = ?2 WHERE station_num = ?1",
+            [from_num, into_num],
+        )?;
+        self.db_conn.execute(
+            "DELETE FROM station_num_aliases WHERE old_num = ?1",
+            [into_num],
+        )?;
+        self.db_conn.execute(
+            "INSERT OR REPLACE INTO station_num_aliases (old_num, station_num) VALUES (?1, ?2)",
+            [from_num, into_num],
+        )?;
+
+        let merged = merge_site_info(into, from);
+        self.update_site(&merged)?;
+        self.db_conn
+            .execute(include_str!("modify/delete_site.sql"), [from_num])?;
+
+        Ok((merged, duplicates))
+    }
+}
+
+/// Keep the values from `into`, filling in anything missing from `from`.
+fn merge_site_info(into: SiteInfo, from: SiteInfo) -> SiteInfo {
+    SiteInfo {
+        station_num: into.station_num,
+        name: into.name.or(from.name),
+        notes: into.notes.or(from.notes),
+        state: into.state.or(from.state),
+        time_zone: into.time_zone.or(from.time_zone),
+        country: into.country.or(from.country),
+        coords: into.coords.or(from.coords),
+        elevation: into.elevation.or(from.elevation),
+        icao_id: into.icao_id.or(from.icao_id),
+        wmo_id: into.wmo_id.or(from.wmo_id),
+    }
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                          Unit Tests
+--------------------------------------------------------------------------------------------------*/
+#[cfg(test)]
+mod unit {
+    use super::*;
+    use crate::archive::unit::*; // test helpers.
+    use crate::models::Model;
+
+    #[test]
+    fn test_merge_sites() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+        for site in &get_test_sites() {
+            arch.add_site(site).expect("Error adding site.");
+        }
+
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+        let missoula = StationNumber::from(3);
+        let num_gfs = arch.count(kmso, Model::GFS).unwrap();
+
+        let merged = arch.merge_sites(kmso, missoula).expect("Error merging.");
+
+        // The name came from the site merged into, the location from the old one.
+        assert_eq!(merged.station_num, missoula);
+        assert_eq!(merged.name.as_deref(), Some("Missoula"));
+        assert!(merged.coords.is_some());
+        assert_eq!(arch.site(missoula), Some(merged.clone()));
+
+        // The old number still works for lookups.
+        assert_eq!(arch.resolve_station_num(kmso).unwrap(), missoula);
+        assert_eq!(arch.site(kmso), Some(merged));
+        assert_eq!(arch.count(kmso, Model::GFS).unwrap(), num_gfs);
+        assert_eq!(arch.count(missoula, Model::GFS).unwrap(), num_gfs);
+        assert_eq!(
+            arch.station_num_for_id("KMSO", Model::GFS).unwrap(),
+            missoula
+        );
+        assert_eq!(
+            arch.most_recent_id(missoula, Model::NAM)
+                .unwrap()
+                .as_deref(),
+            Some("KMSO")
+        );
+        assert!(
+            arch.sites()
+                .unwrap()
+                .iter()
+                .all(|site| site.station_num != kmso)
+        );
+
+        // Merging into yourself or a missing site are errors.
+        assert!(arch.merge_sites(missoula, missoula).is_err());
+        assert!(arch.merge_sites(kmso, StationNumber::from(2)).is_err());
+    }
+
+    #[test]
+    fn test_merge_sites_with_duplicate_files() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+        let other = StationNumber::from(727731);
+        arch.renumber_site(kmso, other).expect("Error renumbering.");
+
+        // The provider switched back to the original number, so there are two sites again.
+        let (_, model, raw_data) = &get_test_data()[0];
+        let text = raw_data.replace("STNM = 727730", "STNM = 727732");
+        let text = text.replace("KMSO", "KMSX");
+        let dup = arch
+            .add("KMSX", None, None, *model, &text)
+            .expect("Error adding file.");
+        assert_eq!(arch.count(dup, *model).unwrap(), 1);
+
+        let init_time = arch.inventory(dup, *model).unwrap()[0];
+        let num_files = arch.count(other, *model).unwrap();
+        arch.merge_sites(dup, other).expect("Error merging.");
+
+        assert_eq!(arch.count(other, *model).unwrap(), num_files);
+        assert!(
+            arch.retrieve(other, *model, init_time)
+                .unwrap()
+                .contains("KMSO")
+        );
+        assert!(
+            !arch
+                .data_root()
+                .join(format!(
+                    "{}_{}_KMSX.buf.gz",
+                    init_time.format("%Y%m%d%HZ"),
+                    model.as_static_str()
+                ))
+                .exists()
+        );
+    }
+
+    #[test]
+    fn test_renumber_site() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+        let new = StationNumber::from(727731);
+        let before = arch.site(kmso).unwrap();
+
+        let renumbered = arch.renumber_site(kmso, new).expect("Error renumbering.");
+        assert_eq!(
+            renumbered,
+            SiteInfo {
+                station_num: new,
+                ..before
+            }
+        );
+        assert_eq!(arch.site(new), Some(renumbered));
+        assert_eq!(arch.models(new).unwrap().len(), 2);
+        assert_eq!(arch.id_history(new).unwrap().len(), 2);
+
+        // Files added with the old number go to the new one.
+        let (site, model, raw_data) = &get_test_data()[0];
+        arch.remove(new, *model, arch.inventory(new, *model).unwrap()[0])
+            .expect("Error removing file.");
+        assert_eq!(
+            arch.add(site, Some(kmso), None, *model, raw_data).unwrap(),
+            new
+        );
+
+        // Renumbering back undoes the alias.
+        assert!(arch.renumber_site(new, kmso).is_ok());
+        assert_eq!(arch.resolve_station_num(new).unwrap(), kmso);
+        assert_eq!(arch.resolve_station_num(kmso).unwrap(), kmso);
+
+        // Can't renumber onto an existing site.
+        arch.add_site(&get_test_sites()[0]).unwrap();
+        assert!(arch.renumber_site(kmso, StationNumber::from(1)).is_err());
+    }
+}
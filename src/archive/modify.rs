@@ -1,7 +1,10 @@
 use metfor::Quantity;
+use rusqlite::OptionalExtension;
 use std::io::Write;
 
 use crate::{
+    boundaries::{self, Location},
+    coords::Coords,
     errors::BufkitDataErr,
     models::Model,
     site::{SiteInfo, StationNumber},
@@ -9,6 +12,9 @@ use crate::{
 
 impl crate::Archive {
     /// Add a bufkit file to the archive.
+    ///
+    /// If this is the first file for a station, a new site is created with the state/providence,
+    /// country, and time zone inferred from the location in the file.
     pub fn add(
         &self,
         site_id_hint: &str,
@@ -28,6 +34,12 @@ impl crate::Archive {
             elevation,
         } = Self::parse_site_info(text_data)?;
 
+        // Station numbers that were merged or renumbered go to the site they were moved to.
+        let parsed_station_num = self.resolve_station_num(parsed_station_num)?;
+        let stn_num_hint = stn_num_hint
+            .map(|stn_num| self.resolve_station_num(stn_num))
+            .transpose()?;
+
         if let Some(init_time_hint) = init_time_hint {
             if init_time_hint != init_time {
                 return Err(BufkitDataErr::MismatchedInitializationTimes {
@@ -53,45 +65,61 @@ impl crate::Archive {
 
         // This is a new station!
         if self.site(parsed_station_num).is_none() {
-            self.add_site(&SiteInfo {
-                station_num: parsed_station_num,
-                ..SiteInfo::default()
-            })?;
+            self.add_site(&Self::new_site_at(parsed_station_num, coords))?;
         }
+        self.fill_site_from_file(
+            parsed_station_num,
+            parsed_site_id.as_deref(),
+            coords,
+            elevation,
+        )?;
 
         let file_name = self.compressed_file_name(site_id, model, init_time);
         let site_id = Some(site_id);
 
-        let added_station_num = match std::fs::File::create(self.data_root().join(&file_name))
-            .map_err(BufkitDataErr::IO)
-            .and_then(|file| {
-                let mut encoder =
-                    flate2::write::GzEncoder::new(file, flate2::Compression::default());
-                encoder
-                    .write_all(text_data.as_bytes())
-                    .map_err(BufkitDataErr::IO)
-            })
-            .and_then(|_| {
-                self.db_conn
-                    .execute(
-                        include_str!("modify/add_file.sql"),
-                        &[
-                            &Into::<u32>::into(parsed_station_num) as &dyn rusqlite::types::ToSql,
-                            &model.as_static_str() as &dyn rusqlite::types::ToSql,
-                            &init_time as &dyn rusqlite::types::ToSql,
-                            &end_time,
-                            &file_name,
-                            &site_id,
-                            &coords.lat,
-                            &coords.lon,
-                            &elevation.unpack(),
-                        ],
-                    )
-                    .map_err(BufkitDataErr::Database)
-            }) {
-            Ok(_) => parsed_station_num,
-            Err(err) => return Err(err),
-        };
+        let derived_soundings = self.parse_for_index(&file_name, text_data)?;
+
+        let file = std::fs::File::create(self.data_root().join(&file_name))?;
+        let mut encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        encoder.write_all(text_data.as_bytes())?;
+        encoder.finish()?;
+
+        let tx = self.db_conn.unchecked_transaction()?;
+        // Replacing the row of a file already in the index doesn't fire the delete triggers, so
+        // clear what was stored for the old contents here.
+        self.db_conn.execute(
+            "DELETE FROM derived_params WHERE file_name = ?1",
+            [&file_name],
+        )?;
+        self.db_conn.execute(
+            "DELETE FROM analog_features WHERE file_name = ?1",
+            [&file_name],
+        )?;
+        self.db_conn.execute(
+            include_str!("modify/add_file.sql"),
+            &[
+                &Into::<u32>::into(parsed_station_num) as &dyn rusqlite::types::ToSql,
+                &model.as_static_str() as &dyn rusqlite::types::ToSql,
+                &init_time as &dyn rusqlite::types::ToSql,
+                &end_time,
+                &file_name,
+                &site_id,
+                &coords.lat,
+                &coords.lon,
+                &elevation.unpack(),
+            ],
+        )?;
+        if let Some(soundings) = derived_soundings {
+            self.write_derived_params(&file_name, init_time, &soundings, self.derived_indexing)?;
+        }
+        tx.commit()?;
+
+        // A file with the same name may have been replaced.
+        self.sounding_cache().remove(&file_name);
+
+        if let Some(site_id) = site_id {
+            self.record_file_id(parsed_station_num, site_id, model, init_time)?;
+        }
 
         if let Some(parsed_id) = parsed_site_id {
             if parsed_id != site_id_hint {
@@ -111,7 +139,7 @@ impl crate::Archive {
             }
         }
 
-        Ok(added_station_num)
+        Ok(parsed_station_num)
     }
 
     /// Add a site to the list of sites.
@@ -127,7 +155,16 @@ impl crate::Archive {
                 &site.state.map(|state_prov| state_prov.as_static_str())
                     as &dyn rusqlite::types::ToSql,
                 &site.notes,
-                &site.time_zone.map(|tz| tz.local_minus_utc()),
+                &site
+                    .time_zone
+                    .map(|tz| tz.standard_offset().local_minus_utc()),
+                &site.coords.map(|coords| coords.lat),
+                &site.coords.map(|coords| coords.lon),
+                &site.elevation.map(|elev| elev.unpack()),
+                &site.icao_id,
+                &site.wmo_id,
+                &site.time_zone.and_then(|tz| tz.iana_name()),
+                &site.country.map(|country| country.as_static_str()),
             ],
         )?;
 
@@ -145,13 +182,128 @@ impl crate::Archive {
                         as &dyn rusqlite::types::ToSql,
                     &site.name,
                     &site.notes,
-                    &site.time_zone.map(|tz| tz.local_minus_utc()),
+                    &site
+                        .time_zone
+                        .map(|tz| tz.standard_offset().local_minus_utc()),
+                    &site.coords.map(|coords| coords.lat),
+                    &site.coords.map(|coords| coords.lon),
+                    &site.elevation.map(|elev| elev.unpack()),
+                    &site.icao_id,
+                    &site.wmo_id,
+                    &site.time_zone.and_then(|tz| tz.iana_name()),
+                    &site.country.map(|country| country.as_static_str()),
                 ],
             )
             .map_err(|err| err.into())
             .map(|_| {})
     }
 
+    /// Fill in missing metadata for all the sites in the archive.
+    ///
+    /// Sites without a location, elevation, or ICAO id get them from their most recent file. Then
+    /// any missing state/providence, country, or time zone is inferred from the location. Values
+    /// that are already set are never changed. Returns the sites that were updated.
+    pub fn fill_missing_site_metadata(&self) -> Result<Vec<SiteInfo>, BufkitDataErr> {
+        let mut most_recent_stmt = self.db_conn.prepare(
+            "
+                SELECT id, lat, lon, elevation_m
+                FROM files
+                WHERE station_num = ?1
+                ORDER BY init_time DESC
+                LIMIT 1
+            ",
+        )?;
+
+        let mut updated = vec![];
+        for site in self.sites()? {
+            let mut filled = site.clone();
+
+            if filled.coords.is_none() || filled.elevation.is_none() || filled.icao_id.is_none() {
+                let most_recent = most_recent_stmt
+                    .query_row([Into::<u32>::into(site.station_num)], |row| {
+                        Ok((
+                            row.get::<_, Option<String>>(0)?,
+                            Coords {
+                                lat: row.get(1)?,
+                                lon: row.get(2)?,
+                            },
+                            metfor::Meters(row.get(3)?),
+                        ))
+                    })
+                    .optional()?;
+
+                if let Some((id, coords, elevation)) = most_recent {
+                    filled.coords = filled.coords.or(Some(coords));
+                    filled.elevation = filled.elevation.or(Some(elevation));
+                    filled.icao_id = filled
+                        .icao_id
+                        .or_else(|| icao_like(id.as_deref()).map(ToOwned::to_owned));
+                }
+            }
+
+            if let Some(coords) = filled.coords {
+                let Location {
+                    state,
+                    country,
+                    time_zone,
+                } = boundaries::locate(coords);
+
+                filled.state = filled.state.or(state);
+                filled.country = filled.country.or(country);
+                filled.time_zone = filled.time_zone.or(time_zone);
+            }
+
+            if filled != site {
+                self.update_site(&filled)?;
+                updated.push(filled);
+            }
+        }
+
+        Ok(updated)
+    }
+
+    /// Create the information for a new site, inferring what is possible from its location.
+    pub(crate) fn new_site_at(station_num: StationNumber, coords: Coords) -> SiteInfo {
+        let Location {
+            state,
+            country,
+            time_zone,
+        } = boundaries::locate(coords);
+
+        SiteInfo {
+            station_num,
+            state,
+            country,
+            time_zone,
+            ..SiteInfo::default()
+        }
+    }
+
+    /// Fill in any missing canonical location and id information for a site with the values
+    /// parsed from a file. Values that are already set, e.g. by `update_site`, are left alone.
+    pub(crate) fn fill_site_from_file(
+        &self,
+        station_num: StationNumber,
+        id: Option<&str>,
+        coords: Coords,
+        elevation: metfor::Meters,
+    ) -> Result<(), BufkitDataErr> {
+        let icao_id = icao_like(id);
+
+        self.db_conn.execute(
+            include_str!("modify/fill_site_from_file.sql"),
+            [
+                &Into::<u32>::into(station_num) as &dyn rusqlite::ToSql,
+                &coords.lat,
+                &coords.lon,
+                &elevation.unpack(),
+                &icao_id,
+            ],
+        )?;
+
+        Ok(())
+    }
+
     /// Remove a file from the archive.
     pub fn remove(
         &self,
@@ -171,8 +323,10 @@ impl crate::Archive {
             |row| row.get(0),
         )?;
 
-        std::fs::remove_file(self.data_root().join(file_name)).map_err(BufkitDataErr::IO)?;
+        std::fs::remove_file(self.data_root().join(&file_name)).map_err(BufkitDataErr::IO)?;
+        self.sounding_cache().remove(&file_name);
 
+        let tx = self.db_conn.unchecked_transaction()?;
         self.db_conn.execute(
             include_str!("modify/delete_file_from_index.sql"),
             &[
@@ -181,6 +335,8 @@ impl crate::Archive {
                 &init_time as &dyn rusqlite::types::ToSql,
             ],
         )?;
+        self.refresh_file_ids(StationNumber::from(station_num), model)?;
+        tx.commit()?;
 
         Ok(())
     }
@@ -201,6 +357,7 @@ impl crate::Archive {
             .map(|res: Result<String, rusqlite::Error>| res.map_err(BufkitDataErr::Database))
             .map(|res| {
                 res.and_then(|fname| {
+                    self.sounding_cache().remove(&fname);
                     std::fs::remove_file(self.data_root().join(&fname))
                         .map_err(BufkitDataErr::IO)
                         .map(|_| fname)
@@ -217,6 +374,41 @@ impl crate::Archive {
             .collect();
         file_deletion_results?;
 
+        let mut member_stmt = self
+            .db_conn
+            .prepare("SELECT file_name FROM member_files WHERE station_num = ?1")?;
+        let member_files: Vec<String> = member_stmt
+            .query_map([station_num], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        for fname in &member_files {
+            self.sounding_cache().remove(fname);
+            std::fs::remove_file(self.data_root().join(fname))?;
+        }
+        self.db_conn.execute(
+            "DELETE FROM member_files WHERE station_num = ?1",
+            [station_num],
+        )?;
+
+        self.db_conn.execute(
+            "DELETE FROM id_history WHERE station_num = ?1",
+            [station_num],
+        )?;
+        self.db_conn.execute(
+            "DELETE FROM station_num_aliases WHERE station_num = ?1",
+            [station_num],
+        )?;
+        self.db_conn.execute(
+            "DELETE FROM climatology WHERE station_num = ?1",
+            [station_num],
+        )?;
+        self.db_conn.execute(
+            "DELETE FROM surface_obs WHERE station_num = ?1",
+            [station_num],
+        )?;
+        self.db_conn.execute(
+            "DELETE FROM raob_levels WHERE station_num = ?1",
+            [station_num],
+        )?;
         self.db_conn
             .execute(include_str!("modify/delete_site.sql"), &[&station_num])?;
 
@@ -240,6 +432,11 @@ impl crate::Archive {
     }
 }
 
+/// Only ids that look like ICAO identifiers are used as the preferred id, others are model specific.
+fn icao_like(id: Option<&str>) -> Option<&str> {
+    id.filter(|id| id.len() == 4 && id.chars().all(|c| c.is_ascii_alphabetic()))
+}
+
 #[cfg(test)]
 mod unit {
     use super::*;
@@ -282,7 +479,15 @@ mod unit {
             name: Some("Zootown".to_owned()),
             notes: Some("Mountains, not coast.".to_owned()),
             state: Some(crate::StateProv::MT),
-            time_zone: Some(chrono::FixedOffset::west_opt(7 * 3600).unwrap()),
+            time_zone: Some(chrono_tz::America::Denver.into()),
+            coords: Some(crate::Coords {
+                lat: 46.92,
+                lon: -114.09,
+            }),
+            elevation: Some(metfor::Meters(972.0)),
+            icao_id: Some("KMSO".to_owned()),
+            wmo_id: Some("72773".to_owned()),
+            country: Some(crate::Country::US),
         };
 
         arch.update_site(&zootown).expect("Error updating site.");
@@ -301,6 +506,79 @@ mod unit {
         fill_test_archive(&mut arch);
     }
 
+    #[test]
+    fn test_add_fills_site_metadata() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+        let site = arch.site(kmso).expect("Site not created.");
+        let coords = site.coords.expect("Coordinates not filled.");
+        assert!((coords.lat - 46.92).abs() < 0.1);
+        assert!((coords.lon + 114.09).abs() < 0.1);
+        assert!(site.elevation.is_some());
+        assert_eq!(site.icao_id.as_deref(), Some("KMSO"));
+        assert!(site.wmo_id.is_none());
+        assert_eq!(site.state, Some(crate::StateProv::MT));
+        assert_eq!(site.country, Some(crate::Country::US));
+        assert_eq!(site.time_zone, Some(chrono_tz::America::Denver.into()));
+
+        // Edits are kept when more files are added.
+        let edited = SiteInfo {
+            coords: Some(crate::Coords {
+                lat: 47.0,
+                lon: -114.0,
+            }),
+            wmo_id: Some("72773".to_owned()),
+            ..site
+        };
+        arch.update_site(&edited).expect("Error updating site.");
+        fill_test_archive(&mut arch);
+        assert_eq!(arch.site(kmso).unwrap(), edited);
+    }
+
+    #[test]
+    fn test_fill_missing_site_metadata() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+
+        // Make it look like a site from an older archive, with a hand edited name and state.
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+        let old_site = SiteInfo {
+            station_num: kmso,
+            name: Some("Missoula".to_owned()),
+            state: Some(crate::StateProv::ID),
+            ..SiteInfo::default()
+        };
+        arch.update_site(&old_site).expect("Error updating site.");
+
+        let updated = arch
+            .fill_missing_site_metadata()
+            .expect("Error filling metadata.");
+        assert_eq!(updated.len(), 1);
+
+        let site = arch.site(kmso).unwrap();
+        assert_eq!(site, updated[0]);
+        assert_eq!(site.name.as_deref(), Some("Missoula"));
+        assert_eq!(site.state, Some(crate::StateProv::ID));
+        assert_eq!(site.country, Some(crate::Country::US));
+        assert_eq!(site.time_zone, Some(chrono_tz::America::Denver.into()));
+        assert_eq!(site.icao_id.as_deref(), Some("KMSO"));
+        assert!(site.coords.is_some());
+        assert!(site.elevation.is_some());
+
+        // Nothing left to fill.
+        assert!(arch.fill_missing_site_metadata().unwrap().is_empty());
+    }
+
     #[test]
     fn test_remove_file() {
         let TestArchive {
@@ -324,6 +602,44 @@ mod unit {
             .expect("Error checking db"));
     }
 
+    #[test]
+    fn test_remove_updates_id_history() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+
+        let site = StationNumber::from(727730); // Station number for KMSO
+        let model = Model::GFS;
+        let init_times = arch
+            .inventory(site, model)
+            .expect("Error getting inventory.");
+        assert_eq!(init_times.len(), 3);
+
+        arch.remove(site, model, init_times[2])
+            .expect("Error while removing.");
+        let gfs = arch
+            .id_history(site)
+            .unwrap()
+            .into_iter()
+            .find(|entry| entry.model == Some(model))
+            .unwrap();
+        assert_eq!(gfs.end, Some(init_times[1]));
+
+        for &init_time in &init_times[..2] {
+            arch.remove(site, model, init_time)
+                .expect("Error while removing.");
+        }
+        assert!(arch.ids(site, model).unwrap().is_empty());
+        assert!(arch.station_num_for_id("KMSO", model).is_err());
+
+        // The other model still has the id.
+        assert_eq!(arch.ids(site, Model::NAM).unwrap(), vec!["KMSO".to_owned()]);
+        assert_eq!(arch.station_num_for_id("KMSO", Model::NAM).unwrap(), site);
+    }
+
     #[test]
     fn test_remove_site() {
         let TestArchive {
@@ -4,6 +4,13 @@ INSERT INTO sites
     name,
     state,
     notes,
-    tz_offset_sec
+    tz_offset_sec,
+    lat,
+    lon,
+    elevation_m,
+    icao_id,
+    wmo_id,
+    tz_name,
+    country
 )
-VALUES (?1, ?2, ?3, ?4, ?5)
+VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
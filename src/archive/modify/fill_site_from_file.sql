@@ -0,0 +1,13 @@
+UPDATE sites
+SET (
+    lat,
+    lon,
+    elevation_m,
+    icao_id
+) = (
+    COALESCE(lat, ?2),
+    COALESCE(lon, ?3),
+    COALESCE(elevation_m, ?4),
+    COALESCE(icao_id, ?5)
+)
+WHERE station_num = ?1
@@ -3,6 +3,13 @@ SET (
     state,
     name,
     notes,
-    tz_offset_sec
-) = (?2, ?3, ?4, ?5)
+    tz_offset_sec,
+    lat,
+    lon,
+    elevation_m,
+    icao_id,
+    wmo_id,
+    tz_name,
+    country
+) = (?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
 WHERE station_num = ?1
@@ -0,0 +1,653 @@
+//! Store observations and verify the archived forecasts against them.
+
+use crate::{
+    archive::{Archive, ClimoParam, StitchRule},
+    errors::BufkitDataErr,
+    models::Model,
+    site::StationNumber,
+};
+use chrono::{Datelike, NaiveDateTime};
+use metfor::{Celsius, HectoPascal, Knots, Meters, WindSpdDir};
+use serde::Deserialize;
+use sounding_analysis::{Sounding, StationInfo};
+use std::{collections::BTreeMap, collections::HashMap, io::Read};
+
+/// The time format used in observation files.
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+/// Three month seasons for grouping verification statistics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Season {
+    /// December, January, and February.
+    DecJanFeb,
+    /// March, April, and May.
+    MarAprMay,
+    /// June, July, and August.
+    JunJulAug,
+    /// September, October, and November.
+    SepOctNov,
+}
+
+impl Season {
+    /// Get the season a time is in.
+    pub fn of(time: NaiveDateTime) -> Season {
+        match time.month() {
+            3..=5 => Season::MarAprMay,
+            6..=8 => Season::JunJulAug,
+            9..=11 => Season::SepOctNov,
+            _ => Season::DecJanFeb,
+        }
+    }
+}
+
+/// A parameter that can be verified against observations.
+#[derive(Clone, Debug, PartialEq)]
+pub enum VerifyParam {
+    /// A surface parameter, named as in the bufkit surface section, e.g. T2MS. Verified against
+    /// the surface observations.
+    Surface(String),
+    /// A parameter computed from the soundings. Verified against the observed soundings.
+    Sounding(ClimoParam),
+}
+
+/// The forecast errors for one model, season, and lead time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VerificationStats {
+    /// The model.
+    pub model: Model,
+    /// The season of the valid times.
+    pub season: Season,
+    /// The hours from the initialization time to the valid time.
+    pub lead_hours: i32,
+    /// The number of forecast and observation pairs.
+    pub num_samples: usize,
+    /// The mean of forecast minus observed.
+    pub bias: f64,
+    /// The mean absolute error.
+    pub mae: f64,
+    /// The root mean square error.
+    pub rmse: f64,
+}
+
+/// A level of an observed sounding as it appears in a file.
+#[derive(Debug, Deserialize)]
+struct RaobLevel {
+    station_num: u32,
+    valid_time: String,
+    pressure_hpa: f64,
+    height_m: Option<f64>,
+    temperature_c: Option<f64>,
+    dew_point_c: Option<f64>,
+    wind_dir_deg: Option<f64>,
+    wind_speed_kt: Option<f64>,
+}
+
+impl Archive {
+    /// Import surface observations from a CSV file and return the number of rows imported.
+    ///
+    /// The first two columns are `station_num` and `valid_time`, formatted like
+    /// `2017-04-01 12:00`. Every other column is a parameter named like in the bufkit surface
+    /// section, e.g. T2MS or P01M, ignoring case. Empty values are skipped, and values already in
+    /// the archive are replaced. Observations for merged or renumbered sites are stored with the
+    /// site's current station number.
+    pub fn import_surface_obs(&self, reader: impl Read) -> Result<usize, BufkitDataErr> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+
+        let params: Vec<String> = rdr
+            .headers()?
+            .iter()
+            .skip(2)
+            .map(str::to_uppercase)
+            .collect();
+
+        let tx = self.db_conn.unchecked_transaction()?;
+        let mut stmt = self.db_conn.prepare(
+            "
+                INSERT OR REPLACE INTO surface_obs (station_num, valid_time, param, value)
+                VALUES (?1, ?2, ?3, ?4)
+            ",
+        )?;
+
+        let mut num_rows = 0;
+        for row in rdr.records() {
+            let row = row?;
+            let station_num = parse_station_num(row.get(0).unwrap_or_default())?;
+            let station_num: u32 = self.resolve_station_num(station_num.into())?.into();
+            let valid_time = parse_time(row.get(1).unwrap_or_default())?;
+
+            for (param, val) in params.iter().zip(row.iter().skip(2)) {
+                if val.is_empty() {
+                    continue;
+                }
+
+                let value: f64 = val.parse().map_err(|_| {
+                    BufkitDataErr::GeneralError(format!("invalid value for {}: {}", param, val))
+                })?;
+
+                stmt.execute(rusqlite::params![station_num, valid_time, param, value])?;
+            }
+
+            num_rows += 1;
+        }
+
+        drop(stmt);
+        tx.commit()?;
+
+        Ok(num_rows)
+    }
+
+    /// Import observed soundings from a CSV file and return the number of soundings imported.
+    ///
+    /// Each row is one level with the columns `station_num`, `valid_time`, `pressure_hpa`,
+    /// `height_m`, `temperature_c`, `dew_point_c`, `wind_dir_deg`, and `wind_speed_kt`. The first
+    /// level of each sounding is the surface. The station number, valid time, and pressure are
+    /// required, every other column may be empty. A sounding already in the archive for the same
+    /// station and time is replaced. Like `import_surface_obs`, soundings for merged or renumbered
+    /// sites are stored with the site's current station number.
+    pub fn import_raobs(&self, reader: impl Read) -> Result<usize, BufkitDataErr> {
+        let levels: Vec<RaobLevel> = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(reader)
+            .deserialize()
+            .collect::<Result<_, _>>()?;
+
+        let mut soundings: BTreeMap<(u32, NaiveDateTime), Vec<RaobLevel>> = BTreeMap::new();
+        for level in levels {
+            let station_num: u32 = self.resolve_station_num(level.station_num.into())?.into();
+            let key = (station_num, parse_time(&level.valid_time)?);
+            soundings.entry(key).or_default().push(level);
+        }
+
+        let tx = self.db_conn.unchecked_transaction()?;
+        let mut del_stmt = self
+            .db_conn
+            .prepare("DELETE FROM raob_levels WHERE station_num = ?1 AND valid_time = ?2")?;
+        let mut ins_stmt = self.db_conn.prepare(
+            "
+                INSERT OR REPLACE INTO raob_levels (
+                    station_num, valid_time, pressure, height, temperature, dew_point, wind_dir,
+                    wind_speed)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            ",
+        )?;
+
+        for ((station_num, valid_time), lvls) in &soundings {
+            del_stmt.execute(rusqlite::params![station_num, valid_time])?;
+
+            for lvl in lvls {
+                ins_stmt.execute(rusqlite::params![
+                    station_num,
+                    valid_time,
+                    lvl.pressure_hpa,
+                    lvl.height_m,
+                    lvl.temperature_c,
+                    lvl.dew_point_c,
+                    lvl.wind_dir_deg,
+                    lvl.wind_speed_kt,
+                ])?;
+            }
+        }
+
+        drop((del_stmt, ins_stmt));
+        tx.commit()?;
+
+        Ok(soundings.len())
+    }
+
+    /// Get the observed values of a surface parameter between `start` and `end`, inclusive,
+    /// sorted by valid time.
+    pub fn surface_obs(
+        &self,
+        station_num: StationNumber,
+        param: &str,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+    ) -> Result<Vec<(NaiveDateTime, f64)>, BufkitDataErr> {
+        let station_num = self.resolve_station_num(station_num)?;
+
+        let mut stmt = self.db_conn.prepare(
+            "
+                SELECT valid_time, value FROM surface_obs
+                WHERE station_num = ?1 AND param = ?2 AND valid_time >= ?3 AND valid_time <= ?4
+                ORDER BY valid_time
+            ",
+        )?;
+
+        let station_num: u32 = station_num.into();
+        let obs = stmt
+            .query_map(
+                rusqlite::params![station_num, param.to_uppercase(), start, end],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?
+            .collect::<Result<_, _>>()?;
+
+        Ok(obs)
+    }
+
+    /// Get the valid times of the observed soundings between `start` and `end`, inclusive.
+    pub fn raob_times(
+        &self,
+        station_num: StationNumber,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+    ) -> Result<Vec<NaiveDateTime>, BufkitDataErr> {
+        let station_num = self.resolve_station_num(station_num)?;
+
+        let mut stmt = self.db_conn.prepare(
+            "
+                SELECT DISTINCT valid_time FROM raob_levels
+                WHERE station_num = ?1 AND valid_time >= ?2 AND valid_time <= ?3
+                ORDER BY valid_time
+            ",
+        )?;
+
+        let station_num: u32 = station_num.into();
+        let times = stmt
+            .query_map(rusqlite::params![station_num, start, end], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+
+        Ok(times)
+    }
+
+    /// Get an observed sounding.
+    pub fn observed_sounding(
+        &self,
+        station_num: StationNumber,
+        valid_time: NaiveDateTime,
+    ) -> Result<Sounding, BufkitDataErr> {
+        let station_num = self.resolve_station_num(station_num)?;
+
+        let mut stmt = self.db_conn.prepare(
+            "
+                SELECT pressure, height, temperature, dew_point, wind_dir, wind_speed
+                FROM raob_levels
+                WHERE station_num = ?1 AND valid_time = ?2
+                ORDER BY pressure DESC
+            ",
+        )?;
+
+        let station: u32 = station_num.into();
+        type Row = (
+            f64,
+            Option<f64>,
+            Option<f64>,
+            Option<f64>,
+            Option<f64>,
+            Option<f64>,
+        );
+        let levels: Vec<Row> = stmt
+            .query_map(rusqlite::params![station, valid_time], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                ))
+            })?
+            .collect::<Result<_, _>>()?;
+
+        let (sfc, upper) = levels.split_first().ok_or(BufkitDataErr::NotInIndex)?;
+        let wind = |(_, _, _, _, dir, spd): &Row| match (dir, spd) {
+            (Some(direction), Some(speed)) => Some(WindSpdDir {
+                speed: Knots(*speed),
+                direction: *direction,
+            }),
+            _ => None,
+        };
+
+        let station_info = StationInfo::new()
+            .with_station(Into::<u32>::into(station_num) as i32)
+            .with_elevation(sfc.1.map(Meters));
+
+        // The profile setters put the surface values set here at the bottom of the profiles.
+        let snd = Sounding::new()
+            .with_station_info(station_info)
+            .with_valid_time(valid_time)
+            .with_lead_time(0)
+            .with_station_pressure(HectoPascal(sfc.0))
+            .with_sfc_temperature(sfc.2.map(Celsius))
+            .with_sfc_dew_point(sfc.3.map(Celsius))
+            .with_sfc_wind(wind(sfc))
+            .with_pressure_profile(
+                upper
+                    .iter()
+                    .map(|l| Some(HectoPascal(l.0)).into())
+                    .collect(),
+            )
+            .with_height_profile(upper.iter().map(|l| l.1.map(Meters).into()).collect())
+            .with_temperature_profile(upper.iter().map(|l| l.2.map(Celsius).into()).collect())
+            .with_dew_point_profile(upper.iter().map(|l| l.3.map(Celsius).into()).collect())
+            .with_wind_profile(upper.iter().map(|l| wind(l).into()).collect());
+
+        Ok(snd)
+    }
+
+    /// Verify the forecasts of several models against the observations valid between `start`
+    /// and `end`, inclusive.
+    ///
+    /// Every run with a forecast at an observed time is used, and the errors are grouped by model,
+    /// season, and lead time. The results are sorted in that order.
+    pub fn verify(
+        &self,
+        station_num: StationNumber,
+        models: &[Model],
+        param: &VerifyParam,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+    ) -> Result<Vec<VerificationStats>, BufkitDataErr> {
+        let mut sums: BTreeMap<(Model, Season, i32), ErrorSums> = BTreeMap::new();
+        let mut add = |model, valid_time, lead_hours, error: f64| {
+            let sum = sums
+                .entry((model, Season::of(valid_time), lead_hours))
+                .or_default();
+            sum.count += 1;
+            sum.total += error;
+            sum.total_abs += error.abs();
+            sum.total_sq += error * error;
+        };
+
+        match param {
+            VerifyParam::Surface(param) => {
+                let obs: HashMap<NaiveDateTime, f64> = self
+                    .surface_obs(station_num, param, start, end)?
+                    .into_iter()
+                    .collect();
+
+                for &model in models {
+                    let series = self.param_time_series(
+                        station_num,
+                        model,
+                        param,
+                        start,
+                        end,
+                        StitchRule::AllRuns,
+                    )?;
+
+                    for pnt in series {
+                        if let Some(ob) = obs.get(&pnt.valid_time) {
+                            add(model, pnt.valid_time, pnt.lead_hours, pnt.value - ob);
+                        }
+                    }
+                }
+            }
+            VerifyParam::Sounding(param) => {
+                for valid_time in self.raob_times(station_num, start, end)? {
+                    let ob = match param.value(&self.observed_sounding(station_num, valid_time)?) {
+                        Some(ob) => ob,
+                        None => continue,
+                    };
+
+                    for &model in models {
+                        let fcsts = match self.soundings_valid_at(station_num, model, valid_time) {
+                            Ok(fcsts) => fcsts,
+                            Err(BufkitDataErr::NotInIndex) => continue,
+                            Err(err) => return Err(err),
+                        };
+
+                        for fcst in fcsts {
+                            if let Some(val) = param.value(&fcst.sounding) {
+                                let lead_hours = fcst.lead_time.num_hours() as i32;
+                                add(model, valid_time, lead_hours, val - ob);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(sums
+            .into_iter()
+            .map(|((model, season, lead_hours), sum)| {
+                let count = sum.count as f64;
+                VerificationStats {
+                    model,
+                    season,
+                    lead_hours,
+                    num_samples: sum.count,
+                    bias: sum.total / count,
+                    mae: sum.total_abs / count,
+                    rmse: (sum.total_sq / count).sqrt(),
+                }
+            })
+            .collect())
+    }
+}
+
+/// Running sums of the forecast errors for one group.
+#[derive(Default)]
+struct ErrorSums {
+    count: usize,
+    total: f64,
+    total_abs: f64,
+    total_sq: f64,
+}
+
+fn parse_station_num(val: &str) -> Result<u32, BufkitDataErr> {
+    val.parse()
+        .map_err(|_| BufkitDataErr::GeneralError(format!("invalid station number: {}", val)))
+}
+
+fn parse_time(val: &str) -> Result<NaiveDateTime, BufkitDataErr> {
+    NaiveDateTime::parse_from_str(val, TIME_FORMAT)
+        .map_err(|_| BufkitDataErr::GeneralError(format!("invalid time: {}", val)))
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                          Unit Tests
+--------------------------------------------------------------------------------------------------*/
+#[cfg(test)]
+mod unit {
+    use super::*;
+    use crate::archive::unit::*; // test helpers.
+
+    use chrono::NaiveDate;
+
+    fn time(day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2017, 4, day)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_season() {
+        assert_eq!(Season::of(time(1, 0)), Season::MarAprMay);
+        let jan = NaiveDate::from_ymd_opt(2018, 1, 15)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        assert_eq!(Season::of(jan), Season::DecJanFeb);
+    }
+
+    #[test]
+    fn test_verify_surface() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+
+        // Observations exactly 1 degree warmer than the GFS 12Z run.
+        let fcst = arch
+            .param_time_series(
+                kmso,
+                Model::GFS,
+                "T2MS",
+                time(2, 0),
+                time(2, 6),
+                StitchRule::LeadTime(12),
+            )
+            .unwrap();
+        assert!(!fcst.is_empty());
+
+        let mut csv = String::from("station_num,valid_time,t2ms,pmsl\n");
+        for pnt in &fcst {
+            csv.push_str(&format!(
+                "727730,{},{},\n",
+                pnt.valid_time.format(TIME_FORMAT),
+                pnt.value + 1.0
+            ));
+        }
+        assert_eq!(arch.import_surface_obs(csv.as_bytes()).unwrap(), fcst.len());
+        assert_eq!(
+            arch.surface_obs(kmso, "T2MS", time(1, 0), time(3, 0))
+                .unwrap()
+                .len(),
+            fcst.len()
+        );
+        assert!(
+            arch.surface_obs(kmso, "PMSL", time(1, 0), time(3, 0))
+                .unwrap()
+                .is_empty()
+        );
+
+        let param = VerifyParam::Surface("T2MS".to_owned());
+        let stats = arch
+            .verify(
+                kmso,
+                &[Model::GFS, Model::NAM],
+                &param,
+                time(1, 0),
+                time(3, 0),
+            )
+            .unwrap();
+        assert!(stats.iter().any(|st| st.model == Model::NAM));
+        assert!(stats.iter().all(|st| st.season == Season::MarAprMay));
+
+        let gfs12: Vec<_> = stats
+            .iter()
+            .filter(|st| st.model == Model::GFS && st.lead_hours == 12)
+            .collect();
+        assert_eq!(gfs12.len(), 1);
+        assert!(gfs12[0].num_samples >= 1);
+        assert!((gfs12[0].bias + 1.0).abs() < 1.0e-6);
+        assert!((gfs12[0].rmse - 1.0).abs() < 1.0e-6);
+
+        assert!(
+            arch.import_surface_obs("station_num,valid_time,T2MS\n727730,yesterday,1\n".as_bytes())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_obs_follow_renumbered_site() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+        let new_num = StationNumber::from(727731);
+        arch.renumber_site(kmso, new_num).unwrap();
+
+        // Observations imported with the old number are stored with the new one.
+        let sfc = "station_num,valid_time,T2MS\n727730,2017-04-02 00:00,10\n";
+        assert_eq!(arch.import_surface_obs(sfc.as_bytes()).unwrap(), 1);
+
+        let raob = "station_num,valid_time,pressure_hpa,height_m,temperature_c,dew_point_c,\
+                    wind_dir_deg,wind_speed_kt\n\
+                    727730,2017-04-02 00:00,900,1000,10,0,180,10\n\
+                    727730,2017-04-02 00:00,500,5500,-20,-30,270,40\n";
+        assert_eq!(arch.import_raobs(raob.as_bytes()).unwrap(), 1);
+
+        for station_num in [kmso, new_num] {
+            assert_eq!(
+                arch.surface_obs(station_num, "T2MS", time(1, 0), time(3, 0))
+                    .unwrap(),
+                vec![(time(2, 0), 10.0)]
+            );
+            assert_eq!(
+                arch.raob_times(station_num, time(1, 0), time(3, 0))
+                    .unwrap(),
+                vec![time(2, 0)]
+            );
+            assert!(arch.observed_sounding(station_num, time(2, 0)).is_ok());
+        }
+
+        let stored: i64 = arch
+            .db_conn
+            .query_row(
+                "SELECT COUNT(*) FROM surface_obs WHERE station_num = ?1",
+                [Into::<u32>::into(new_num)],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(stored, 1);
+    }
+
+    #[test]
+    fn test_verify_soundings() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+        let valid_time = time(2, 0);
+
+        // Use the GFS forecast as the observed sounding.
+        let fcst = arch
+            .soundings_valid_at(kmso, Model::GFS, valid_time)
+            .unwrap()
+            .remove(0)
+            .sounding;
+
+        let mut csv = String::from(
+            "station_num,valid_time,pressure_hpa,height_m,temperature_c,dew_point_c,wind_dir_deg,wind_speed_kt\n",
+        );
+        let opt = |val: Option<f64>| val.map(|v| v.to_string()).unwrap_or_default();
+        for row in fcst.top_down().collect::<Vec<_>>().into_iter().rev() {
+            let Some(p) = row.pressure.into_option() else {
+                continue;
+            };
+            let wind = row.wind.into_option();
+            csv.push_str(&format!(
+                "727730,{},{},{},{},{},{},{}\n",
+                valid_time.format(TIME_FORMAT),
+                p.0,
+                opt(row.height.into_option().map(|h| h.0)),
+                opt(row.temperature.into_option().map(|t| t.0)),
+                opt(row.dew_point.into_option().map(|t| t.0)),
+                opt(wind.map(|w| w.direction)),
+                opt(wind.map(|w| w.speed.0)),
+            ));
+        }
+        assert_eq!(arch.import_raobs(csv.as_bytes()).unwrap(), 1);
+        // Importing again replaces the sounding.
+        assert_eq!(arch.import_raobs(csv.as_bytes()).unwrap(), 1);
+        assert_eq!(
+            arch.raob_times(kmso, time(1, 0), time(3, 0)).unwrap(),
+            vec![valid_time]
+        );
+
+        let obs = arch.observed_sounding(kmso, valid_time).unwrap();
+        assert_eq!(obs.valid_time(), Some(valid_time));
+        let pw_obs = ClimoParam::Pwat.value(&obs).unwrap();
+        let pw_fcst = ClimoParam::Pwat.value(&fcst).unwrap();
+        assert!((pw_obs - pw_fcst).abs() < 0.5);
+
+        let param = VerifyParam::Sounding(ClimoParam::Pwat);
+        let stats = arch
+            .verify(kmso, &[Model::GFS], &param, time(1, 0), time(3, 0))
+            .unwrap();
+        assert_eq!(stats.len(), 3);
+        assert!(stats.iter().all(|st| st.num_samples == 1));
+        assert!(stats.iter().all(|st| st.rmse >= st.bias.abs()));
+
+        assert!(matches!(
+            arch.observed_sounding(kmso, time(3, 0)),
+            Err(BufkitDataErr::NotInIndex)
+        ));
+    }
+}
@@ -0,0 +1,234 @@
+//! Accumulated forecast precipitation.
+
+use crate::{
+    archive::{Archive, FileQuery, FileRecord, hourly_params::HourlyParams},
+    errors::BufkitDataErr,
+    models::Model,
+    site::StationNumber,
+};
+use chrono::{Duration, NaiveDateTime};
+use serde::Serialize;
+use std::io::Write;
+
+/// The forecast precipitation from one model run accumulated over a window of time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QpfRecord {
+    /// The station the forecast is for.
+    pub station_num: StationNumber,
+    /// The model that made the forecast.
+    pub model: Model,
+    /// The initialization time of the model run.
+    pub init_time: NaiveDateTime,
+    /// The start of the accumulation window.
+    pub start: NaiveDateTime,
+    /// The end of the accumulation window.
+    pub end: NaiveDateTime,
+    /// The total precipitation in the window in millimeters.
+    pub precip_mm: f64,
+    /// The convective precipitation in the window in millimeters, if the model outputs it.
+    pub convective_mm: Option<f64>,
+    /// Whether the model run had precipitation data for the whole window.
+    pub complete: bool,
+}
+
+/// A row of the CSV output.
+#[derive(Serialize)]
+struct QpfRow {
+    station_num: u32,
+    model: &'static str,
+    init_time: String,
+    start: String,
+    end: String,
+    precip_mm: f64,
+    convective_mm: Option<f64>,
+    complete: bool,
+}
+
+impl QpfRecord {
+    /// Write records as CSV with a header row.
+    pub fn write_csv(records: &[QpfRecord], writer: impl Write) -> Result<(), BufkitDataErr> {
+        const TIME_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+        let mut wtr = csv::Writer::from_writer(writer);
+        for rec in records {
+            wtr.serialize(QpfRow {
+                station_num: rec.station_num.into(),
+                model: rec.model.as_static_str(),
+                init_time: rec.init_time.format(TIME_FORMAT).to_string(),
+                start: rec.start.format(TIME_FORMAT).to_string(),
+                end: rec.end.format(TIME_FORMAT).to_string(),
+                precip_mm: rec.precip_mm,
+                convective_mm: rec.convective_mm,
+                complete: rec.complete,
+            })?;
+        }
+        wtr.flush()?;
+
+        Ok(())
+    }
+}
+
+impl Archive {
+    /// Get the precipitation forecast by one model run between `start` and `end`.
+    ///
+    /// Uses the P01M/C01M or P03M/C03M columns of the surface data, whichever the model outputs.
+    /// Only accumulation periods entirely inside the window are counted, check `complete` to see
+    /// if the run covered the whole window.
+    pub fn run_qpf(
+        &self,
+        station_num: StationNumber,
+        model: Model,
+        init_time: NaiveDateTime,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+    ) -> Result<QpfRecord, BufkitDataErr> {
+        let (record, text) = self.retrieve_record(station_num, model, init_time)?;
+        Self::qpf_from_text(&record, &text, start, end)
+    }
+
+    /// Get the precipitation forecast by each run matching a query between `first_hour` and
+    /// `last_hour` after the initialization time, e.g. 0 and 72 for the 72 hour QPF.
+    pub fn runs_qpf(
+        &self,
+        query: &FileQuery,
+        first_hour: i64,
+        last_hour: i64,
+    ) -> Result<Vec<QpfRecord>, BufkitDataErr> {
+        self.retrieve_files(query)?
+            .map(|file| {
+                let (record, text) = file?;
+                let start = record.init_time + Duration::hours(first_hour);
+                let end = record.init_time + Duration::hours(last_hour);
+
+                Self::qpf_from_text(&record, &text, start, end)
+            })
+            .collect()
+    }
+
+    fn qpf_from_text(
+        record: &FileRecord,
+        text: &str,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+    ) -> Result<QpfRecord, BufkitDataErr> {
+        let mut precip_mm = 0.0;
+        let mut convective_mm: Option<f64> = None;
+        let mut covered = Duration::zero();
+
+        for (valid_time, vals) in HourlyParams::parse(text)?.hours {
+            let (period, precip, convective) = match (vals.get("P01M"), vals.get("P03M")) {
+                (Some(&precip), _) => (1, precip, vals.get("C01M")),
+                (None, Some(&precip)) => (3, precip, vals.get("C03M")),
+                (None, None) => continue,
+            };
+
+            let period = Duration::hours(period);
+            if valid_time - period < start || valid_time > end {
+                continue;
+            }
+
+            precip_mm += precip;
+            if let Some(&convective) = convective {
+                *convective_mm.get_or_insert(0.0) += convective;
+            }
+            covered += period;
+        }
+
+        Ok(QpfRecord {
+            station_num: record.station_num,
+            model: record.model,
+            init_time: record.init_time,
+            start,
+            end,
+            precip_mm,
+            convective_mm,
+            complete: covered == end - start,
+        })
+    }
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                          Unit Tests
+--------------------------------------------------------------------------------------------------*/
+#[cfg(test)]
+mod unit {
+    use super::*;
+    use crate::archive::unit::*; // test helpers.
+
+    use chrono::NaiveDate;
+
+    fn time(day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2017, 4, day)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_run_qpf() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+
+        for model in [Model::GFS, Model::NAM] {
+            let init_time = time(1, 12);
+            let day1 = arch
+                .run_qpf(kmso, model, init_time, time(1, 12), time(2, 12))
+                .unwrap();
+            let day2 = arch
+                .run_qpf(kmso, model, init_time, time(2, 12), time(3, 12))
+                .unwrap();
+            let both = arch
+                .run_qpf(kmso, model, init_time, time(1, 12), time(3, 12))
+                .unwrap();
+
+            assert!(day1.complete && day2.complete && both.complete);
+            assert!(both.precip_mm >= 0.0);
+            assert!((day1.precip_mm + day2.precip_mm - both.precip_mm).abs() < 1.0e-6);
+            assert!(both.convective_mm.unwrap() <= both.precip_mm + 1.0e-6);
+
+            // Starts before the run.
+            let early = arch
+                .run_qpf(kmso, model, init_time, time(1, 0), time(2, 0))
+                .unwrap();
+            assert!(!early.complete);
+        }
+    }
+
+    #[test]
+    fn test_runs_qpf_csv() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+
+        let query = FileQuery::new().model(Model::GFS);
+        let records = arch.runs_qpf(&query, 0, 72).unwrap();
+        assert_eq!(records.len(), 3);
+        for rec in &records {
+            assert_eq!(rec.end - rec.init_time, Duration::hours(72));
+            assert!(rec.complete);
+        }
+
+        let mut buf = vec![];
+        QpfRecord::write_csv(&records, &mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        let mut lines = text.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            "station_num,model,init_time,start,end,precip_mm,convective_mm,complete"
+        );
+        assert_eq!(lines.count(), 3);
+        assert!(text.contains(&format!(
+            "727730,{},2017-04-01 06:00,2017-04-01 06:00,2017-04-04 06:00,",
+            Model::GFS.as_static_str()
+        )));
+    }
+}
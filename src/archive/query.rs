@@ -1,12 +1,20 @@
 use rusqlite::OptionalExtension;
-use std::{collections::HashSet, io::Read, iter::FromIterator, str::FromStr};
+use std::{collections::HashSet, iter::FromIterator, str::FromStr};
 
 use crate::{
+    archive::{FileIter, FileRecord},
+    coords::Coords,
     errors::BufkitDataErr,
     models::Model,
-    site::{SiteInfo, StateProv, StationNumber},
+    site::{Country, SiteInfo, SiteTimeZone, StateProv, StationNumber},
 };
 
+mod nearby;
+pub use nearby::NearbyStation;
+
+mod station_search;
+pub use station_search::{StationMatch, StationMatchKind};
+
 mod station_summary;
 pub use station_summary::StationSummary;
 
@@ -25,6 +33,26 @@ impl crate::Archive {
         vals
     }
 
+    /// Retrieve a list of sites in the archive located in a state or providence.
+    pub fn sites_in_state(&self, state: StateProv) -> Result<Vec<SiteInfo>, BufkitDataErr> {
+        Ok(self
+            .sites()?
+            .into_iter()
+            .filter(|site| site.state == Some(state))
+            .collect())
+    }
+
+    /// Retrieve a list of sites in the archive located in a country.
+    ///
+    /// Sites without a country are included if they are in a state or providence in the country.
+    pub fn sites_in_country(&self, country: Country) -> Result<Vec<SiteInfo>, BufkitDataErr> {
+        Ok(self
+            .sites()?
+            .into_iter()
+            .filter(|site| site.resolved_country() == Some(country))
+            .collect())
+    }
+
     fn parse_row_to_site(row: &rusqlite::Row) -> Result<SiteInfo, rusqlite::Error> {
         let station_num: u32 = row.get(0)?;
         let station_num = StationNumber::from(station_num);
@@ -36,14 +64,18 @@ impl crate::Archive {
             .ok()
             .and_then(|a_string| StateProv::from_str(&a_string).ok());
 
-        let time_zone: Option<chrono::FixedOffset> =
-            row.get::<_, i32>(4).ok().and_then(|offset: i32| {
-                if offset < 0 {
-                    chrono::FixedOffset::west_opt(offset.abs())
-                } else {
-                    chrono::FixedOffset::east_opt(offset)
-                }
-            });
+        let time_zone = Self::parse_time_zone(row.get(10)?, row.get(4)?);
+
+        let lat: Option<f64> = row.get(5)?;
+        let lon: Option<f64> = row.get(6)?;
+        let coords: Option<Coords> = lat.and_then(|lat| lon.map(|lon| Coords { lat, lon }));
+
+        let elevation: Option<metfor::Meters> = row.get::<_, Option<f64>>(7)?.map(metfor::Meters);
+        let icao_id: Option<String> = row.get(8)?;
+        let wmo_id: Option<String> = row.get(9)?;
+        let country: Option<Country> = row
+            .get::<_, Option<String>>(11)?
+            .and_then(|a_string| Country::from_str(&a_string).ok());
 
         Ok(SiteInfo {
             station_num,
@@ -51,9 +83,31 @@ impl crate::Archive {
             notes,
             state,
             time_zone,
+            country,
+            coords,
+            elevation,
+            icao_id,
+            wmo_id,
         })
     }
 
+    /// Get the time zone from the `tz_name` and `tz_offset_sec` columns of the sites table. Older
+    /// archives only have the offset, so fall back to that if there is no name.
+    fn parse_time_zone(name: Option<String>, offset: Option<i32>) -> Option<SiteTimeZone> {
+        name.and_then(|name| SiteTimeZone::from_str(&name).ok())
+            .or_else(|| {
+                offset
+                    .and_then(|offset: i32| {
+                        if offset < 0 {
+                            chrono::FixedOffset::west_opt(offset.abs())
+                        } else {
+                            chrono::FixedOffset::east_opt(offset)
+                        }
+                    })
+                    .map(SiteTimeZone::from)
+            })
+    }
+
     /// Retrieve the sites with their most recent station id for the given model.
     pub fn sites_and_ids_for(
         &self,
@@ -84,6 +138,13 @@ impl crate::Archive {
                     sites.state, 
                     sites.notes, 
                     sites.tz_offset_sec, 
+                    sites.lat,
+                    sites.lon,
+                    sites.elevation_m,
+                    sites.icao_id,
+                    sites.wmo_id,
+                    sites.tz_name,
+                    sites.country,
                     temp_ids.id
                 FROM sites JOIN temp_ids ON temp_ids.station_num = sites.station_num
             ",
@@ -91,7 +152,7 @@ impl crate::Archive {
 
         let parse_row = |row: &rusqlite::Row| -> Result<(SiteInfo, String), rusqlite::Error> {
             let site_info = Self::parse_row_to_site(row)?;
-            let site_id: String = row.get(5)?;
+            let site_id: String = row.get(12)?;
             Ok((site_info, site_id))
         };
 
@@ -113,11 +174,20 @@ impl crate::Archive {
                          name,
                          state,
                          notes,
-                         tz_offset_sec
+                         tz_offset_sec,
+                         lat,
+                         lon,
+                         elevation_m,
+                         icao_id,
+                         wmo_id,
+                         tz_name,
+                         country
                     FROM sites 
                     WHERE station_num = ?1
                 ",
-                &[&Into::<u32>::into(station_num)],
+                &[&Into::<u32>::into(
+                    self.resolve_station_num(station_num).ok()?,
+                )],
                 Self::parse_row_to_site,
             )
             .ok()
@@ -125,7 +195,7 @@ impl crate::Archive {
 
     /// Get a list of models in the archive for this site.
     pub fn models(&self, station_num: StationNumber) -> Result<Vec<Model>, BufkitDataErr> {
-        let station_num: u32 = Into::<u32>::into(station_num);
+        let station_num: u32 = self.resolve_station_num(station_num)?.into();
 
         let mut stmt = self
             .db_conn
@@ -149,29 +219,8 @@ impl crate::Archive {
         model: Model,
         init_time: chrono::NaiveDateTime,
     ) -> Result<String, BufkitDataErr> {
-        let station_num: u32 = Into::<u32>::into(station_num);
-
-        let file_name: Result<String, _> = self.db_conn.query_row(
-            "SELECT file_name FROM files WHERE station_num = ?1 AND model = ?2 AND init_time = ?3",
-            &[
-                &station_num as &dyn rusqlite::types::ToSql,
-                &model.as_static_str() as &dyn rusqlite::types::ToSql,
-                &init_time as &dyn rusqlite::types::ToSql,
-            ],
-            |row| row.get(0),
-        );
-
-        let file_name = match file_name {
-            Ok(fname) => fname,
-            Err(rusqlite::Error::QueryReturnedNoRows) => return Err(BufkitDataErr::NotInIndex),
-            Err(x) => return Err(BufkitDataErr::Database(x)),
-        };
-
-        let file = std::fs::File::open(self.data_root().join(file_name))?;
-        let mut decoder = flate2::read::GzDecoder::new(file);
-        let mut s = String::new();
-        decoder.read_to_string(&mut s)?;
-        Ok(s)
+        self.retrieve_record(station_num, model, init_time)
+            .map(|(_, text)| text)
     }
 
     /// Retrieve the  most recent file.
@@ -180,49 +229,26 @@ impl crate::Archive {
         station_num: StationNumber,
         model: Model,
     ) -> Result<String, BufkitDataErr> {
-        let station_num: u32 = Into::<u32>::into(station_num);
-
-        let file_name: Result<String, _> = self.db_conn.query_row(
-            "
-                SELECT file_name 
-                FROM files 
-                WHERE station_num = ?1 AND model = ?2 
-                ORDER BY init_time DESC 
-                LIMIT 1
-            ",
-            &[
-                &station_num as &dyn rusqlite::types::ToSql,
-                &model.as_static_str() as &dyn rusqlite::types::ToSql,
-            ],
-            |row| row.get(0),
-        );
-
-        let file_name = match file_name {
-            Ok(fname) => fname,
-            Err(rusqlite::Error::QueryReturnedNoRows) => return Err(BufkitDataErr::NotInIndex),
-            Err(x) => return Err(BufkitDataErr::Database(x)),
-        };
-
-        let file = std::fs::File::open(self.data_root().join(file_name))?;
-        let mut decoder = flate2::read::GzDecoder::new(file);
-        let mut s = String::new();
-        decoder.read_to_string(&mut s)?;
-        Ok(s)
+        self.retrieve_most_recent_record(station_num, model)
+            .map(|(_, text)| text)
     }
 
     /// Retrieve all the soundings with any data valid between the start and end times.
+    ///
+    /// The files are loaded lazily as the iterator is used, and any errors loading them are
+    /// returned by the iterator.
     pub fn retrieve_all_valid_in(
         &self,
         station_num: StationNumber,
         model: Model,
         start: chrono::NaiveDateTime,
         end: chrono::NaiveDateTime,
-    ) -> Result<impl Iterator<Item = String>, BufkitDataErr> {
-        let station_num: u32 = Into::<u32>::into(station_num);
+    ) -> Result<FileIter, BufkitDataErr> {
+        let station_num: u32 = self.resolve_station_num(station_num)?.into();
 
-        let mut stmt = self.db_conn.prepare(
+        let mut stmt = self.db_conn.prepare(&format!(
             "
-                    SELECT file_name 
+                    SELECT {}
                     FROM files 
                     WHERE station_num = ?1 AND model = ?2 AND 
                         (
@@ -232,9 +258,10 @@ impl crate::Archive {
                         )
                     ORDER BY init_time ASC 
                 ",
-        )?;
+            Self::FILE_RECORD_COLUMNS
+        ))?;
 
-        let file_names: Vec<String> = stmt
+        let records: Vec<FileRecord> = stmt
             .query_map(
                 &[
                     &station_num as &dyn rusqlite::types::ToSql,
@@ -242,26 +269,15 @@ impl crate::Archive {
                     &start as &dyn rusqlite::types::ToSql,
                     &end as &dyn rusqlite::types::ToSql,
                 ],
-                |row| row.get(0),
+                Self::parse_row_to_file_record,
             )?
-            .filter_map(|res| res.ok())
-            .collect();
+            .collect::<Result<_, _>>()?;
 
-        if file_names.is_empty() {
+        if records.is_empty() {
             return Err(BufkitDataErr::NotInIndex);
         }
 
-        let root = self.data_root();
-        Ok(file_names.into_iter().filter_map(move |fname| {
-            std::fs::File::open(root.join(fname)).ok().and_then(|f| {
-                let mut decoder = flate2::read::GzDecoder::new(f);
-                let mut s = String::new();
-                match decoder.read_to_string(&mut s) {
-                    Ok(_) => Some(s),
-                    Err(_) => None,
-                }
-            })
-        }))
+        Ok(FileIter::new(self, records))
     }
 
     /// Check to see if a file is present in the archive and it is retrieveable.
@@ -274,7 +290,7 @@ impl crate::Archive {
         let num_records: i32 = self.db_conn.query_row(
             "SELECT COUNT(*) FROM files WHERE station_num = ?1 AND model = ?2 AND init_time = ?3",
             &[
-                &Into::<i64>::into(site) as &dyn rusqlite::types::ToSql,
+                &Into::<i64>::into(self.resolve_station_num(site)?) as &dyn rusqlite::types::ToSql,
                 &model.as_static_str() as &dyn rusqlite::types::ToSql,
                 &init_time as &dyn rusqlite::types::ToSql,
             ],
@@ -285,6 +301,8 @@ impl crate::Archive {
     }
 
     /// Retrieve the most recent station number used with this ID and model.
+    ///
+    /// This includes aliases added with `add_id_alias`.
     pub fn station_num_for_id(
         &self,
         id: &str,
@@ -314,13 +332,13 @@ impl crate::Archive {
         station_num: StationNumber,
         model: Model,
     ) -> Result<Vec<String>, BufkitDataErr> {
-        let station_num: u32 = Into::<u32>::into(station_num);
+        let station_num: u32 = self.resolve_station_num(station_num)?.into();
 
         let mut stmt = self.db_conn.prepare(
             "
-                SELECT DISTINCT id 
-                FROM files
-                WHERE station_num = ?1 AND model = ?2
+                SELECT DISTINCT id
+                FROM id_history
+                WHERE station_num = ?1 AND model = ?2 AND is_alias = 0
             ",
         )?;
 
@@ -338,38 +356,41 @@ impl crate::Archive {
     }
 
     /// Retrieve the most recently used ID with a site.
+    ///
+    /// Returns `None` if the id has since been used with a different station.
     pub fn most_recent_id(
         &self,
         station_num: StationNumber,
         model: Model,
     ) -> Result<Option<String>, BufkitDataErr> {
+        let station_num = self.resolve_station_num(station_num)?;
         let station_num_raw: u32 = Into::<u32>::into(station_num);
 
-        let mut stmt = self.db_conn.prepare(
-            "
-                SELECT id, init_time 
-                FROM files
-                WHERE station_num = ?1 AND model = ?2
-                ORDER BY init_time DESC
-                LIMIT 1
-            ",
-        )?;
-
-        let most_recent_site: String = match stmt
+        let most_recent: Option<(String, chrono::NaiveDateTime)> = self
+            .db_conn
             .query_row(
+                "
+                    SELECT id, end_time
+                    FROM id_history
+                    WHERE station_num = ?1 AND model = ?2 AND is_alias = 0
+                    ORDER BY end_time DESC
+                    LIMIT 1
+                ",
                 &[
                     &station_num_raw as &dyn rusqlite::types::ToSql,
                     &model.as_static_str() as &dyn rusqlite::types::ToSql,
                 ],
-                |row| row.get(0),
+                |row| Ok((row.get(0)?, row.get(1)?)),
             )
-            .optional()?
-        {
-            Some(id) => id,
+            .optional()?;
+
+        let (most_recent_site, end_time) = match most_recent {
+            Some(vals) => vals,
             None => return Ok(None),
         };
 
-        let most_recent_station_num = self.station_num_for_id(&most_recent_site, model)?;
+        let most_recent_station_num =
+            self.station_num_for_id_at(&most_recent_site, end_time, Some(model))?;
 
         if most_recent_station_num == station_num {
             Ok(Some(most_recent_site))
@@ -384,7 +405,7 @@ impl crate::Archive {
         station_num: StationNumber,
         model: Model,
     ) -> Result<Vec<chrono::NaiveDateTime>, BufkitDataErr> {
-        let station_num: u32 = Into::<u32>::into(station_num);
+        let station_num: u32 = self.resolve_station_num(station_num)?.into();
 
         let mut stmt = self.db_conn.prepare(
             "
@@ -439,7 +460,7 @@ impl crate::Archive {
 
     /// Get the number of files in the archive for the given station and model.
     pub fn count(&self, station_num: StationNumber, model: Model) -> Result<u32, BufkitDataErr> {
-        let station_num: u32 = Into::<u32>::into(station_num);
+        let station_num: u32 = self.resolve_station_num(station_num)?.into();
         self.db_conn
             .query_row(
                 "
@@ -461,7 +482,7 @@ impl crate::Archive {
         station_num: StationNumber,
         model: Model,
     ) -> Result<(chrono::NaiveDateTime, chrono::NaiveDateTime), BufkitDataErr> {
-        let station_num: u32 = Into::<u32>::into(station_num);
+        let station_num: u32 = self.resolve_station_num(station_num)?.into();
 
         let start = self.db_conn.query_row(
             "
@@ -561,7 +582,7 @@ mod unit {
             Some("A coastal city with coffe and rain".to_owned())
         );
         assert_eq!(si.state, Some(StateProv::WA));
-        assert_eq!(si.time_zone, Some(chrono::FixedOffset::west_opt(8 * 3600).unwrap()));
+        assert_eq!(si.time_zone, Some(chrono_tz::America::Los_Angeles.into()));
 
         let si = arch
             .site(StationNumber::from(3))
@@ -569,12 +590,50 @@ mod unit {
         assert_eq!(si.name, Some("Missoula".to_owned()));
         assert_eq!(si.notes, Some("In a valley.".to_owned()));
         assert_eq!(si.state, None);
-        assert_eq!(si.time_zone, Some(chrono::FixedOffset::west_opt(7 * 3600).unwrap()));
+        assert_eq!(
+            si.time_zone,
+            Some(chrono::FixedOffset::west_opt(7 * 3600).unwrap().into())
+        );
 
         assert!(arch.site(StationNumber::from(0)).is_none());
         assert!(arch.site(StationNumber::from(100)).is_none());
     }
 
+    #[test]
+    fn test_sites_in_state_and_country() {
+        let TestArchive { tmp: _tmp, arch } =
+            create_test_archive().expect("Failed to create test archive.");
+
+        for site in &get_test_sites() {
+            arch.add_site(site).expect("Error adding site.");
+        }
+
+        let calgary = SiteInfo {
+            station_num: StationNumber::from(718770),
+            name: Some("Calgary".to_owned()),
+            state: Some(StateProv::AB),
+            ..SiteInfo::default()
+        };
+        arch.add_site(&calgary).expect("Error adding site.");
+
+        let in_wa = arch
+            .sites_in_state(StateProv::WA)
+            .expect("Error querying archive.");
+        assert_eq!(in_wa.len(), 1);
+        assert_eq!(in_wa[0].station_num, StationNumber::from(2));
+
+        let in_ca = arch
+            .sites_in_country(Country::CA)
+            .expect("Error querying archive.");
+        assert_eq!(in_ca, vec![calgary]);
+
+        // Missoula has no state, so it can't be placed in a country.
+        let in_us = arch
+            .sites_in_country(Country::US)
+            .expect("Error querying archive.");
+        assert_eq!(in_us.len(), 2);
+    }
+
     #[test]
     fn test_models_for_site() {
         let TestArchive {
@@ -0,0 +1,318 @@
+use crate::{coords::Coords, errors::BufkitDataErr, models::Model, site::SiteInfo};
+use rusqlite::ToSql;
+
+#[cfg(feature = "pylib")]
+use pyo3::prelude::*;
+
+/// A site near a point.
+#[cfg_attr(feature = "pylib", pyclass(module = "bufkit_data"))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct NearbyStation {
+    /// The site.
+    pub site: SiteInfo,
+    /// The great circle distance from the point to the site in kilometers.
+    pub distance_km: f64,
+    /// The initial bearing from the point to the site in degrees clockwise from north.
+    pub bearing_deg: f64,
+}
+
+impl crate::Archive {
+    /// Get all the sites within `radius_km` kilometers of a point, closest first.
+    ///
+    /// Only sites with a location are searched, see `fill_missing_site_metadata` for sites added
+    /// by older versions of this crate.
+    pub fn stations_within(
+        &self,
+        lat: f64,
+        lon: f64,
+        radius_km: f64,
+    ) -> Result<Vec<NearbyStation>, BufkitDataErr> {
+        self.nearby_stations(Coords { lat, lon }, radius_km, None)
+    }
+
+    /// Get the `n` sites closest to a point, closest first.
+    ///
+    /// If `model_filter` is given, only sites with files for that model are included. Only sites
+    /// with a location are searched, see `fill_missing_site_metadata` for sites added by older
+    /// versions of this crate.
+    pub fn nearest_stations(
+        &self,
+        lat: f64,
+        lon: f64,
+        n: usize,
+        model_filter: Option<Model>,
+    ) -> Result<Vec<NearbyStation>, BufkitDataErr> {
+        const START_RADIUS_KM: f64 = 100.0;
+        let max_radius_km = std::f64::consts::PI * Coords::EARTH_RADIUS_KM;
+
+        let center = Coords { lat, lon };
+        if n == 0 {
+            return Ok(vec![]);
+        }
+
+        // Grow the search until it holds enough sites. Everything within the radius has been
+        // found, so the closest n are correct once there are at least n of them.
+        let mut radius_km = START_RADIUS_KM;
+        loop {
+            let mut found = self.nearby_stations(center, radius_km, model_filter)?;
+
+            if found.len() >= n || radius_km >= max_radius_km {
+                found.truncate(n);
+                return Ok(found);
+            }
+
+            radius_km *= 4.0;
+        }
+    }
+
+    fn nearby_stations(
+        &self,
+        center: Coords,
+        radius_km: f64,
+        model_filter: Option<Model>,
+    ) -> Result<Vec<NearbyStation>, BufkitDataErr> {
+        let (lat_range, lon_ranges) = bounding_box(center, radius_km);
+
+        let mut stmt = self.db_conn.prepare(
+            "
+                SELECT
+                    sites.station_num,
+                    sites.name,
+                    sites.state,
+                    sites.notes,
+                    sites.tz_offset_sec,
+                    sites.lat,
+                    sites.lon,
+                    sites.elevation_m,
+                    sites.icao_id,
+                    sites.wmo_id,
+                    sites.tz_name,
+                    sites.country
+                FROM site_locations JOIN sites
+                    ON sites.station_num = site_locations.station_num
+                WHERE site_locations.max_lat >= ?1 AND site_locations.min_lat <= ?2
+                    AND (
+                        (site_locations.max_lon >= ?3 AND site_locations.min_lon <= ?4)
+                        OR (site_locations.max_lon >= ?5 AND site_locations.min_lon <= ?6)
+                    )
+                    AND (?7 IS NULL OR EXISTS (
+                        SELECT 1 FROM files
+                        WHERE files.station_num = sites.station_num AND files.model = ?7
+                    ))
+            ",
+        )?;
+
+        let mut found: Vec<NearbyStation> = stmt
+            .query_and_then(
+                [
+                    &lat_range.0 as &dyn ToSql,
+                    &lat_range.1,
+                    &lon_ranges[0].0,
+                    &lon_ranges[0].1,
+                    &lon_ranges[1].0,
+                    &lon_ranges[1].1,
+                    &model_filter.map(Model::as_static_str),
+                ],
+                Self::parse_row_to_site,
+            )?
+            .filter_map(|res| {
+                res.map(|site| {
+                    let coords = site.coords?;
+                    let distance_km = center.distance_km(coords);
+
+                    if distance_km <= radius_km {
+                        Some(NearbyStation {
+                            distance_km,
+                            bearing_deg: center.bearing_to(coords),
+                            site,
+                        })
+                    } else {
+                        None
+                    }
+                })
+                .transpose()
+            })
+            .collect::<Result<_, rusqlite::Error>>()?;
+
+        found.sort_by(|left, right| left.distance_km.total_cmp(&right.distance_km));
+
+        Ok(found)
+    }
+}
+
+/// Get a latitude range and two longitude ranges that hold every point within `radius_km` of the
+/// center. The second longitude range is only used when the first crosses the antimeridian.
+fn bounding_box(center: Coords, radius_km: f64) -> ((f64, f64), [(f64, f64); 2]) {
+    const EMPTY: (f64, f64) = (1000.0, 1000.0);
+    const ALL_LONS: [(f64, f64); 2] = [(-180.0, 180.0), EMPTY];
+
+    let angle = radius_km / Coords::EARTH_RADIUS_KM;
+    let dlat = angle.to_degrees();
+
+    let min_lat = center.lat - dlat;
+    let max_lat = center.lat + dlat;
+
+    // Meridians converge toward the poles, so the longitude range grows with latitude.
+    if min_lat <= -90.0 || max_lat >= 90.0 || angle >= std::f64::consts::FRAC_PI_2 {
+        return ((min_lat.max(-90.0), max_lat.min(90.0)), ALL_LONS);
+    }
+
+    let dlon = f64::asin(f64::sin(angle) / f64::cos(center.lat.to_radians())).to_degrees();
+    let min_lon = center.lon - dlon;
+    let max_lon = center.lon + dlon;
+
+    let lon_ranges = if min_lon < -180.0 {
+        [(min_lon + 360.0, 180.0), (-180.0, max_lon)]
+    } else if max_lon > 180.0 {
+        [(min_lon, 180.0), (-180.0, max_lon - 360.0)]
+    } else {
+        [(min_lon, max_lon), EMPTY]
+    };
+
+    ((min_lat, max_lat), lon_ranges)
+}
+
+#[cfg(feature = "pylib")]
+#[cfg_attr(feature = "pylib", pymethods)]
+impl NearbyStation {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!(
+            "NearbyStation({}, {:.1} km, {:.0}°)",
+            self.site.station_num, self.distance_km, self.bearing_deg
+        ))
+    }
+
+    #[getter]
+    fn get_site(&self) -> SiteInfo {
+        self.site.clone()
+    }
+
+    #[getter]
+    fn get_distance_km(&self) -> f64 {
+        self.distance_km
+    }
+
+    #[getter]
+    fn get_bearing_deg(&self) -> f64 {
+        self.bearing_deg
+    }
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                          Unit Tests
+--------------------------------------------------------------------------------------------------*/
+#[cfg(test)]
+mod unit {
+    use super::*;
+    use crate::archive::unit::*; // test helpers.
+    use crate::site::StationNumber;
+
+    fn add_site_at(arch: &crate::Archive, num: u32, lat: f64, lon: f64) {
+        arch.add_site(&SiteInfo {
+            station_num: StationNumber::from(num),
+            coords: Some(Coords { lat, lon }),
+            ..SiteInfo::default()
+        })
+        .expect("Error adding site.");
+    }
+
+    #[test]
+    fn test_bounding_box() {
+        let (lats, lons) = bounding_box(Coords::from((60.0, 0.0)), 111.2);
+        assert!((lats.0 - 59.0).abs() < 0.01 && (lats.1 - 61.0).abs() < 0.01);
+        // Twice as wide in longitude at 60 degrees latitude.
+        assert!((lons[0].0 + 2.0).abs() < 0.01 && (lons[0].1 - 2.0).abs() < 0.01);
+
+        let (_, lons) = bounding_box(Coords::from((0.0, 179.5)), 111.2);
+        assert_eq!(lons[0].1, 180.0);
+        assert_eq!(lons[1].0, -180.0);
+        assert!((lons[1].1 + 179.5).abs() < 0.01);
+
+        let (lats, lons) = bounding_box(Coords::from((89.5, 0.0)), 111.2);
+        assert_eq!(lats.1, 90.0);
+        assert_eq!(lons[0], (-180.0, 180.0));
+    }
+
+    #[test]
+    fn test_stations_within() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+        add_site_at(&arch, 1, 47.47, -111.38); // Great Falls
+        add_site_at(&arch, 2, 46.92, -114.09); // Missoula
+        add_site_at(&arch, 3, 0.0, 179.9);
+        arch.add_site(&SiteInfo {
+            station_num: StationNumber::from(4),
+            ..SiteInfo::default()
+        })
+        .unwrap();
+
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+        let found = arch.stations_within(46.92, -114.09, 50.0).unwrap();
+        let nums: Vec<_> = found.iter().map(|near| near.site.station_num).collect();
+        assert_eq!(nums, vec![StationNumber::from(2), kmso]);
+        assert!(found[0].distance_km < 0.01);
+
+        let found = arch.stations_within(46.92, -114.09, 250.0).unwrap();
+        assert_eq!(found.len(), 3);
+        assert_eq!(found[2].site.station_num, StationNumber::from(1));
+        assert!((found[2].bearing_deg - 73.0).abs() < 2.0);
+
+        // Across the antimeridian.
+        let found = arch.stations_within(0.0, -179.9, 50.0).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].site.station_num, StationNumber::from(3));
+
+        // Moving a site updates the index.
+        arch.update_site(&SiteInfo {
+            station_num: StationNumber::from(3),
+            coords: Some(Coords::from((47.0, -114.0))),
+            ..SiteInfo::default()
+        })
+        .unwrap();
+        assert!(arch.stations_within(0.0, -179.9, 50.0).unwrap().is_empty());
+        arch.remove_site(StationNumber::from(3)).unwrap();
+        assert_eq!(
+            arch.stations_within(46.92, -114.09, 250.0).unwrap().len(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_nearest_stations() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+        add_site_at(&arch, 1, 47.47, -111.38); // Great Falls
+        add_site_at(&arch, 2, -33.9, 151.2); // Sydney
+
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+
+        let found = arch.nearest_stations(47.0, -111.0, 2, None).unwrap();
+        let nums: Vec<_> = found.iter().map(|near| near.site.station_num).collect();
+        assert_eq!(nums, vec![StationNumber::from(1), kmso]);
+
+        let found = arch
+            .nearest_stations(47.0, -111.0, 1, Some(Model::GFS))
+            .unwrap();
+        assert_eq!(found[0].site.station_num, kmso);
+        assert!(found[0].bearing_deg > 180.0);
+
+        // Far away sites are found too.
+        let found = arch.nearest_stations(47.0, -111.0, 10, None).unwrap();
+        assert_eq!(found.len(), 3);
+        assert_eq!(found[2].site.station_num, StationNumber::from(2));
+
+        assert!(
+            arch.nearest_stations(47.0, -111.0, 0, None)
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -3,5 +3,12 @@ SELECT
     name,
     state,
     notes,
-    tz_offset_sec
+    tz_offset_sec,
+    lat,
+    lon,
+    elevation_m,
+    icao_id,
+    wmo_id,
+    tz_name,
+    country
 FROM sites
@@ -1,5 +1,5 @@
-SELECT station_num, init_time
-FROM files
-WHERE id = ?1 AND model = ?2
-ORDER BY init_time DESC
+SELECT station_num, end_time
+FROM id_history
+WHERE id = ?1 AND (model = ?2 OR model IS NULL)
+ORDER BY COALESCE(end_time, '9999') DESC, is_alias DESC
 LIMIT 1
@@ -0,0 +1,254 @@
+use crate::{errors::BufkitDataErr, site::StationNumber};
+use std::collections::HashMap;
+
+/// How a station matched a search.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum StationMatchKind {
+    /// An id used with the station matched exactly, ignoring case.
+    Id,
+    /// An id used with the station was close to the search, e.g. MSO for KMSO or KMOS for KMSO.
+    FuzzyId,
+    /// The name of the station contains the search, ignoring case.
+    Name,
+}
+
+/// A station that matched a search.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StationMatch {
+    /// The station number.
+    pub station_num: StationNumber,
+    /// How the station matched.
+    pub kind: StationMatchKind,
+    /// The id or name that matched.
+    pub matched: String,
+    /// The most recent initialization time of any file for the station, for any model.
+    pub last_used: Option<chrono::NaiveDateTime>,
+    /// The number of files in the archive for the station, for all models.
+    pub number_of_files: u32,
+}
+
+impl crate::Archive {
+    /// Search for stations by id or name without knowing the model.
+    ///
+    /// Ids from all models and id aliases are searched, ignoring case. Exact id matches come first,
+    /// followed by ids that are close to the search, and then stations whose name contains the
+    /// search. Within each group the stations used most recently and with the most files come
+    /// first. Each station is only listed once, with its best match.
+    pub fn search_stations(&self, query: &str) -> Result<Vec<StationMatch>, BufkitDataErr> {
+        let query = query.trim().to_uppercase();
+        if query.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut best: HashMap<StationNumber, (StationMatchKind, String)> = HashMap::new();
+        let mut consider = |station_num: StationNumber, kind: StationMatchKind, matched: String| {
+            best.entry(station_num)
+                .and_modify(|curr| {
+                    if kind < curr.0 {
+                        *curr = (kind, matched.clone());
+                    }
+                })
+                .or_insert((kind, matched));
+        };
+
+        let mut ids_stmt = self
+            .db_conn
+            .prepare("SELECT DISTINCT station_num, id FROM id_history")?;
+        let ids = ids_stmt.query_map([], |row| {
+            Ok((
+                StationNumber::from(row.get::<_, u32>(0)?),
+                row.get::<_, String>(1)?,
+            ))
+        })?;
+        for pair in ids {
+            let (station_num, id) = pair?;
+            if id == query {
+                consider(station_num, StationMatchKind::Id, id);
+            } else if is_fuzzy_id_match(&query, &id) {
+                consider(station_num, StationMatchKind::FuzzyId, id);
+            }
+        }
+
+        let mut names_stmt = self.db_conn.prepare(
+            "SELECT station_num, name FROM sites WHERE name LIKE '%' || ?1 || '%' ESCAPE '\\'",
+        )?;
+        let escaped = query
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        let names = names_stmt.query_map([escaped], |row| {
+            Ok((
+                StationNumber::from(row.get::<_, u32>(0)?),
+                row.get::<_, String>(1)?,
+            ))
+        })?;
+        for pair in names {
+            let (station_num, name) = pair?;
+            consider(station_num, StationMatchKind::Name, name);
+        }
+
+        let mut stats_stmt = self
+            .db_conn
+            .prepare("SELECT MAX(init_time), COUNT(*) FROM files WHERE station_num = ?1")?;
+        let mut matches: Vec<StationMatch> = best
+            .into_iter()
+            .map(|(station_num, (kind, matched))| {
+                let (last_used, number_of_files) = stats_stmt
+                    .query_row([Into::<u32>::into(station_num)], |row| {
+                        Ok((row.get(0)?, row.get(1)?))
+                    })?;
+
+                Ok(StationMatch {
+                    station_num,
+                    kind,
+                    matched,
+                    last_used,
+                    number_of_files,
+                })
+            })
+            .collect::<Result<_, BufkitDataErr>>()?;
+
+        matches.sort_by(|a, b| {
+            a.kind
+                .cmp(&b.kind)
+                .then_with(|| b.last_used.cmp(&a.last_used))
+                .then_with(|| b.number_of_files.cmp(&a.number_of_files))
+                .then_with(|| a.station_num.cmp(&b.station_num))
+        });
+
+        Ok(matches)
+    }
+
+    /// Find the station number for an id without knowing the model.
+    ///
+    /// If the id has been used with more than one station, the one used most recently wins.
+    pub fn station_num_for_id_any_model(&self, id: &str) -> Result<StationNumber, BufkitDataErr> {
+        self.search_stations(id)?
+            .into_iter()
+            .find(|candidate| candidate.kind == StationMatchKind::Id)
+            .map(|candidate| candidate.station_num)
+            .ok_or(BufkitDataErr::NotInIndex)
+    }
+}
+
+/// Check if two ids are close. Both should already be upper case.
+///
+/// One id may have a prefix or suffix the other lacks, e.g. MSO and KMSO, or they may differ by a
+/// single edit, e.g. KMOS and KMSO.
+fn is_fuzzy_id_match(query: &str, id: &str) -> bool {
+    const MIN_LEN: usize = 3;
+
+    if query.len() < MIN_LEN || id.len() < MIN_LEN {
+        return false;
+    }
+
+    id.contains(query) || query.contains(id) || edit_distance(query, id) <= 1
+}
+
+/// The Damerau-Levenshtein distance between two strings, counting adjacent transpositions as a
+/// single edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    let mut dist = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in dist.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, val) in dist[0].iter_mut().enumerate() {
+        *val = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (dist[i - 1][j] + 1)
+                .min(dist[i][j - 1] + 1)
+                .min(dist[i - 1][j - 1] + cost);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(dist[i - 2][j - 2] + 1);
+            }
+
+            dist[i][j] = best;
+        }
+    }
+
+    dist[a.len()][b.len()]
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                          Unit Tests
+--------------------------------------------------------------------------------------------------*/
+#[cfg(test)]
+mod unit {
+    use super::*;
+    use crate::archive::unit::*; // test helpers.
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("KMSO", "KMSO"), 0);
+        assert_eq!(edit_distance("KMSO", "KMOS"), 1);
+        assert_eq!(edit_distance("KMSO", "KMS"), 1);
+        assert_eq!(edit_distance("KMSO", "KBOI"), 3);
+
+        assert!(is_fuzzy_id_match("MSO", "KMSO"));
+        assert!(is_fuzzy_id_match("KMSX", "KMSO"));
+        assert!(!is_fuzzy_id_match("SO", "KMSO"));
+        assert!(!is_fuzzy_id_match("KBOI", "KMSO"));
+    }
+
+    #[test]
+    fn test_search_stations() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+        for site in &get_test_sites() {
+            arch.add_site(site).expect("Error adding site.");
+        }
+
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+
+        let found = arch.search_stations("kmso").expect("Database error.");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].station_num, kmso);
+        assert_eq!(found[0].kind, StationMatchKind::Id);
+        assert_eq!(found[0].number_of_files, 6);
+        assert!(found[0].last_used.is_some());
+        assert_eq!(arch.station_num_for_id_any_model("kmso").unwrap(), kmso);
+
+        let found = arch.search_stations("mso").expect("Database error.");
+        assert_eq!(found[0].station_num, kmso);
+        assert_eq!(found[0].kind, StationMatchKind::FuzzyId);
+        assert!(arch.station_num_for_id_any_model("mso").is_err());
+
+        // Exact id matches come before name matches, and stations without files come last.
+        let missoula = StationNumber::from(3);
+        arch.add_id_alias(
+            missoula,
+            "SEA",
+            None,
+            chrono::NaiveDate::from_ymd_opt(2020, 1, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap(),
+            None,
+        )
+        .unwrap();
+        let found = arch.search_stations("sea").expect("Database error.");
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].station_num, missoula);
+        assert_eq!(found[0].kind, StationMatchKind::Id);
+        assert_eq!(found[1].station_num, StationNumber::from(2));
+        assert_eq!(found[1].kind, StationMatchKind::Name);
+        assert_eq!(found[1].matched, "Seattle");
+        assert_eq!(found[1].number_of_files, 0);
+        assert_eq!(found[1].last_used, None);
+
+        assert!(arch.search_stations("  ").unwrap().is_empty());
+        assert!(arch.search_stations("100%").unwrap().is_empty());
+    }
+}
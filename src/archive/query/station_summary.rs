@@ -1,11 +1,12 @@
 use crate::{
+    coords::Coords,
     errors::BufkitDataErr,
     models::Model,
-    site::{StateProv, StationNumber},
+    region::Region,
+    site::{SiteTimeZone, StateProv, StationNumber},
 };
-use chrono::FixedOffset;
 use std::{collections::HashMap, str::FromStr};
-use rusqlite::Statement;
+use rusqlite::{Params, Statement};
 
 #[cfg(feature = "pylib")]
 use pyo3::prelude::*;
@@ -26,8 +27,8 @@ pub struct StationSummary {
     pub notes: Option<String>,
     /// The state-province associated with the site.
     pub state: Option<StateProv>,
-    /// The time zone offset to local standard time.
-    pub time_zone: Option<FixedOffset>,
+    /// The time zone of the site.
+    pub time_zone: Option<SiteTimeZone>,
     /// Coordinates
     pub coords: Vec<(f64, f64)>,
     /// The number of files in the archive related to this site.
@@ -41,7 +42,7 @@ struct StationEntry {
     name: Option<String>,
     notes: Option<String>,
     state: Option<StateProv>,
-    time_zone: Option<FixedOffset>,
+    time_zone: Option<SiteTimeZone>,
     lat: f64,
     lon: f64,
     number_of_files: u32,
@@ -118,10 +119,13 @@ impl crate::Archive {
     pub fn station_summaries(&self) -> Result<Vec<StationSummary>, BufkitDataErr> {
         let mut stmt = self.db_conn.prepare(include_str!("station_summary.sql"))?;
 
-        Self::process_summary_statement(&mut stmt)
+        Self::process_summary_statement(&mut stmt, [], |_| true)
     }
 
     /// Get a summary of all the stations in the archive near a point..
+    ///
+    /// This searches a box 2.5 degrees on a side around the point, use `stations_within` or
+    /// `nearest_stations` to search by distance.
     pub fn station_summaries_near(&self, lat: f64, lon: f64) -> Result<Vec<StationSummary>, BufkitDataErr> {
 
         let max_lat = lat + 2.5;
@@ -140,15 +144,16 @@ impl crate::Archive {
                     sites.tz_offset_sec, 
                     files.lat,
                     files.lon,
-                    COUNT(files.station_num)
+                    COUNT(files.station_num),
+                    sites.tz_name
                 FROM sites LEFT JOIN files ON files.station_num = sites.station_num
                 WHERE files.lat > {} AND files.lat < {} AND files.lon > {} AND files.lon < {}
-                GROUP BY sites.station_num, id, model, lat, lon
+                GROUP BY sites.station_num, files.id, files.model, files.lat, files.lon
             "#, min_lat, max_lat, min_lon, max_lon);
 
         let mut stmt = self.db_conn.prepare(&query_str)?;
 
-        let mut summaries = Self::process_summary_statement(&mut stmt)?;
+        let mut summaries = Self::process_summary_statement(&mut stmt, [], |_| true)?;
 
         // Haversine function in kilometers for the selected point
         let distance = move |coords: &(f64, f64)| -> f64 {
@@ -183,11 +188,46 @@ impl crate::Archive {
         Ok(summaries)
     }
 
-    fn process_summary_statement(stmt: &mut Statement) -> Result<Vec<StationSummary>, BufkitDataErr> {
+    /// Get a summary of all the stations in the archive with files located inside a region.
+    ///
+    /// Files located outside the region are not included in the summaries.
+    pub fn station_summaries_in(
+        &self,
+        region: &Region,
+    ) -> Result<Vec<StationSummary>, BufkitDataErr> {
+        let (min_lat, min_lon, max_lat, max_lon) = region.bounds();
+
+        let mut stmt = self
+            .db_conn
+            .prepare(include_str!("station_summary_in_box.sql"))?;
+
+        Self::process_summary_statement(&mut stmt, [min_lat, min_lon, max_lat, max_lon], |entry| {
+            region.contains(Coords::from((entry.lat, entry.lon)))
+        })
+    }
+
+    /// Get a summary of all the stations in the archive with files located inside a latitude and
+    /// longitude box.
+    pub fn station_summaries_in_box(
+        &self,
+        min_lat: f64,
+        min_lon: f64,
+        max_lat: f64,
+        max_lon: f64,
+    ) -> Result<Vec<StationSummary>, BufkitDataErr> {
+        self.station_summaries_in(&Region::from_bounds(min_lat, min_lon, max_lat, max_lon))
+    }
+
+    fn process_summary_statement(
+        stmt: &mut Statement,
+        params: impl Params,
+        keep: impl Fn(&StationEntry) -> bool,
+    ) -> Result<Vec<StationSummary>, BufkitDataErr> {
 
         let mut vals: HashMap<StationNumber, StationSummary> = HashMap::new();
 
-        stmt.query_and_then([], Self::parse_row_to_entry)?
+        stmt.query_and_then(params, Self::parse_row_to_entry)?
+            .filter(|stn_entry| stn_entry.as_ref().map(&keep).unwrap_or(true))
             .for_each(|stn_entry| {
                 if let Ok(stn_entry) = stn_entry {
                     if let Some(summary) = vals.get_mut(&stn_entry.station_num) {
@@ -237,14 +277,7 @@ impl crate::Archive {
 
         let notes: Option<String> = row.get(5)?;
 
-        let time_zone: Option<chrono::FixedOffset> =
-            row.get::<_, i32>(6).ok().and_then(|offset: i32| {
-                if offset < 0 {
-                    chrono::FixedOffset::west_opt(offset.abs())
-                } else {
-                    chrono::FixedOffset::east_opt(offset)
-                }
-            });
+        let time_zone = Self::parse_time_zone(row.get(10)?, row.get(6)?);
 
         let lat: f64 = row.get(7)?;
         let lon: f64 = row.get(8)?;
@@ -346,7 +379,8 @@ impl StationSummary {
 #[cfg(test)]
 mod unit {
     use crate::archive::unit::*; // test helpers.
-    use crate::{Model, StationNumber};
+    use crate::{Model, Region, StationNumber};
+    use std::str::FromStr;
 
     #[test]
     fn test_summaries() {
@@ -373,8 +407,38 @@ mod unit {
             assert_eq!(sum.number_of_files, 6);
             assert!(sum.name.is_none());
             assert!(sum.notes.is_none());
-            assert!(sum.time_zone.is_none());
-            assert!(sum.state.is_none());
+            assert_eq!(sum.time_zone, Some(chrono_tz::America::Denver.into()));
+            assert_eq!(sum.state, Some(crate::StateProv::MT));
         }
     }
+
+    #[test]
+    fn test_summaries_in_region() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+
+        let sums = arch
+            .station_summaries_in_box(46.0, -115.0, 48.0, -113.0)
+            .unwrap();
+        assert_eq!(sums.len(), 1);
+        assert_eq!(sums[0].station_num, StationNumber::new(727730));
+        assert_eq!(sums[0].number_of_files, 6);
+
+        assert!(
+            arch.station_summaries_in_box(40.0, -115.0, 42.0, -113.0)
+                .unwrap()
+                .is_empty()
+        );
+
+        // The bounding box of this triangle holds Missoula, but the triangle does not.
+        let region = Region::from_str("POLYGON ((-116 46, -113 49, -116 49, -116 46))").unwrap();
+        assert!(arch.station_summaries_in(&region).unwrap().is_empty());
+
+        let region = Region::from_str("POLYGON ((-116 46, -113 46, -113 49, -116 46))").unwrap();
+        assert_eq!(arch.station_summaries_in(&region).unwrap().len(), 1);
+    }
 }
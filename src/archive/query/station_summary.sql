@@ -8,6 +8,7 @@ SELECT
 	sites.tz_offset_sec, 
     files.lat,
     files.lon,
-	COUNT(files.station_num)
+	COUNT(files.station_num),
+	sites.tz_name
 FROM sites LEFT JOIN files ON files.station_num = sites.station_num
-GROUP BY sites.station_num, id, model, lat, lon
+GROUP BY sites.station_num, files.id, files.model, files.lat, files.lon
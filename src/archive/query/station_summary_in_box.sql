@@ -0,0 +1,15 @@
+SELECT 
+	sites.station_num, 
+	files.id, 
+	files.model, 
+	sites.name, 
+	sites.state, 
+	sites.notes, 
+	sites.tz_offset_sec, 
+    files.lat,
+    files.lon,
+	COUNT(files.station_num),
+	sites.tz_name
+FROM sites JOIN files ON files.station_num = sites.station_num
+WHERE files.lat >= ?1 AND files.lon >= ?2 AND files.lat <= ?3 AND files.lon <= ?4
+GROUP BY sites.station_num, files.id, files.model, files.lat, files.lon
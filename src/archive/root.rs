@@ -1,4 +1,4 @@
-use crate::{errors::BufkitDataErr, models::Model, site::StationNumber, Archive};
+use crate::{Archive, errors::BufkitDataErr, models::Model, region::Region, site::StationNumber};
 use rusqlite::ToSql;
 
 impl Archive {
@@ -20,8 +20,14 @@ impl Archive {
         )?;
 
         db_conn.execute_batch(include_str!("root/create_index.sql"))?;
+        Self::upgrade_index(&db_conn)?;
 
-        Ok(Archive { root, db_conn })
+        Ok(Archive {
+            root,
+            db_conn,
+            sounding_cache: Default::default(),
+            derived_indexing: Default::default(),
+        })
     }
 
     /// Open an existing archive.
@@ -35,7 +41,115 @@ impl Archive {
             rusqlite::OpenFlags::SQLITE_OPEN_READ_WRITE,
         )?;
 
-        Ok(Archive { root, db_conn })
+        Self::upgrade_index(&db_conn)?;
+
+        Ok(Archive {
+            root,
+            db_conn,
+            sounding_cache: Default::default(),
+            derived_indexing: Default::default(),
+        })
+    }
+
+    /// Bring an index created by an older version of this crate up to date with the current
+    /// schema. Everything done here must be safe to repeat on an index that is already current.
+    fn upgrade_index(db_conn: &rusqlite::Connection) -> Result<(), BufkitDataErr> {
+        // Columns added to the sites table since it was first created, in the same order they
+        // appear in create_index.sql so `SELECT *` copies between archives still line up.
+        const SITES_COLUMNS: &[(&str, &str)] = &[
+            ("lat", "REAL"),
+            ("lon", "REAL"),
+            ("elevation_m", "REAL"),
+            ("icao_id", "TEXT"),
+            ("wmo_id", "TEXT"),
+            ("tz_name", "TEXT"),
+            ("country", "TEXT"),
+        ];
+
+        for (column, col_type) in SITES_COLUMNS {
+            let exists: bool = db_conn.query_row(
+                "SELECT COUNT(*) > 0 FROM pragma_table_info('sites') WHERE name = ?1",
+                [column],
+                |row| row.get(0),
+            )?;
+
+            if !exists {
+                db_conn.execute(
+                    &format!(
+                        "ALTER TABLE sites ADD COLUMN {} {} DEFAULT NULL",
+                        column, col_type
+                    ),
+                    [],
+                )?;
+            }
+        }
+
+        let table_exists = |name: &str| -> Result<bool, rusqlite::Error> {
+            db_conn.query_row(
+                "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+                [name],
+                |row| row.get(0),
+            )
+        };
+        let has_id_history = table_exists("id_history")?;
+        let has_site_locations = table_exists("site_locations")?;
+
+        db_conn.execute_batch(include_str!("root/upgrade_index.sql"))?;
+
+        // Ensemble groups created before members could have their own files.
+        let has_member_tags: bool = db_conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('ensemble_members') WHERE name = 'tag'",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_member_tags {
+            db_conn.execute(
+                "ALTER TABLE ensemble_members ADD COLUMN tag TEXT DEFAULT NULL",
+                [],
+            )?;
+        }
+
+        if !has_id_history {
+            Self::rebuild_file_id_history(db_conn)?;
+        }
+
+        if !has_site_locations {
+            db_conn.execute(
+                "
+                    INSERT INTO site_locations
+                    SELECT station_num, lat, lat, lon, lon
+                    FROM sites
+                    WHERE lat IS NOT NULL AND lon IS NOT NULL
+                ",
+                [],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Export all the files for the stations inside a region into a new archive.
+    ///
+    /// This works like `export` for all of the stations returned by `station_summaries_in`.
+    /// Returns the station numbers that were exported.
+    pub fn export_region(
+        &self,
+        region: &Region,
+        models: &[Model],
+        start: chrono::NaiveDateTime,
+        end: chrono::NaiveDateTime,
+        dest: &std::path::Path,
+    ) -> Result<Vec<StationNumber>, BufkitDataErr> {
+        let mut stations: Vec<StationNumber> = self
+            .station_summaries_in(region)?
+            .into_iter()
+            .map(|summary| summary.station_num)
+            .collect();
+        stations.sort_unstable();
+
+        self.export(&stations, models, start, end, dest)?;
+
+        Ok(stations)
     }
 
     /// Close the connection to the database. Anything after this will fail.
@@ -90,6 +204,22 @@ impl Archive {
             ",
         )?;
 
+        let mut aliases_stmt = self.db_conn.prepare(
+            "
+                INSERT INTO ex.id_history
+                SELECT * FROM main.id_history
+                WHERE main.id_history.station_num = ?1 AND main.id_history.is_alias = 1
+            ",
+        )?;
+
+        let mut station_num_aliases_stmt = self.db_conn.prepare(
+            "
+                INSERT INTO ex.station_num_aliases
+                SELECT * FROM main.station_num_aliases
+                WHERE main.station_num_aliases.station_num = ?1
+            ",
+        )?;
+
         let source_dir = self.root.join(Archive::DATA_DIR);
         let dest_dir = dest.join(Archive::DATA_DIR);
         let mut file_names_stmt = self.db_conn.prepare(
@@ -103,6 +233,8 @@ impl Archive {
         for &stn in stations {
             let stn_num: u32 = stn.into();
             sites_stmt.execute([stn_num])?;
+            aliases_stmt.execute([stn_num])?;
+            station_num_aliases_stmt.execute([stn_num])?;
 
             for &model in models {
                 files_stmt.execute(&[
@@ -126,6 +258,8 @@ impl Archive {
             }
         }
 
+        new_db.rebuild_id_history()?;
+
         Ok(())
     }
 }
@@ -150,6 +284,97 @@ mod unit {
         assert!(Archive::connect(&"unlikely_directory_in_my_project").is_err());
     }
 
+    #[test]
+    fn test_archive_connect_upgrades_old_index() {
+        let tmp = tempdir::TempDir::new("bufkit-data-test-archive").unwrap();
+        std::fs::create_dir_all(tmp.path().join(Archive::DATA_DIR)).unwrap();
+
+        // The sites table as it was before canonical locations and ids were added.
+        let db_conn = rusqlite::Connection::open(tmp.path().join(Archive::DB_FILE)).unwrap();
+        db_conn
+            .execute_batch(
+                "
+                    CREATE TABLE sites (
+                        station_num   INT  UNIQUE  NOT NULL,
+                        name          TEXT DEFAULT NULL,
+                        state         TEXT DEFAULT NULL,
+                        notes         TEXT DEFAULT NULL,
+                        tz_offset_sec INT  DEFAULT 0,
+                        PRIMARY KEY (station_num)
+                    );
+                    INSERT INTO sites (station_num, name, tz_offset_sec) VALUES (1, 'Old', -25200);
+                    CREATE TABLE files (
+                        station_num INT         NOT NULL,
+                        model       TEXT        NOT NULL,
+                        init_time   TEXT        NOT NULL,
+                        end_time    TEXT        NOT NULL,
+                        file_name   TEXT UNIQUE NOT NULL,
+                        id          TEXT,
+                        lat         REAL        NOT NULL,
+                        lon         REAL        NOT NULL,
+                        elevation_m INT         NOT NULL
+                    );
+                    INSERT INTO files VALUES
+                        (1, 'GFS', '2017-04-01T00:00:00', '2017-04-08T00:00:00', 'a', 'KOLD',
+                            46.9, -114.1, 972),
+                        (1, 'GFS', '2017-04-01T12:00:00', '2017-04-08T12:00:00', 'b', 'KOLD',
+                            46.9, -114.1, 972);
+                ",
+            )
+            .unwrap();
+        db_conn.close().unwrap();
+
+        let arch = Archive::connect(&tmp.path()).expect("Failed to upgrade old index.");
+        let site = arch
+            .site(StationNumber::from(1))
+            .expect("Site lost in upgrade.");
+        assert_eq!(site.name.as_deref(), Some("Old"));
+        assert_eq!(
+            site.time_zone,
+            Some(chrono::FixedOffset::west_opt(7 * 3600).unwrap().into())
+        );
+        assert!(site.coords.is_none());
+        assert!(site.elevation.is_none());
+
+        // The id history is built from the files already in the index.
+        let history = arch.id_history(StationNumber::from(1)).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].id, "KOLD");
+        assert_eq!(history[0].model, Some(Model::GFS));
+        drop(arch);
+
+        // Connecting again to an up to date index should change nothing.
+        assert!(Archive::connect(&tmp.path()).is_ok());
+    }
+
+    #[test]
+    fn test_export_region() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+        fill_test_archive(&mut arch);
+
+        let dest = tempdir::TempDir::new("bufkit-data-test-export").unwrap();
+        let start = chrono::NaiveDate::from_ymd_opt(2017, 4, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let end = start + chrono::Duration::days(1);
+
+        let region = Region::from_bounds(46.0, -115.0, 48.0, -113.0);
+        let exported = arch
+            .export_region(&region, &[Model::GFS], start, end, dest.path())
+            .expect("Error exporting.");
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+        assert_eq!(exported, vec![kmso]);
+
+        let ex = Archive::connect(&dest.path()).unwrap();
+        assert_eq!(ex.count(kmso, Model::GFS).unwrap(), 3);
+        assert_eq!(ex.count(kmso, Model::NAM).unwrap(), 0);
+        assert!(ex.site(kmso).is_some());
+    }
+
     #[test]
     fn test_get_root() {
         let TestArchive { tmp, arch } =
@@ -19,6 +19,13 @@ CREATE TABLE sites (
     state         TEXT DEFAULT NULL,     -- State/Providence code
     notes         TEXT DEFAULT NULL,     -- Human readable notes
     tz_offset_sec INT  DEFAULT 0,        -- Offset from UTC in seconds
+    lat           REAL DEFAULT NULL,     -- Canonical latitude
+    lon           REAL DEFAULT NULL,     -- Canonical longitude
+    elevation_m   REAL DEFAULT NULL,     -- Canonical elevation in meters
+    icao_id       TEXT DEFAULT NULL,     -- Preferred ICAO identifier
+    wmo_id        TEXT DEFAULT NULL,     -- Preferred WMO identifier
+    tz_name       TEXT DEFAULT NULL,     -- IANA time zone name, overrides tz_offset_sec
+    country       TEXT DEFAULT NULL,     -- ISO 3166-1 country code
     PRIMARY KEY (station_num)
 );
 
@@ -0,0 +1,153 @@
+-- Tables added after the original schema. Everything here must be safe to run on an index that
+-- is already up to date.
+
+-- Which ids were used with which station numbers, and when.
+CREATE TABLE IF NOT EXISTS id_history (
+    station_num INT  NOT NULL,
+    id          TEXT NOT NULL,
+    model       TEXT DEFAULT NULL,     -- NULL for aliases that apply to all models
+    start_time  TEXT NOT NULL,         -- First init time the id was used
+    end_time    TEXT DEFAULT NULL,     -- Last init time the id was used, NULL if still in use
+    is_alias    INT  NOT NULL DEFAULT 0, -- 1 for manual entries, 0 if derived from files
+    FOREIGN KEY (station_num) REFERENCES sites(station_num)
+);
+
+-- Only one range per station, id, and model is derived from the files.
+CREATE UNIQUE INDEX IF NOT EXISTS id_history_from_files ON id_history(station_num, id, model)
+WHERE is_alias = 0;
+
+-- For fast lookups of station numbers by id.
+CREATE INDEX IF NOT EXISTS id_history_by_id ON id_history(id, start_time, end_time);
+
+-- Station numbers that were merged into or renumbered as another station.
+CREATE TABLE IF NOT EXISTS station_num_aliases (
+    old_num     INT UNIQUE NOT NULL, -- The station number no longer in the sites table
+    station_num INT        NOT NULL, -- The station number it resolves to
+    PRIMARY KEY (old_num),
+    FOREIGN KEY (station_num) REFERENCES sites(station_num)
+);
+
+-- Spatial index of the canonical site locations, kept up to date by the triggers below.
+CREATE VIRTUAL TABLE IF NOT EXISTS site_locations USING rtree(
+    station_num,
+    min_lat, max_lat,
+    min_lon, max_lon
+);
+
+CREATE TRIGGER IF NOT EXISTS site_locations_insert AFTER INSERT ON sites
+WHEN NEW.lat IS NOT NULL AND NEW.lon IS NOT NULL
+BEGIN
+    INSERT OR REPLACE INTO site_locations
+    VALUES (NEW.station_num, NEW.lat, NEW.lat, NEW.lon, NEW.lon);
+END;
+
+CREATE TRIGGER IF NOT EXISTS site_locations_update AFTER UPDATE OF station_num, lat, lon ON sites
+BEGIN
+    DELETE FROM site_locations WHERE station_num = OLD.station_num;
+    INSERT INTO site_locations
+    SELECT NEW.station_num, NEW.lat, NEW.lat, NEW.lon, NEW.lon
+    WHERE NEW.lat IS NOT NULL AND NEW.lon IS NOT NULL;
+END;
+
+CREATE TRIGGER IF NOT EXISTS site_locations_delete AFTER DELETE ON sites
+BEGIN
+    DELETE FROM site_locations WHERE station_num = OLD.station_num;
+END;
+
+-- Parameters derived from each forecast hour of a file, see DerivedIndexing.
+CREATE TABLE IF NOT EXISTS derived_params (
+    file_name  TEXT NOT NULL, -- The file the forecast hour is in
+    valid_time TEXT NOT NULL,
+    lead_hours INT  NOT NULL,
+    param      TEXT NOT NULL,
+    value      REAL NOT NULL,
+    PRIMARY KEY (file_name, valid_time, param),
+    FOREIGN KEY (file_name) REFERENCES files(file_name)
+);
+
+-- For fast searches by parameter value.
+CREATE INDEX IF NOT EXISTS derived_params_by_value ON derived_params(param, value);
+
+CREATE TRIGGER IF NOT EXISTS derived_params_delete AFTER DELETE ON files
+BEGIN
+    DELETE FROM derived_params WHERE file_name = OLD.file_name;
+END;
+
+-- Distributions of sounding parameters built by Archive::build_climatology.
+CREATE TABLE IF NOT EXISTS climatology (
+    station_num INT  NOT NULL,
+    model       TEXT NOT NULL,
+    month       INT  NOT NULL, -- Month of the valid times, 1 - 12
+    lead_hours  INT  NOT NULL,
+    param       TEXT NOT NULL,
+    num_samples INT  NOT NULL,
+    mean        REAL NOT NULL,
+    percentiles TEXT NOT NULL, -- JSON array of the 0th through 100th percentiles
+    PRIMARY KEY (station_num, model, month, lead_hours, param),
+    FOREIGN KEY (station_num) REFERENCES sites(station_num)
+);
+
+-- Observed surface values imported by Archive::import_surface_obs.
+CREATE TABLE IF NOT EXISTS surface_obs (
+    station_num INT  NOT NULL,
+    valid_time  TEXT NOT NULL,
+    param       TEXT NOT NULL, -- Same names as the bufkit surface parameters, e.g. T2MS
+    value       REAL NOT NULL,
+    PRIMARY KEY (station_num, valid_time, param)
+);
+
+-- Levels of observed soundings imported by Archive::import_raobs.
+CREATE TABLE IF NOT EXISTS raob_levels (
+    station_num INT  NOT NULL,
+    valid_time  TEXT NOT NULL,
+    pressure    REAL NOT NULL, -- hPa
+    height      REAL,          -- m
+    temperature REAL,          -- C
+    dew_point   REAL,          -- C
+    wind_dir    REAL,          -- degrees
+    wind_speed  REAL,          -- knots
+    PRIMARY KEY (station_num, valid_time, pressure)
+);
+
+-- Feature vectors of each forecast hour of a file, see Archive::index_analog_features.
+CREATE TABLE IF NOT EXISTS analog_features (
+    file_name   TEXT NOT NULL, -- The file the forecast hour is in
+    valid_time  TEXT NOT NULL,
+    lead_hours  INT  NOT NULL,
+    feature_set TEXT NOT NULL,
+    features    TEXT NOT NULL, -- JSON array of the feature values
+    PRIMARY KEY (file_name, valid_time, feature_set),
+    FOREIGN KEY (file_name) REFERENCES files(file_name)
+);
+
+CREATE TRIGGER IF NOT EXISTS analog_features_delete AFTER DELETE ON files
+BEGIN
+    DELETE FROM analog_features WHERE file_name = OLD.file_name;
+END;
+
+-- The members of ensembles, see Archive::add_ensemble_group.
+CREATE TABLE IF NOT EXISTS ensemble_members (
+    ensemble   TEXT NOT NULL,
+    member     TEXT NOT NULL,
+    model      TEXT NOT NULL,
+    is_control INT  NOT NULL DEFAULT 0,
+    tag        TEXT DEFAULT NULL, -- Tag in member_files, NULL for the model's regular files
+    PRIMARY KEY (ensemble, member)
+);
+
+-- Files for ensemble members that share a model, e.g. the SREF members, with a tag for the member
+-- they belong to. See Archive::add_member_file.
+CREATE TABLE IF NOT EXISTS member_files (
+    tag         TEXT        NOT NULL,
+    station_num INT         NOT NULL,
+    model       TEXT        NOT NULL,
+    init_time   TEXT        NOT NULL,
+    end_time    TEXT        NOT NULL,
+    file_name   TEXT UNIQUE NOT NULL,
+    id          TEXT,
+    lat         REAL        NOT NULL,
+    lon         REAL        NOT NULL,
+    elevation_m INT         NOT NULL,
+    PRIMARY KEY (tag, model, station_num, init_time),
+    FOREIGN KEY (station_num) REFERENCES sites(station_num)
+);
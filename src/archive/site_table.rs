@@ -0,0 +1,447 @@
+//! Import and export the sites table in bulk.
+
+use crate::{
+    archive::Archive,
+    coords::Coords,
+    errors::BufkitDataErr,
+    site::{Country, SiteInfo, SiteTimeZone, StateProv, StationNumber},
+};
+use metfor::Quantity;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    io::{Read, Write},
+    str::FromStr,
+};
+
+/// File formats for importing and exporting site metadata.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SiteTableFormat {
+    /// Comma separated values with a header row.
+    Csv,
+    /// A JSON array of objects.
+    Json,
+}
+
+impl SiteTableFormat {
+    /// Guess the format from a file extension.
+    pub fn from_path(path: &std::path::Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "csv" => Some(SiteTableFormat::Csv),
+            "json" => Some(SiteTableFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// A change to a site made during an import.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SiteUpdate {
+    /// The site before the import.
+    pub before: SiteInfo,
+    /// The site after the import.
+    pub after: SiteInfo,
+}
+
+impl SiteUpdate {
+    /// List the fields that changed with their values before and after the import.
+    pub fn changes(&self) -> Vec<(&'static str, String, String)> {
+        let before = SiteRecord::from(&self.before).fields();
+        let after = SiteRecord::from(&self.after).fields();
+
+        before
+            .into_iter()
+            .zip(after)
+            .filter(|((_, b), (_, a))| a != b)
+            .map(|((field, b), (_, a))| (field, b, a))
+            .collect()
+    }
+}
+
+/// A summary of what an import changed, or would change, in the sites table.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SiteImportReport {
+    /// Sites that were not in the archive before.
+    pub added: Vec<SiteInfo>,
+    /// Sites that were changed.
+    pub updated: Vec<SiteUpdate>,
+    /// The number of sites in the import that matched the archive already.
+    pub unchanged: usize,
+}
+
+/// A row in an imported or exported sites table.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SiteRecord {
+    station_num: u32,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    state: Option<String>,
+    #[serde(default)]
+    country: Option<String>,
+    #[serde(default)]
+    time_zone: Option<String>,
+    #[serde(default)]
+    notes: Option<String>,
+    #[serde(default)]
+    icao_id: Option<String>,
+    #[serde(default)]
+    wmo_id: Option<String>,
+    #[serde(default)]
+    lat: Option<f64>,
+    #[serde(default)]
+    lon: Option<f64>,
+    #[serde(default)]
+    elevation_m: Option<f64>,
+}
+
+impl From<&SiteInfo> for SiteRecord {
+    fn from(site: &SiteInfo) -> Self {
+        SiteRecord {
+            station_num: site.station_num.into(),
+            name: site.name.clone(),
+            state: site.state.map(|st| st.as_static_str().to_owned()),
+            country: site.country.map(|c| c.as_static_str().to_owned()),
+            time_zone: site.time_zone.map(|tz| tz.to_string()),
+            notes: site.notes.clone(),
+            icao_id: site.icao_id.clone(),
+            wmo_id: site.wmo_id.clone(),
+            lat: site.coords.map(|coords| coords.lat),
+            lon: site.coords.map(|coords| coords.lon),
+            elevation_m: site.elevation.map(|elev| elev.unpack()),
+        }
+    }
+}
+
+impl SiteRecord {
+    /// Apply the values in this record to a site. Missing or empty values leave the site
+    /// unchanged.
+    fn merge_into(self, mut site: SiteInfo) -> Result<SiteInfo, BufkitDataErr> {
+        let station_num = StationNumber::from(self.station_num);
+        let invalid = |field: &'static str, value: &str| BufkitDataErr::InvalidSiteRecord {
+            station_num,
+            field,
+            value: value.to_owned(),
+        };
+
+        // Treat blank cells from a spreadsheet the same as missing values.
+        let non_empty = |val: Option<String>| -> Option<String> {
+            val.map(|v| v.trim().to_owned()).filter(|v| !v.is_empty())
+        };
+
+        if let Some(name) = non_empty(self.name) {
+            site.name = Some(name);
+        }
+
+        if let Some(state) = non_empty(self.state) {
+            site.state = Some(
+                StateProv::from_str(&state.to_uppercase()).map_err(|_| invalid("state", &state))?,
+            );
+        }
+
+        if let Some(country) = non_empty(self.country) {
+            site.country = Some(
+                Country::from_str(&country.to_uppercase())
+                    .map_err(|_| invalid("country", &country))?,
+            );
+        }
+
+        if let Some(tz) = non_empty(self.time_zone) {
+            site.time_zone =
+                Some(SiteTimeZone::from_str(&tz).map_err(|_| invalid("time_zone", &tz))?);
+        }
+
+        if let Some(notes) = non_empty(self.notes) {
+            site.notes = Some(notes);
+        }
+
+        if let Some(icao_id) = non_empty(self.icao_id) {
+            site.icao_id = Some(icao_id.to_uppercase());
+        }
+
+        if let Some(wmo_id) = non_empty(self.wmo_id) {
+            site.wmo_id = Some(wmo_id);
+        }
+
+        match (self.lat, self.lon, site.coords) {
+            (None, None, _) => {}
+            (Some(lat), Some(lon), _) => site.coords = Some(Coords { lat, lon }),
+            (Some(lat), None, Some(coords)) => site.coords = Some(Coords { lat, ..coords }),
+            (None, Some(lon), Some(coords)) => site.coords = Some(Coords { lon, ..coords }),
+            (Some(lat), None, None) => {
+                return Err(invalid("lon", &format!("missing, lat = {}", lat)));
+            }
+            (None, Some(lon), None) => {
+                return Err(invalid("lat", &format!("missing, lon = {}", lon)));
+            }
+        }
+
+        if let Some(coords) = site.coords
+            && (!(-90.0..=90.0).contains(&coords.lat) || !(-180.0..=180.0).contains(&coords.lon))
+        {
+            return Err(invalid(
+                "lat/lon",
+                &format!("({}, {})", coords.lat, coords.lon),
+            ));
+        }
+
+        if let Some(elevation) = self.elevation_m {
+            site.elevation = Some(metfor::Meters(elevation));
+        }
+
+        Ok(site)
+    }
+
+    /// The values as strings for comparing records.
+    fn fields(self) -> [(&'static str, String); 10] {
+        let text = |val: Option<String>| val.unwrap_or_default();
+        let num = |val: Option<f64>| val.map(|v| v.to_string()).unwrap_or_default();
+
+        [
+            ("name", text(self.name)),
+            ("state", text(self.state)),
+            ("country", text(self.country)),
+            ("time_zone", text(self.time_zone)),
+            ("notes", text(self.notes)),
+            ("icao_id", text(self.icao_id)),
+            ("wmo_id", text(self.wmo_id)),
+            ("lat", num(self.lat)),
+            ("lon", num(self.lon)),
+            ("elevation_m", num(self.elevation_m)),
+        ]
+    }
+}
+
+impl Archive {
+    /// Import site metadata from a CSV or JSON table and add or update the sites in the archive.
+    ///
+    /// The only required column is `station_num`. Other columns are `name`, `state`, `country`,
+    /// `time_zone`, `notes`, `icao_id`, `wmo_id`, `lat`, `lon`, and `elevation_m`, the same as
+    /// produced by `export_sites`. Missing or empty values leave the site as it is, so a table
+    /// with only a few columns can be used. The whole table is validated before anything is
+    /// changed.
+    ///
+    /// Because empty values are ignored, an import can set or change a value but never clear it.
+    /// Use `update_site` to remove a value from a site.
+    pub fn import_sites(
+        &self,
+        reader: impl Read,
+        format: SiteTableFormat,
+    ) -> Result<SiteImportReport, BufkitDataErr> {
+        let (report, sites) = self.diff_site_table(reader, format)?;
+
+        let tx = self.db_conn.unchecked_transaction()?;
+        for site in &report.added {
+            self.add_site(site)?;
+        }
+        for site in sites {
+            self.update_site(&site)?;
+        }
+        tx.commit()?;
+
+        Ok(report)
+    }
+
+    /// Report what `import_sites` would change without modifying the archive.
+    pub fn preview_site_import(
+        &self,
+        reader: impl Read,
+        format: SiteTableFormat,
+    ) -> Result<SiteImportReport, BufkitDataErr> {
+        self.diff_site_table(reader, format)
+            .map(|(report, _)| report)
+    }
+
+    /// Export the sites table in a format that can be read by `import_sites`.
+    pub fn export_sites(
+        &self,
+        writer: impl Write,
+        format: SiteTableFormat,
+    ) -> Result<(), BufkitDataErr> {
+        let mut sites = self.sites()?;
+        sites.sort_unstable_by_key(|site| site.station_num);
+
+        let records = sites.iter().map(SiteRecord::from);
+
+        match format {
+            SiteTableFormat::Csv => {
+                let mut wtr = csv::Writer::from_writer(writer);
+                for record in records {
+                    wtr.serialize(record)?;
+                }
+                wtr.flush()?;
+            }
+            SiteTableFormat::Json => {
+                serde_json::to_writer_pretty(writer, &records.collect::<Vec<_>>())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compare a table with the archive, returning the report and the updated sites.
+    fn diff_site_table(
+        &self,
+        reader: impl Read,
+        format: SiteTableFormat,
+    ) -> Result<(SiteImportReport, Vec<SiteInfo>), BufkitDataErr> {
+        let records: Vec<SiteRecord> = match format {
+            SiteTableFormat::Csv => csv::ReaderBuilder::new()
+                .trim(csv::Trim::All)
+                .from_reader(reader)
+                .deserialize()
+                .collect::<Result<_, _>>()?,
+            SiteTableFormat::Json => serde_json::from_reader(reader)?,
+        };
+
+        let mut report = SiteImportReport::default();
+        let mut updated_sites = vec![];
+        let mut seen = HashSet::new();
+        for record in records {
+            let station_num = StationNumber::from(record.station_num);
+            if !station_num.is_valid() || !seen.insert(station_num) {
+                return Err(BufkitDataErr::InvalidSiteRecord {
+                    station_num,
+                    field: "station_num",
+                    value: station_num.to_string(),
+                });
+            }
+
+            match self.site(station_num) {
+                Some(before) => {
+                    let after = record.merge_into(before.clone())?;
+                    if after == before {
+                        report.unchanged += 1;
+                    } else {
+                        updated_sites.push(after.clone());
+                        report.updated.push(SiteUpdate { before, after });
+                    }
+                }
+                None => {
+                    let new_site = record.merge_into(SiteInfo {
+                        station_num,
+                        ..SiteInfo::default()
+                    })?;
+                    report.added.push(new_site);
+                }
+            }
+        }
+
+        Ok((report, updated_sites))
+    }
+}
+
+#[cfg(test)]
+mod unit {
+    use super::*;
+    use crate::archive::unit::*; // test helpers.
+
+    #[test]
+    fn test_import_csv() {
+        let TestArchive { tmp: _tmp, arch } =
+            create_test_archive().expect("Failed to create test archive.");
+
+        for site in &get_test_sites() {
+            arch.add_site(site).expect("Error adding site.");
+        }
+
+        let table = "\
+station_num,name,state,time_zone,icao_id
+3,,MT,America/Denver,kmso
+4,Calgary,AB,America/Edmonton,CYYC
+";
+
+        let preview = arch
+            .preview_site_import(table.as_bytes(), SiteTableFormat::Csv)
+            .expect("Error previewing import.");
+        assert!(arch.site(StationNumber::from(4)).is_none());
+
+        let report = arch
+            .import_sites(table.as_bytes(), SiteTableFormat::Csv)
+            .expect("Error importing.");
+        assert_eq!(preview, report);
+
+        assert_eq!(report.added.len(), 1);
+        assert_eq!(report.added[0].name.as_deref(), Some("Calgary"));
+        assert_eq!(report.unchanged, 0);
+        assert_eq!(report.updated.len(), 1);
+        assert_eq!(
+            report.updated[0].changes(),
+            vec![
+                ("state", "".to_owned(), "MT".to_owned()),
+                (
+                    "time_zone",
+                    "UTC-07:00".to_owned(),
+                    "America/Denver".to_owned()
+                ),
+                ("icao_id", "".to_owned(), "KMSO".to_owned()),
+            ]
+        );
+
+        let missoula = arch.site(StationNumber::from(3)).unwrap();
+        assert_eq!(missoula.name.as_deref(), Some("Missoula"));
+        assert_eq!(missoula.state, Some(StateProv::MT));
+        assert_eq!(arch.site(StationNumber::from(4)).unwrap(), report.added[0]);
+
+        // Importing again changes nothing.
+        let report = arch
+            .import_sites(table.as_bytes(), SiteTableFormat::Csv)
+            .expect("Error importing.");
+        assert_eq!(report.unchanged, 2);
+        assert!(report.added.is_empty() && report.updated.is_empty());
+    }
+
+    #[test]
+    fn test_import_invalid_changes_nothing() {
+        let TestArchive { tmp: _tmp, arch } =
+            create_test_archive().expect("Failed to create test archive.");
+
+        let table = r#"[
+            {"station_num": 5, "name": "Good"},
+            {"station_num": 6, "name": "Bad", "state": "XX"}
+        ]"#;
+
+        match arch.import_sites(table.as_bytes(), SiteTableFormat::Json) {
+            Err(BufkitDataErr::InvalidSiteRecord { field, .. }) => assert_eq!(field, "state"),
+            x => panic!("Expected an invalid record: {:?}", x),
+        }
+
+        assert!(arch.sites().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_export_import_round_trip() {
+        let TestArchive { tmp: _tmp, arch } =
+            create_test_archive().expect("Failed to create test archive.");
+        let TestArchive {
+            tmp: _tmp2,
+            arch: other,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        let mut test_sites = get_test_sites();
+        test_sites[0].coords = Some(Coords {
+            lat: 41.98,
+            lon: -87.9,
+        });
+        test_sites[0].elevation = Some(metfor::Meters(201.0));
+        test_sites[0].country = Some(Country::US);
+
+        for site in &test_sites {
+            arch.add_site(site).expect("Error adding site.");
+        }
+
+        for format in [SiteTableFormat::Csv, SiteTableFormat::Json] {
+            let mut buf = vec![];
+            arch.export_sites(&mut buf, format)
+                .expect("Error exporting.");
+
+            other
+                .import_sites(buf.as_slice(), format)
+                .expect("Error importing.");
+
+            let mut sites = other.sites().unwrap();
+            sites.sort_unstable_by_key(|site| site.station_num);
+            assert_eq!(sites, test_sites);
+        }
+    }
+}
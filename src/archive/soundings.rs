@@ -0,0 +1,233 @@
+//! Retrieve parsed soundings instead of the raw text of the files.
+
+use crate::{
+    archive::{Archive, FileRecord},
+    errors::BufkitDataErr,
+    models::Model,
+    site::StationNumber,
+};
+use sounding_analysis::Sounding;
+use std::{
+    collections::HashMap,
+    sync::{Arc, MutexGuard, PoisonError},
+};
+
+/// A sounding and the surface and station parameters valid at the same time.
+pub type ParsedSounding = (Sounding, HashMap<&'static str, f64>);
+
+/// A least recently used cache of parsed files, keyed by the file name in the data directory.
+#[derive(Debug, Default)]
+pub(crate) struct SoundingCache {
+    capacity: usize,
+    // Incremented on every use, the entry with the smallest stamp is the least recently used.
+    clock: u64,
+    entries: HashMap<String, (u64, Arc<[ParsedSounding]>)>,
+}
+
+impl SoundingCache {
+    fn get(&mut self, file_name: &str) -> Option<Arc<[ParsedSounding]>> {
+        let (stamp, soundings) = self.entries.get_mut(file_name)?;
+        self.clock += 1;
+        *stamp = self.clock;
+
+        Some(Arc::clone(soundings))
+    }
+
+    fn insert(&mut self, file_name: &str, soundings: Arc<[ParsedSounding]>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.remove(file_name);
+        self.shrink_to(self.capacity - 1);
+
+        self.clock += 1;
+        self.entries
+            .insert(file_name.to_owned(), (self.clock, soundings));
+    }
+
+    pub(crate) fn remove(&mut self, file_name: &str) {
+        self.entries.remove(file_name);
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.shrink_to(capacity);
+    }
+
+    /// Drop the least recently used entries until there are at most `len` left.
+    fn shrink_to(&mut self, len: usize) {
+        while self.entries.len() > len {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (stamp, _))| *stamp)
+                .map(|(name, _)| name.clone());
+
+            match oldest {
+                Some(name) => self.remove(&name),
+                None => break,
+            }
+        }
+    }
+}
+
+impl Archive {
+    /// Keep up to `num_files` parsed files in memory so retrieving them again skips parsing.
+    ///
+    /// The cache is off, zero files, by default. Each cached file holds every sounding in it, so
+    /// a large cache can use a lot of memory.
+    pub fn set_sounding_cache_size(&mut self, num_files: usize) {
+        self.sounding_cache
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .set_capacity(num_files);
+    }
+
+    /// Empty the cache of parsed files.
+    pub fn clear_sounding_cache(&self) {
+        self.sounding_cache().entries.clear();
+    }
+
+    /// Lock the cache of parsed files. A panic while it was locked can at worst lose an entry, so
+    /// the cache is still used after one.
+    pub(crate) fn sounding_cache(&self) -> MutexGuard<'_, SoundingCache> {
+        self.sounding_cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Retrieve and parse a file, one sounding per forecast hour in the file.
+    pub fn retrieve_soundings(
+        &self,
+        station_num: StationNumber,
+        model: Model,
+        init_time: chrono::NaiveDateTime,
+    ) -> Result<Vec<ParsedSounding>, BufkitDataErr> {
+        let record = self.file_record(station_num, model, init_time)?;
+        self.record_soundings(&record)
+    }
+
+    /// Retrieve and parse the most recent file for a site and model.
+    pub fn retrieve_most_recent_soundings(
+        &self,
+        station_num: StationNumber,
+        model: Model,
+    ) -> Result<Vec<ParsedSounding>, BufkitDataErr> {
+        let record = self
+            .inventory_records(station_num, model)?
+            .pop()
+            .ok_or(BufkitDataErr::NotInIndex)?;
+        self.record_soundings(&record)
+    }
+
+    /// Parse the file described by a record, one sounding per forecast hour in the file.
+    pub fn record_soundings(
+        &self,
+        record: &FileRecord,
+    ) -> Result<Vec<ParsedSounding>, BufkitDataErr> {
+        self.shared_soundings(record).map(|snds| snds.to_vec())
+    }
+
+    /// Parse the file described by a record, sharing the soundings with the cache instead of
+    /// copying them.
+    pub(crate) fn shared_soundings(
+        &self,
+        record: &FileRecord,
+    ) -> Result<Arc<[ParsedSounding]>, BufkitDataErr> {
+        if let Some(soundings) = self.sounding_cache().get(&record.file_name) {
+            return Ok(soundings);
+        }
+
+        let text = self.load_file(&record.file_name)?;
+        let soundings: Arc<[ParsedSounding]> =
+            sounding_bufkit::BufkitData::init(&text, &record.file_name)?
+                .into_iter()
+                .collect();
+
+        self.sounding_cache()
+            .insert(&record.file_name, Arc::clone(&soundings));
+
+        Ok(soundings)
+    }
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                          Unit Tests
+--------------------------------------------------------------------------------------------------*/
+#[cfg(test)]
+mod unit {
+    use super::*;
+    use crate::archive::unit::*; // test helpers.
+
+    #[test]
+    fn test_sounding_cache() {
+        let mut cache = SoundingCache::default();
+        let snds: Arc<[ParsedSounding]> = Arc::from(vec![(Sounding::new(), HashMap::new())]);
+
+        cache.insert("a", Arc::clone(&snds));
+        assert!(cache.get("a").is_none());
+
+        cache.set_capacity(2);
+        cache.insert("a", Arc::clone(&snds));
+        cache.insert("b", Arc::clone(&snds));
+        assert!(cache.get("a").is_some());
+        cache.insert("c", Arc::clone(&snds));
+
+        // "b" was the least recently used.
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("c").is_some());
+
+        cache.remove("a");
+        assert!(cache.get("a").is_none());
+
+        cache.set_capacity(1);
+        assert_eq!(cache.entries.len(), 1);
+    }
+
+    #[test]
+    fn test_retrieve_soundings() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+        arch.set_sounding_cache_size(4);
+
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+        let record = arch.inventory_records(kmso, Model::GFS).unwrap().remove(0);
+
+        let soundings = arch
+            .retrieve_soundings(kmso, Model::GFS, record.init_time)
+            .unwrap();
+        assert!(!soundings.is_empty());
+        assert_eq!(soundings[0].0.valid_time(), Some(record.init_time));
+        assert_eq!(
+            soundings.last().unwrap().0.valid_time(),
+            Some(record.end_time)
+        );
+
+        // Served from the cache.
+        let cached = arch.shared_soundings(&record).unwrap();
+        assert!(Arc::ptr_eq(
+            &cached,
+            &arch.shared_soundings(&record).unwrap()
+        ));
+        assert_eq!(cached.len(), soundings.len());
+
+        // Removing the file evicts it.
+        arch.remove(kmso, Model::GFS, record.init_time).unwrap();
+        assert!(arch.record_soundings(&record).is_err());
+
+        arch.clear_sounding_cache();
+        assert!(arch.sounding_cache().entries.is_empty());
+
+        let most_recent = arch
+            .retrieve_most_recent_soundings(kmso, Model::GFS)
+            .unwrap();
+        let last = arch.inventory(kmso, Model::GFS).unwrap().pop().unwrap();
+        assert_eq!(most_recent[0].0.valid_time(), Some(last));
+    }
+}
@@ -0,0 +1,181 @@
+//! Time series of surface and station parameters across model runs.
+
+use crate::{
+    archive::{Archive, FileOrder, FileQuery, hourly_params::HourlyParams},
+    errors::BufkitDataErr,
+    models::Model,
+    site::StationNumber,
+};
+use chrono::NaiveDateTime;
+use std::collections::HashSet;
+
+/// How to pick a value when more than one model run has data at the same valid time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StitchRule {
+    /// Use the value from the most recent run.
+    #[default]
+    LatestRun,
+    /// Only use values from this many hours after the initialization time.
+    LeadTime(i32),
+    /// Keep the values from every run.
+    AllRuns,
+}
+
+/// The value of a parameter at one valid time from one model run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SeriesPoint {
+    /// The time the value is valid.
+    pub valid_time: NaiveDateTime,
+    /// The initialization time of the run the value came from.
+    pub init_time: NaiveDateTime,
+    /// The hours from the initialization time to the valid time.
+    pub lead_hours: i32,
+    /// The value.
+    pub value: f64,
+}
+
+impl Archive {
+    /// Get a time series of a surface or station parameter for all the valid times between `start`
+    /// and `end`, inclusive, sorted by valid time and then initialization time.
+    ///
+    /// The parameter is the name used in the file, e.g. T2MS or P01M from the surface section, or
+    /// PWAT or CAPE from the station parameters, ignoring case. Not every model outputs the same
+    /// parameters, and valid times where the parameter is missing are left out.
+    pub fn param_time_series(
+        &self,
+        station_num: StationNumber,
+        model: Model,
+        param: &str,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+        rule: StitchRule,
+    ) -> Result<Vec<SeriesPoint>, BufkitDataErr> {
+        let param = param.to_uppercase();
+
+        let query = FileQuery::new()
+            .station(station_num)
+            .model(model)
+            .valid_times(start, end)
+            .order(FileOrder::NewestFirst);
+
+        let mut series: Vec<SeriesPoint> = vec![];
+        let mut seen: HashSet<NaiveDateTime> = HashSet::new();
+        for file in self.retrieve_files(&query)? {
+            let (record, text) = file?;
+
+            for (valid_time, vals) in HourlyParams::parse(&text)?.hours {
+                if valid_time < start || valid_time > end {
+                    continue;
+                }
+
+                let value = match vals.get(&param) {
+                    Some(&value) => value,
+                    None => continue,
+                };
+
+                let lead_hours = (valid_time - record.init_time).num_hours() as i32;
+                let keep = match rule {
+                    // Newest runs come first, so only keep the first value at each time.
+                    StitchRule::LatestRun => seen.insert(valid_time),
+                    StitchRule::LeadTime(hours) => lead_hours == hours,
+                    StitchRule::AllRuns => true,
+                };
+
+                if keep {
+                    series.push(SeriesPoint {
+                        valid_time,
+                        init_time: record.init_time,
+                        lead_hours,
+                        value,
+                    });
+                }
+            }
+        }
+
+        series.sort_by_key(|pnt| (pnt.valid_time, pnt.init_time));
+
+        Ok(series)
+    }
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                          Unit Tests
+--------------------------------------------------------------------------------------------------*/
+#[cfg(test)]
+mod unit {
+    use super::*;
+    use crate::archive::unit::*; // test helpers.
+
+    use chrono::NaiveDate;
+
+    fn time(day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2017, 4, day)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_param_time_series() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+        let (start, end) = (time(1, 6), time(2, 6));
+
+        let latest = arch
+            .param_time_series(kmso, Model::GFS, "t2ms", start, end, StitchRule::LatestRun)
+            .unwrap();
+        assert_eq!(latest.first().unwrap().valid_time, start);
+        assert_eq!(latest.last().unwrap().valid_time, end);
+        assert!(latest.windows(2).all(|w| w[0].valid_time < w[1].valid_time));
+        for pnt in &latest {
+            let expected = if pnt.valid_time >= time(1, 18) {
+                time(1, 18)
+            } else if pnt.valid_time >= time(1, 12) {
+                time(1, 12)
+            } else {
+                time(1, 6)
+            };
+            assert_eq!(pnt.init_time, expected);
+            assert_eq!(
+                pnt.valid_time - pnt.init_time,
+                chrono::Duration::hours(pnt.lead_hours as i64)
+            );
+        }
+
+        let all = arch
+            .param_time_series(kmso, Model::GFS, "T2MS", start, end, StitchRule::AllRuns)
+            .unwrap();
+        assert!(all.len() > latest.len());
+        assert_eq!(all.iter().filter(|pnt| pnt.valid_time == end).count(), 3);
+
+        let fixed = arch
+            .param_time_series(
+                kmso,
+                Model::GFS,
+                "PWAT",
+                start,
+                end,
+                StitchRule::LeadTime(6),
+            )
+            .unwrap();
+        let inits: Vec<_> = fixed.iter().map(|pnt| pnt.init_time).collect();
+        assert_eq!(inits, vec![time(1, 6), time(1, 12), time(1, 18)]);
+
+        let nam_precip = arch
+            .param_time_series(kmso, Model::NAM, "P01M", start, end, StitchRule::LatestRun)
+            .unwrap();
+        assert!(!nam_precip.is_empty());
+
+        assert!(
+            arch.param_time_series(kmso, Model::GFS, "NOPE", start, end, StitchRule::LatestRun)
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,122 @@
+//! Retrieve the soundings from every model run valid at a single time.
+
+use crate::{
+    archive::{Archive, FileOrder, FileQuery, FileRecord},
+    errors::BufkitDataErr,
+    models::Model,
+    site::StationNumber,
+};
+use chrono::{Duration, NaiveDateTime};
+use sounding_analysis::Sounding;
+use std::collections::HashMap;
+
+/// A sounding from one model run along with the file it came from.
+#[derive(Clone, Debug)]
+pub struct RunSounding {
+    /// The file the sounding was parsed from.
+    pub record: FileRecord,
+    /// The time from the model initialization time to the valid time of the sounding.
+    pub lead_time: Duration,
+    /// The parsed sounding.
+    pub sounding: Sounding,
+    /// The surface and station parameters that go with the sounding.
+    pub surface: HashMap<&'static str, f64>,
+}
+
+impl Archive {
+    /// Get the sounding valid at `valid_time` from every run of a model, shortest lead time first.
+    ///
+    /// Runs that cover the valid time but do not have a sounding at exactly that time, e.g. a
+    /// model with 3 hourly output late in the forecast, are skipped. Returns `NotInIndex` if no
+    /// runs cover the valid time.
+    pub fn soundings_valid_at(
+        &self,
+        station_num: StationNumber,
+        model: Model,
+        valid_time: NaiveDateTime,
+    ) -> Result<Vec<RunSounding>, BufkitDataErr> {
+        let query = FileQuery::new()
+            .station(station_num)
+            .model(model)
+            .valid_times(valid_time, valid_time)
+            .order(FileOrder::NewestFirst);
+
+        let records = self.file_records(&query)?;
+        if records.is_empty() {
+            return Err(BufkitDataErr::NotInIndex);
+        }
+
+        let mut soundings = Vec::with_capacity(records.len());
+        for record in records {
+            let found = self
+                .shared_soundings(&record)?
+                .iter()
+                .find(|(snd, _)| snd.valid_time() == Some(valid_time))
+                .cloned();
+
+            if let Some((sounding, surface)) = found {
+                soundings.push(RunSounding {
+                    lead_time: valid_time - record.init_time,
+                    record,
+                    sounding,
+                    surface,
+                });
+            }
+        }
+
+        Ok(soundings)
+    }
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                          Unit Tests
+--------------------------------------------------------------------------------------------------*/
+#[cfg(test)]
+mod unit {
+    use super::*;
+    use crate::archive::unit::*; // test helpers.
+
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_soundings_valid_at() {
+        let TestArchive {
+            tmp: _tmp,
+            mut arch,
+        } = create_test_archive().expect("Failed to create test archive.");
+
+        fill_test_archive(&mut arch);
+
+        let kmso = StationNumber::from(727730); // Station number for KMSO
+        let valid_time = NaiveDate::from_ymd_opt(2017, 4, 2)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+
+        let soundings = arch
+            .soundings_valid_at(kmso, Model::GFS, valid_time)
+            .unwrap();
+        assert_eq!(soundings.len(), 3);
+
+        let lead_hours: Vec<_> = soundings
+            .iter()
+            .map(|snd| snd.lead_time.num_hours())
+            .collect();
+        assert_eq!(lead_hours, vec![6, 12, 18]);
+
+        for snd in &soundings {
+            assert_eq!(snd.sounding.valid_time(), Some(valid_time));
+            assert_eq!(snd.record.init_time + snd.lead_time, valid_time);
+            assert!(!snd.surface.is_empty());
+        }
+
+        let before_any = NaiveDate::from_ymd_opt(2017, 3, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        match arch.soundings_valid_at(kmso, Model::GFS, before_any) {
+            Err(BufkitDataErr::NotInIndex) => {}
+            x => panic!("Should not be found: {:?}", x.map(|v| v.len())),
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! Offline lookup of the state/providence, country, and time zone for a location.
+//!
+//! The lookup uses simplified boundary polygons embedded in the binary. The state, providence, and
+//! country boundaries are derived from OpenStreetMap data, © OpenStreetMap contributors, and are
+//! licensed under the Open Database License (ODbL).
+//!
+//! FIXME: The boundary data has U.S. states and Canadian provinces, but not Mexican states, so the
+//! state of a location in Mexico is not filled in yet even though `StateProv` has the Mexican
+//! states. That needs boundary data with them to be added.
+
+use crate::{
+    coords::Coords,
+    site::{Country, SiteTimeZone, StateProv},
+};
+use country_boundaries::{BOUNDARIES_ODBL_60X30, CountryBoundaries, LatLon};
+use std::{str::FromStr, sync::OnceLock};
+use tzf_rs::DefaultFinder;
+
+/// What is known about a location from the boundary data.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct Location {
+    pub state: Option<StateProv>,
+    pub country: Option<Country>,
+    pub time_zone: Option<SiteTimeZone>,
+}
+
+/// Find the state/providence, country, and time zone for a location.
+pub(crate) fn locate(coords: Coords) -> Location {
+    let mut location = Location::default();
+
+    if let (Some(boundaries), Ok(position)) =
+        (political_boundaries(), LatLon::new(coords.lat, coords.lon))
+    {
+        for id in boundaries.ids(position) {
+            match id.split_once('-') {
+                Some((country, state)) => {
+                    // Make sure codes from other countries aren't mistaken for a state/providence.
+                    if let (Ok(country), Ok(state)) =
+                        (Country::from_str(country), StateProv::from_str(state))
+                        && state.country() == country
+                    {
+                        location.state = location.state.or(Some(state));
+                    }
+                }
+                None => {
+                    if let Ok(country) = Country::from_str(id) {
+                        location.country = location.country.or(Some(country));
+                    } else if let Ok(territory) = StateProv::from_str(id) {
+                        // U.S. territories have their own country code.
+                        location.state = location.state.or(Some(territory));
+                        location.country = location.country.or(Some(territory.country()));
+                    }
+                }
+            }
+        }
+    }
+
+    let tz_name = time_zone_finder().get_tz_name(coords.lon, coords.lat);
+    location.time_zone = SiteTimeZone::from_str(tz_name).ok();
+
+    location
+}
+
+fn political_boundaries() -> Option<&'static CountryBoundaries> {
+    static BOUNDARIES: OnceLock<Option<CountryBoundaries>> = OnceLock::new();
+
+    BOUNDARIES
+        .get_or_init(|| CountryBoundaries::from_reader(BOUNDARIES_ODBL_60X30).ok())
+        .as_ref()
+}
+
+fn time_zone_finder() -> &'static DefaultFinder {
+    static FINDER: OnceLock<DefaultFinder> = OnceLock::new();
+
+    FINDER.get_or_init(DefaultFinder::new)
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                          Unit Tests
+--------------------------------------------------------------------------------------------------*/
+#[cfg(test)]
+mod unit {
+    use super::*;
+
+    #[test]
+    fn test_locate() {
+        let missoula = locate(Coords {
+            lat: 46.92,
+            lon: -114.09,
+        });
+        assert_eq!(missoula.state, Some(StateProv::MT));
+        assert_eq!(missoula.country, Some(Country::US));
+        assert_eq!(missoula.time_zone, Some(chrono_tz::America::Denver.into()));
+
+        let calgary = locate(Coords {
+            lat: 51.11,
+            lon: -114.02,
+        });
+        assert_eq!(calgary.state, Some(StateProv::AB));
+        assert_eq!(calgary.country, Some(Country::CA));
+        assert_eq!(calgary.time_zone, Some(chrono_tz::America::Edmonton.into()));
+
+        let provinces = [
+            ((49.25, -123.1), StateProv::BC),
+            ((50.45, -104.61), StateProv::SK),
+            ((49.9, -97.14), StateProv::MB),
+            ((43.7, -79.4), StateProv::ON),
+            ((45.5, -73.57), StateProv::QC),
+            ((45.96, -66.64), StateProv::NB),
+            ((44.65, -63.57), StateProv::NS),
+            ((46.24, -63.13), StateProv::PE),
+            ((47.56, -52.71), StateProv::NL),
+            ((60.72, -135.05), StateProv::YT),
+            ((62.45, -114.37), StateProv::NT),
+            ((63.75, -68.52), StateProv::NU),
+        ];
+        for ((lat, lon), state) in provinces {
+            let location = locate(Coords { lat, lon });
+            assert_eq!(location.state, Some(state));
+            assert_eq!(location.country, Some(Country::CA));
+        }
+
+        let hermosillo = locate(Coords {
+            lat: 29.07,
+            lon: -110.95,
+        });
+        assert_eq!(hermosillo.country, Some(Country::MX));
+        assert_eq!(
+            hermosillo.time_zone,
+            Some(chrono_tz::America::Hermosillo.into())
+        );
+
+        let san_juan = locate(Coords {
+            lat: 18.43,
+            lon: -66.0,
+        });
+        assert_eq!(san_juan.state, Some(StateProv::PR));
+        assert_eq!(san_juan.country, Some(Country::US));
+    }
+}
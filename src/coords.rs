@@ -1,12 +1,51 @@
-//! Latitude and longitude coordinates for internal use only. For now.
+//! Latitude and longitude coordinates.
+
+#[cfg(feature = "pylib")]
+use pyo3::prelude::*;
 
 /// The latitude and longitude
+#[cfg_attr(feature = "pylib", pyclass(module = "bufkit_data"))]
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Coords {
+    /// Latitude in degrees, positive north.
     pub lat: f64,
+    /// Longitude in degrees, positive east.
     pub lon: f64,
 }
 
+impl Coords {
+    /// The mean radius of the earth in kilometers.
+    pub const EARTH_RADIUS_KM: f64 = 6371.0088;
+
+    /// The great circle distance to another point in kilometers.
+    pub fn distance_km(self, other: Coords) -> f64 {
+        let dlat = (other.lat - self.lat).to_radians();
+        let dlon = (other.lon - self.lon).to_radians();
+
+        let lat1 = self.lat.to_radians();
+        let lat2 = other.lat.to_radians();
+
+        let a = f64::powi(f64::sin(dlat / 2.0), 2)
+            + f64::powi(f64::sin(dlon / 2.0), 2) * f64::cos(lat1) * f64::cos(lat2);
+
+        2.0 * Self::EARTH_RADIUS_KM * f64::asin(f64::sqrt(a).min(1.0))
+    }
+
+    /// The initial bearing of the great circle path to another point in degrees clockwise from
+    /// north, in the range [0, 360).
+    pub fn bearing_to(self, other: Coords) -> f64 {
+        let dlon = (other.lon - self.lon).to_radians();
+
+        let lat1 = self.lat.to_radians();
+        let lat2 = other.lat.to_radians();
+
+        let y = f64::sin(dlon) * f64::cos(lat2);
+        let x = f64::cos(lat1) * f64::sin(lat2) - f64::sin(lat1) * f64::cos(lat2) * f64::cos(dlon);
+
+        f64::atan2(y, x).to_degrees().rem_euclid(360.0)
+    }
+}
+
 impl From<(f64, f64)> for Coords {
     fn from(pair: (f64, f64)) -> Self {
         Self {
@@ -15,3 +54,52 @@ impl From<(f64, f64)> for Coords {
         }
     }
 }
+
+impl From<Coords> for (f64, f64) {
+    fn from(coords: Coords) -> Self {
+        (coords.lat, coords.lon)
+    }
+}
+
+#[cfg(feature = "pylib")]
+#[pymethods]
+impl Coords {
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("Coords({}, {})", self.lat, self.lon))
+    }
+
+    #[getter]
+    fn get_lat(&self) -> f64 {
+        self.lat
+    }
+
+    #[getter]
+    fn get_lon(&self) -> f64 {
+        self.lon
+    }
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                          Unit Tests
+--------------------------------------------------------------------------------------------------*/
+#[cfg(test)]
+mod unit {
+    use super::*;
+
+    #[test]
+    fn test_distance_and_bearing() {
+        let missoula = Coords::from((46.92, -114.09));
+        let great_falls = Coords::from((47.47, -111.38));
+
+        let dist = missoula.distance_km(great_falls);
+        assert!((dist - 213.0).abs() < 2.0, "{}", dist);
+
+        let bearing = missoula.bearing_to(great_falls);
+        assert!((bearing - 73.0).abs() < 2.0, "{}", bearing);
+        assert!((great_falls.bearing_to(missoula) - 255.0).abs() < 2.0);
+
+        let north_pole = Coords::from((90.0, 0.0));
+        assert_eq!(missoula.distance_km(missoula), 0.0);
+        assert!(missoula.bearing_to(north_pole).abs() < 1.0e-9);
+    }
+}
@@ -17,6 +17,10 @@ pub enum BufkitDataErr {
     // Other forwarded errors
     /// Database error
     Database(rusqlite::Error),
+    /// Error reading or writing CSV
+    Csv(csv::Error),
+    /// Error reading or writing JSON
+    Json(serde_json::Error),
     /// Error forwarded from the strum crate
     StrumError(strum::ParseError),
     /// General error with any cause information erased and replaced by a string
@@ -56,6 +60,17 @@ pub enum BufkitDataErr {
         /// The inizialization time that was parsed from the file.
         parsed: chrono::NaiveDateTime,
     },
+    /// A site record had a value that could not be parsed.
+    InvalidSiteRecord {
+        /// The station number of the record.
+        station_num: crate::StationNumber,
+        /// The name of the field with the invalid value.
+        field: &'static str,
+        /// The invalid value.
+        value: String,
+    },
+    /// A region could not be parsed from GeoJSON or WKT.
+    InvalidRegion(String),
 }
 
 impl Display for BufkitDataErr {
@@ -69,6 +84,8 @@ impl Display for BufkitDataErr {
             IO(err) => write!(f, "std lib io error: {}", err),
 
             Database(err) => write!(f, "database error: {}", err),
+            Csv(err) => write!(f, "csv error: {}", err),
+            Json(err) => write!(f, "json error: {}", err),
             StrumError(err) => write!(f, "error forwarded from strum crate: {}", err),
             GeneralError(msg) => write!(f, "general error forwarded: {}", msg),
 
@@ -83,6 +100,16 @@ impl Display for BufkitDataErr {
             }
             MismatchedStationNumbers { .. } => write!(f, "mismatched station numbers"),
             MismatchedInitializationTimes { .. } => write!(f, "mismatched initialization times"),
+            InvalidSiteRecord {
+                station_num,
+                field,
+                value,
+            } => write!(
+                f,
+                "invalid value for {} in site record {}: {}",
+                field, station_num, value
+            ),
+            InvalidRegion(msg) => write!(f, "invalid region: {}", msg),
         }
     }
 }
@@ -113,6 +140,18 @@ impl From<rusqlite::Error> for BufkitDataErr {
     }
 }
 
+impl From<csv::Error> for BufkitDataErr {
+    fn from(err: csv::Error) -> BufkitDataErr {
+        BufkitDataErr::Csv(err)
+    }
+}
+
+impl From<serde_json::Error> for BufkitDataErr {
+    fn from(err: serde_json::Error) -> BufkitDataErr {
+        BufkitDataErr::Json(err)
+    }
+}
+
 impl From<strum::ParseError> for BufkitDataErr {
     fn from(err: strum::ParseError) -> BufkitDataErr {
         BufkitDataErr::StrumError(err)
@@ -8,6 +8,10 @@
 //! track of files stored in a common directory. The files are compressed, and so should only be
 //! accessed via the API provided by this crate.
 //!
+//! The state/providence and country of new sites are inferred from boundary data derived from
+//! [OpenStreetMap](https://www.openstreetmap.org/copyright), © OpenStreetMap contributors,
+//! available under the Open Database License.
+//!
 //! ## Python integration
 //! When compiled with the `pylib` feature it minimally supports access from Python. At this time it
 //! only supports reading files from the archive.
@@ -42,10 +46,19 @@
 //
 // Public API
 //
-pub use crate::archive::{Archive, StationSummary};
+pub use crate::archive::{
+    Analog, AnalogFeatures, Archive, Climatology, ClimoParam, DerivedIndexing, DerivedParams,
+    DistanceMetric, DprogDt, EnsembleGroup, EnsembleMember, EnsembleRun, EnsembleStat,
+    EnsembleStats, FileIter, FileOrder, FileQuery, FileRecord, HazardIndices, IdHistoryEntry,
+    ModelComparison, NearbyStation, ParsedSounding, PrecipType, QpfRecord, RunSounding, Season,
+    SeriesPoint, SiteImportReport, SiteTableFormat, SiteUpdate, StationMatch, StationMatchKind,
+    StationSummary, StitchRule, TimeAlignment, VerificationStats, VerifyParam,
+};
+pub use crate::coords::Coords;
 pub use crate::errors::BufkitDataErr;
 pub use crate::models::Model;
-pub use crate::site::{SiteInfo, StateProv, StationNumber};
+pub use crate::region::Region;
+pub use crate::site::{Country, SiteInfo, SiteTimeZone, StateProv, StationNumber};
 
 
 //
@@ -61,12 +74,17 @@ mod bufkit_data {
 
     #[pymodule_export]
     use crate::{
-        archive::{Archive, StationSummary},
+        archive::{Archive, FileRecord, NearbyStation, StationSummary},
+        coords::Coords,
         models::Model,
         site::{SiteInfo, StationNumber},
     };
 
-    use crate::errors::BufkitDataErr;
+    use crate::{
+        archive::{ClimoParam, StitchRule, TimeAlignment},
+        errors::BufkitDataErr,
+        region::Region,
+    };
 
     use chrono:: NaiveDateTime;
     use pyo3::{ exceptions, prelude::*, IntoPyObjectExt};
@@ -120,10 +138,183 @@ mod bufkit_data {
             let model = Model::from_str(model).map_err(BufkitDataErr::from)?;
 
             self.retrieve_all_valid_in(station_num, model, start, end)
-                .map(|iter| iter.collect())
+                .and_then(|iter| iter.map(|res| res.map(|(_, text)| text)).collect())
                 .map_err(Into::into)
         }
 
+        /// Get the metadata for all the files for a site and model, oldest first.
+        fn file_inventory(
+            &self,
+            station_num: StationNumber,
+            model: &str,
+        ) -> PyResult<Vec<FileRecord>> {
+            let model = Model::from_str(model).map_err(BufkitDataErr::from)?;
+            self.inventory_records(station_num, model)
+                .map_err(Into::into)
+        }
+
+        /// Get the derived parameters in the index for a file as a list of
+        /// (valid time, lead hours, {param: value}).
+        fn derived_params_for(
+            &self,
+            station_num: StationNumber,
+            model: &str,
+            init_time: NaiveDateTime,
+        ) -> PyResult<Vec<(NaiveDateTime, i32, std::collections::HashMap<String, f64>)>> {
+            let model = Model::from_str(model).map_err(BufkitDataErr::from)?;
+            let record = self.file_record(station_num, model, init_time)?;
+
+            Ok(self
+                .derived_params(&record)?
+                .into_iter()
+                .map(|hour| (hour.valid_time, hour.lead_hours, hour.values))
+                .collect())
+        }
+
+        /// Get a time series of a surface or station parameter as a list of
+        /// (valid time, init time, lead hours, value). By default the most recent run is used at
+        /// each valid time, if `lead_hours` is given only values with that lead time are used.
+        #[pyo3(signature = (station_num, model, param, start, end, lead_hours=None))]
+        fn param_time_series_for(
+            &self,
+            station_num: StationNumber,
+            model: &str,
+            param: &str,
+            start: NaiveDateTime,
+            end: NaiveDateTime,
+            lead_hours: Option<i32>,
+        ) -> PyResult<Vec<(NaiveDateTime, NaiveDateTime, i32, f64)>> {
+            let model = Model::from_str(model).map_err(BufkitDataErr::from)?;
+            let rule = lead_hours.map(StitchRule::LeadTime).unwrap_or_default();
+
+            Ok(self
+                .param_time_series(station_num, model, param, start, end, rule)?
+                .into_iter()
+                .map(|pnt| (pnt.valid_time, pnt.init_time, pnt.lead_hours, pnt.value))
+                .collect())
+        }
+
+        /// Get the precipitation forecast by a model run between `start` and `end` as
+        /// (total mm, convective mm, complete).
+        fn qpf_for(
+            &self,
+            station_num: StationNumber,
+            model: &str,
+            init_time: NaiveDateTime,
+            start: NaiveDateTime,
+            end: NaiveDateTime,
+        ) -> PyResult<(f64, Option<f64>, bool)> {
+            let model = Model::from_str(model).map_err(BufkitDataErr::from)?;
+            let qpf = self.run_qpf(station_num, model, init_time, start, end)?;
+
+            Ok((qpf.precip_mm, qpf.convective_mm, qpf.complete))
+        }
+
+        /// Get the percentile of a value in a climatology built with `build_climatology`. The
+        /// parameter is one of pwat, t500, fzlv, or cape.
+        fn climatology_percentile_of(
+            &self,
+            station_num: StationNumber,
+            model: &str,
+            month: u32,
+            lead_hours: i32,
+            param: &str,
+            value: f64,
+        ) -> PyResult<f64> {
+            let model = Model::from_str(model).map_err(BufkitDataErr::from)?;
+            let param = ClimoParam::from_str(param).map_err(BufkitDataErr::from)?;
+
+            Ok(self
+                .climatology(station_num, model, month, lead_hours, param)?
+                .percentile_of(value))
+        }
+
+        /// Get the forecasts of a parameter valid at a time from every run of the models as a list
+        /// of (model, initialization time, value), oldest run first.
+        fn dprog_dt_for(
+            &self,
+            station_num: StationNumber,
+            models: Vec<String>,
+            param: &str,
+            valid_time: NaiveDateTime,
+        ) -> PyResult<Vec<(&'static str, NaiveDateTime, f64)>> {
+            let models = models
+                .iter()
+                .map(|model| Model::from_str(model))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(BufkitDataErr::from)?;
+
+            Ok(self
+                .multi_model_dprog_dt(station_num, &models, param, valid_time)?
+                .forecasts
+                .into_iter()
+                .map(|(model, pnt)| (model.as_static_str(), pnt.init_time, pnt.value))
+                .collect())
+        }
+
+        /// Line up a parameter from the most recent runs of several models as a list of
+        /// (valid time, [value per model]). Times only some models have are interpolated if
+        /// `interpolate` is true, and left out otherwise.
+        #[pyo3(signature = (station_num, models, param, interpolate=false))]
+        fn compare_params_for(
+            &self,
+            station_num: StationNumber,
+            models: Vec<String>,
+            param: &str,
+            interpolate: bool,
+        ) -> PyResult<Vec<(NaiveDateTime, Vec<f64>)>> {
+            let runs = models
+                .iter()
+                .map(|model| Model::from_str(model).map(|model| (model, None)))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(BufkitDataErr::from)?;
+            let alignment = if interpolate {
+                TimeAlignment::Interpolate
+            } else {
+                TimeAlignment::SharedTimes
+            };
+
+            Ok(self
+                .compare_params(station_num, &runs, param, alignment)?
+                .times)
+        }
+
+        /// Get the hazard indices for each forecast hour of a file as a list of (valid time,
+        /// lead hours, Haines, hot-dry-windy, precipitation type, snow ratio).
+        fn hazards_for(
+            &self,
+            station_num: StationNumber,
+            model: &str,
+            init_time: NaiveDateTime,
+        ) -> PyResult<
+            Vec<(
+                NaiveDateTime,
+                i32,
+                Option<f64>,
+                Option<f64>,
+                Option<&'static str>,
+                Option<f64>,
+            )>,
+        > {
+            let model = Model::from_str(model).map_err(BufkitDataErr::from)?;
+            let record = self.file_record(station_num, model, init_time)?;
+
+            Ok(self
+                .hazard_indices(&record)?
+                .into_iter()
+                .map(|hr| {
+                    (
+                        hr.valid_time,
+                        hr.lead_hours,
+                        hr.haines,
+                        hr.hot_dry_windy,
+                        hr.precip_type.map(|tp| tp.as_static_str()),
+                        hr.snow_ratio,
+                    )
+                })
+                .collect())
+        }
+
         fn id_to_station_num(&self, id: &str, model: &str) -> PyResult<StationNumber> {
             let model = Model::from_str(model).map_err(BufkitDataErr::from)?;
             self.station_num_for_id(id, model).map_err(Into::into)
@@ -137,6 +328,10 @@ mod bufkit_data {
             }
         }
 
+        fn id_to_station_num_any_model(&self, id: &str) -> PyResult<StationNumber> {
+            self.station_num_for_id_any_model(id).map_err(Into::into)
+        }
+
         fn all_ids(&self, station_num: StationNumber, model: &str) -> PyResult<Vec<String>> {
             let model = Model::from_str(model).map_err(BufkitDataErr::from)?;
             self.ids(station_num, model).map_err(Into::into)
@@ -166,6 +361,53 @@ mod bufkit_data {
             
             Ok(result)
         }
+
+        /// Get a summary of all the stations with files inside a latitude and longitude box.
+        fn get_station_summaries_in_box(
+            &self,
+            min_lat: f64,
+            min_lon: f64,
+            max_lat: f64,
+            max_lon: f64,
+        ) -> PyResult<Vec<StationSummary>> {
+            self.station_summaries_in_box(min_lat, min_lon, max_lat, max_lon)
+                .map_err(Into::into)
+        }
+
+        /// Get a summary of all the stations with files inside a GeoJSON or WKT polygon.
+        fn get_station_summaries_in_region(&self, region: &str) -> PyResult<Vec<StationSummary>> {
+            let region = Region::from_str(region)?;
+            self.station_summaries_in(&region).map_err(Into::into)
+        }
+
+        /// Get all the sites within a radius in kilometers of a point, closest first.
+        fn get_stations_within(
+            &self,
+            lat: f64,
+            lon: f64,
+            radius_km: f64,
+        ) -> PyResult<Vec<NearbyStation>> {
+            self.stations_within(lat, lon, radius_km)
+                .map_err(Into::into)
+        }
+
+        /// Get the n sites closest to a point, optionally only those with files for a model.
+        #[pyo3(signature = (lat, lon, n, model=None))]
+        fn get_nearest_stations(
+            &self,
+            lat: f64,
+            lon: f64,
+            n: usize,
+            model: Option<&str>,
+        ) -> PyResult<Vec<NearbyStation>> {
+            let model = model
+                .map(Model::from_str)
+                .transpose()
+                .map_err(BufkitDataErr::from)?;
+
+            self.nearest_stations(lat, lon, n, model)
+                .map_err(Into::into)
+        }
     }
 
     #[pyfunction]
@@ -200,7 +442,9 @@ mod bufkit_data {
 }
 
 mod archive;
+mod boundaries;
 mod coords;
 mod errors;
 mod models;
+mod region;
 mod site;
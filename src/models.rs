@@ -29,6 +29,10 @@ pub enum Model {
     /// The high resolution nest of the `NAM`
     #[strum(to_string = "nam4km", serialize = "NAM4KM")]
     NAM4KM,
+    /// The U.S. Short Range Ensemble Forecast system. Each run has many members, see
+    /// `EnsembleGroup`.
+    #[strum(to_string = "sref", serialize = "SREF")]
+    SREF,
 }
 
 impl fmt::Display for Model {
@@ -39,6 +43,7 @@ impl fmt::Display for Model {
             GFS => write!(f, "{}", stringify!(GFS)),
             NAM => write!(f, "{}", stringify!(NAM)),
             NAM4KM => write!(f, "{}", stringify!(NAM4KM)),
+            SREF => write!(f, "{}", stringify!(SREF)),
         }
     }
 }
@@ -47,7 +52,7 @@ impl Model {
     /// Get the number of hours between runs.
     pub fn hours_between_runs(self) -> i64 {
         match self {
-            Model::GFS | Model::NAM | Model::NAM4KM => 6,
+            Model::GFS | Model::NAM | Model::NAM4KM | Model::SREF => 6,
         }
     }
 
@@ -59,6 +64,7 @@ impl Model {
     pub fn base_hour(self) -> i64 {
         match self {
             Model::GFS | Model::NAM | Model::NAM4KM => 0,
+            Model::SREF => 3,
         }
     }
 
@@ -116,7 +122,7 @@ impl Model {
 mod unit {
     use super::*;
 
-    use chrono::NaiveDate;
+    use chrono::{NaiveDate, Timelike};
 
     #[test]
     fn test_all_runs() {
@@ -195,4 +201,29 @@ mod unit {
             })
             .for_each(|rt| assert!(rt >= *end && rt <= *start));
     }
+
+    #[test]
+    fn test_sref_runs() {
+        assert_eq!(Model::SREF.hours_between_runs(), 6);
+        assert_eq!(Model::SREF.base_hour(), 3);
+
+        let start = &NaiveDate::from_ymd_opt(2018, 9, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let end = &NaiveDate::from_ymd_opt(2018, 9, 2)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let hours: Vec<u32> = Model::SREF
+            .all_runs(start, end)
+            .map(|rt| rt.hour())
+            .collect();
+        assert_eq!(hours, vec![3, 9, 15, 21]);
+
+        let runs: Vec<_> = Model::SREF.all_runs(end, start).collect();
+        assert_eq!(runs.len(), 4);
+        assert!(runs.windows(2).all(|w| w[0] > w[1]));
+        assert!(runs.iter().all(|rt| rt.hour() % 6 == 3));
+    }
 }
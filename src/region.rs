@@ -0,0 +1,389 @@
+//! Geographic regions for selecting stations.
+
+use crate::{coords::Coords, errors::BufkitDataErr};
+use std::str::FromStr;
+
+/// An area on the map made of one or more polygons, e.g. a county warning area.
+///
+/// Polygons are treated as flat in latitude and longitude, and they may not cross the
+/// antimeridian.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Region {
+    polygons: Vec<Polygon>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Polygon {
+    exterior: Vec<Coords>,
+    holes: Vec<Vec<Coords>>,
+}
+
+/// Coordinates as nested lists, the common structure of GeoJSON and WKT.
+enum Nested {
+    Position(Vec<f64>),
+    List(Vec<Nested>),
+}
+
+impl Region {
+    /// Create a region from a latitude and longitude box.
+    pub fn from_bounds(min_lat: f64, min_lon: f64, max_lat: f64, max_lon: f64) -> Self {
+        let exterior = vec![
+            Coords::from((min_lat, min_lon)),
+            Coords::from((min_lat, max_lon)),
+            Coords::from((max_lat, max_lon)),
+            Coords::from((max_lat, min_lon)),
+        ];
+
+        Region {
+            polygons: vec![Polygon {
+                exterior,
+                holes: vec![],
+            }],
+        }
+    }
+
+    /// Parse a region from a GeoJSON Polygon or MultiPolygon.
+    ///
+    /// A Feature, FeatureCollection, or GeometryCollection is the union of all the polygons it
+    /// holds.
+    pub fn from_geojson(text: &str) -> Result<Self, BufkitDataErr> {
+        let value: serde_json::Value = serde_json::from_str(text)?;
+
+        let mut polygons = vec![];
+        collect_geojson_polygons(&value, &mut polygons)?;
+
+        Self::from_polygons(polygons)
+    }
+
+    /// Parse a region from a WKT POLYGON or MULTIPOLYGON.
+    pub fn from_wkt(text: &str) -> Result<Self, BufkitDataErr> {
+        let text = text.trim();
+        let split = text
+            .find('(')
+            .ok_or_else(|| invalid("missing coordinates in WKT"))?;
+        let (kind, coords) = text.split_at(split);
+
+        let mut chars = coords.chars().peekable();
+        let nested = parse_wkt_list(&mut chars)?;
+        if chars.any(|c| !c.is_whitespace()) {
+            return Err(invalid("unexpected text after WKT coordinates"));
+        }
+
+        let polygons = match kind.trim().to_uppercase().as_str() {
+            "POLYGON" => vec![Polygon::from_nested(nested)?],
+            "MULTIPOLYGON" => Polygon::from_nested_multi(nested)?,
+            other => return Err(invalid(&format!("unsupported WKT type {}", other))),
+        };
+
+        Self::from_polygons(polygons)
+    }
+
+    /// Check if a point is in the region. Points exactly on an edge may go either way.
+    pub fn contains(&self, coords: Coords) -> bool {
+        self.polygons.iter().any(|poly| poly.contains(coords))
+    }
+
+    /// Get the smallest latitude and longitude box holding the region as
+    /// `(min_lat, min_lon, max_lat, max_lon)`.
+    pub fn bounds(&self) -> (f64, f64, f64, f64) {
+        self.polygons
+            .iter()
+            .flat_map(|poly| poly.exterior.iter())
+            .fold(
+                (f64::MAX, f64::MAX, f64::MIN, f64::MIN),
+                |(min_lat, min_lon, max_lat, max_lon), coords| {
+                    (
+                        min_lat.min(coords.lat),
+                        min_lon.min(coords.lon),
+                        max_lat.max(coords.lat),
+                        max_lon.max(coords.lon),
+                    )
+                },
+            )
+    }
+
+    fn from_polygons(polygons: Vec<Polygon>) -> Result<Self, BufkitDataErr> {
+        if polygons.is_empty() {
+            return Err(invalid("no polygons"));
+        }
+
+        Ok(Region { polygons })
+    }
+}
+
+impl FromStr for Region {
+    type Err = BufkitDataErr;
+
+    /// Parse GeoJSON if the text starts with `{`, otherwise parse WKT.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim_start().starts_with('{') {
+            Self::from_geojson(s)
+        } else {
+            Self::from_wkt(s)
+        }
+    }
+}
+
+impl Polygon {
+    fn contains(&self, coords: Coords) -> bool {
+        ring_contains(&self.exterior, coords)
+            && !self.holes.iter().any(|hole| ring_contains(hole, coords))
+    }
+
+    fn from_nested(nested: Nested) -> Result<Self, BufkitDataErr> {
+        let mut rings = as_list(nested)?
+            .into_iter()
+            .map(ring_from_nested)
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter();
+
+        let exterior = rings
+            .next()
+            .ok_or_else(|| invalid("polygon without rings"))?;
+
+        Ok(Polygon {
+            exterior,
+            holes: rings.collect(),
+        })
+    }
+
+    fn from_nested_multi(nested: Nested) -> Result<Vec<Self>, BufkitDataErr> {
+        as_list(nested)?
+            .into_iter()
+            .map(Polygon::from_nested)
+            .collect()
+    }
+}
+
+/// Ray casting test for a point in a ring. The ring may be open or closed.
+fn ring_contains(ring: &[Coords], coords: Coords) -> bool {
+    let mut inside = false;
+
+    let mut prev = match ring.last() {
+        Some(&last) => last,
+        None => return false,
+    };
+    for &curr in ring {
+        if (curr.lat > coords.lat) != (prev.lat > coords.lat) {
+            let cross_lon =
+                curr.lon + (coords.lat - curr.lat) / (prev.lat - curr.lat) * (prev.lon - curr.lon);
+            if coords.lon < cross_lon {
+                inside = !inside;
+            }
+        }
+        prev = curr;
+    }
+
+    inside
+}
+
+fn ring_from_nested(nested: Nested) -> Result<Vec<Coords>, BufkitDataErr> {
+    let ring = as_list(nested)?
+        .into_iter()
+        .map(|position| match position {
+            // Positions are longitude first, then latitude.
+            Nested::Position(vals) if vals.len() >= 2 => Ok(Coords::from((vals[1], vals[0]))),
+            _ => Err(invalid("expected a position with a longitude and latitude")),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if ring.len() < 3 {
+        return Err(invalid("a ring needs at least 3 positions"));
+    }
+
+    Ok(ring)
+}
+
+fn as_list(nested: Nested) -> Result<Vec<Nested>, BufkitDataErr> {
+    match nested {
+        Nested::List(list) => Ok(list),
+        Nested::Position(_) => Err(invalid("unexpected position")),
+    }
+}
+
+fn collect_geojson_polygons(
+    value: &serde_json::Value,
+    polygons: &mut Vec<Polygon>,
+) -> Result<(), BufkitDataErr> {
+    let get = |key: &str| {
+        value
+            .get(key)
+            .ok_or_else(|| invalid(&format!("missing {}", key)))
+    };
+
+    match get("type")?.as_str() {
+        Some("Polygon") => polygons.push(Polygon::from_nested(nested_from_json(get(
+            "coordinates",
+        )?)?)?),
+        Some("MultiPolygon") => polygons.extend(Polygon::from_nested_multi(nested_from_json(
+            get("coordinates")?,
+        )?)?),
+        Some("Feature") => collect_geojson_polygons(get("geometry")?, polygons)?,
+        Some("FeatureCollection") => {
+            for feature in get("features")?.as_array().into_iter().flatten() {
+                collect_geojson_polygons(feature, polygons)?;
+            }
+        }
+        Some("GeometryCollection") => {
+            for geometry in get("geometries")?.as_array().into_iter().flatten() {
+                collect_geojson_polygons(geometry, polygons)?;
+            }
+        }
+        Some(other) => return Err(invalid(&format!("unsupported GeoJSON type {}", other))),
+        None => return Err(invalid("GeoJSON type is not a string")),
+    }
+
+    Ok(())
+}
+
+fn nested_from_json(value: &serde_json::Value) -> Result<Nested, BufkitDataErr> {
+    let list = value
+        .as_array()
+        .ok_or_else(|| invalid("coordinates must be arrays"))?;
+
+    if list.first().is_some_and(serde_json::Value::is_number) {
+        list.iter()
+            .map(|val| {
+                val.as_f64()
+                    .ok_or_else(|| invalid("position must be numbers"))
+            })
+            .collect::<Result<_, _>>()
+            .map(Nested::Position)
+    } else {
+        list.iter()
+            .map(nested_from_json)
+            .collect::<Result<_, _>>()
+            .map(Nested::List)
+    }
+}
+
+fn parse_wkt_list(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+) -> Result<Nested, BufkitDataErr> {
+    skip_whitespace(chars);
+    if chars.next() != Some('(') {
+        return Err(invalid("expected ( in WKT"));
+    }
+
+    let mut items = vec![];
+    loop {
+        skip_whitespace(chars);
+        if chars.peek() == Some(&'(') {
+            items.push(parse_wkt_list(chars)?);
+        } else {
+            let mut text = String::new();
+            while let Some(&c) = chars.peek() {
+                if c == ',' || c == ')' {
+                    break;
+                }
+                text.push(c);
+                chars.next();
+            }
+
+            let position = text
+                .split_whitespace()
+                .map(|val| {
+                    f64::from_str(val).map_err(|_| invalid(&format!("bad number {} in WKT", val)))
+                })
+                .collect::<Result<_, _>>()?;
+            items.push(Nested::Position(position));
+        }
+
+        skip_whitespace(chars);
+        match chars.next() {
+            Some(',') => continue,
+            Some(')') => break,
+            _ => return Err(invalid("unbalanced parentheses in WKT")),
+        }
+    }
+
+    Ok(Nested::List(items))
+}
+
+fn skip_whitespace(chars: &mut std::iter::Peekable<std::str::Chars>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+fn invalid(msg: &str) -> BufkitDataErr {
+    BufkitDataErr::InvalidRegion(msg.to_owned())
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                          Unit Tests
+--------------------------------------------------------------------------------------------------*/
+#[cfg(test)]
+mod unit {
+    use super::*;
+
+    const MISSOULA: Coords = Coords {
+        lat: 46.92,
+        lon: -114.09,
+    };
+    const GREAT_FALLS: Coords = Coords {
+        lat: 47.47,
+        lon: -111.38,
+    };
+
+    #[test]
+    fn test_bounds() {
+        let region = Region::from_bounds(46.0, -115.0, 48.0, -113.0);
+        assert!(region.contains(MISSOULA));
+        assert!(!region.contains(GREAT_FALLS));
+        assert_eq!(region.bounds(), (46.0, -115.0, 48.0, -113.0));
+    }
+
+    #[test]
+    fn test_wkt() {
+        let region = Region::from_wkt(
+            "POLYGON ((-116 45, -110 45, -110 49, -116 49, -116 45), \
+             (-115 46, -113 46, -113 48, -115 48, -115 46))",
+        )
+        .unwrap();
+        // Missoula is in the hole.
+        assert!(!region.contains(MISSOULA));
+        assert!(region.contains(GREAT_FALLS));
+
+        let region = Region::from_str(
+            "multipolygon(((-115 46, -113 46, -113 48, -115 48)), ((-112 47, -111 47, -111 48)))",
+        )
+        .unwrap();
+        assert!(region.contains(MISSOULA));
+        assert!(region.contains(GREAT_FALLS));
+        assert_eq!(region.bounds(), (46.0, -115.0, 48.0, -111.0));
+
+        assert!(Region::from_wkt("POINT (-114 46)").is_err());
+        assert!(Region::from_wkt("POLYGON ((-114 46, -113 46, -113 47)").is_err());
+        assert!(Region::from_wkt("POLYGON ((-114 46, -113 46))").is_err());
+    }
+
+    #[test]
+    fn test_geojson() {
+        let region = Region::from_str(
+            r#"{
+                "type": "FeatureCollection",
+                "features": [
+                    {
+                        "type": "Feature",
+                        "properties": {"cwa": "MSO"},
+                        "geometry": {
+                            "type": "Polygon",
+                            "coordinates": [[[-115, 46], [-113, 46], [-113, 48], [-115, 48], [-115, 46]]]
+                        }
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert!(region.contains(MISSOULA));
+        assert!(!region.contains(GREAT_FALLS));
+
+        let region = Region::from_geojson(
+            r#"{"type": "MultiPolygon", "coordinates": [[[[-112, 47], [-111, 47], [-111, 48]]]]}"#,
+        )
+        .unwrap();
+        assert!(region.contains(GREAT_FALLS));
+
+        assert!(Region::from_geojson(r#"{"type": "Point", "coordinates": [-114, 46]}"#).is_err());
+        assert!(Region::from_geojson(r#"{"type": "FeatureCollection", "features": []}"#).is_err());
+    }
+}
@@ -1,17 +1,24 @@
+use crate::coords::Coords;
 use std::fmt::Display;
 
+mod country;
+pub use country::Country;
+
 mod station_num;
 pub use station_num::StationNumber;
 
 mod state_prov;
 pub use state_prov::StateProv;
 
+mod time_zone;
+pub use time_zone::SiteTimeZone;
+
 #[cfg(feature = "pylib")]
 use pyo3::prelude::*;
 
 /// Description of a site with a sounding.
 #[cfg_attr(feature = "pylib", pyclass(module = "bufkit_data"))]
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct SiteInfo {
     /// Station number, this should be unique to the site. Site ids sometimes change around.
     pub station_num: StationNumber,
@@ -23,7 +30,19 @@ pub struct SiteInfo {
     /// state or providence they are in.
     pub state: Option<StateProv>,
     /// Time zone information
-    pub time_zone: Option<chrono::FixedOffset>,
+    pub time_zone: Option<SiteTimeZone>,
+    /// The country where this location is located. If this is not set, the country of the state
+    /// or providence is assumed.
+    pub country: Option<Country>,
+    /// The canonical location of the site. The location in individual files may vary slightly
+    /// between models and model upgrades.
+    pub coords: Option<Coords>,
+    /// The canonical elevation of the site.
+    pub elevation: Option<metfor::Meters>,
+    /// The preferred ICAO identifier, e.g. KMSO.
+    pub icao_id: Option<String>,
+    /// The preferred WMO identifier, e.g. 72773.
+    pub wmo_id: Option<String>,
 }
 
 impl SiteInfo {
@@ -36,6 +55,12 @@ impl SiteInfo {
             || !self.station_num.is_valid()
     }
 
+    /// Get the country of the site, falling back to the country of the state or providence if
+    /// the country is not set.
+    pub fn resolved_country(&self) -> Option<Country> {
+        self.country.or_else(|| self.state.map(StateProv::country))
+    }
+
     /// Get description of the site without all the meta-data details.
     pub fn description(&self) -> String {
         let mut desc = String::new();
@@ -47,6 +72,14 @@ impl SiteInfo {
                 desc += st.as_static_str();
             }
 
+            match self.resolved_country() {
+                Some(Country::US) | None => {}
+                Some(country) => {
+                    desc += ", ";
+                    desc += country.name();
+                }
+            }
+
             desc += " ";
         }
 
@@ -54,6 +87,15 @@ impl SiteInfo {
 
         desc
     }
+
+    /// Convert a valid time, which is in UTC, to the local time at the site. Returns `None` if the
+    /// time zone is not known.
+    pub fn local_time(
+        &self,
+        valid_time: chrono::NaiveDateTime,
+    ) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+        self.time_zone.map(|tz| tz.local_time(valid_time))
+    }
 }
 
 impl Display for SiteInfo {
@@ -69,6 +111,16 @@ impl Display for SiteInfo {
     }
 }
 
+// Sites are hashed by station number alone, equal sites always have the same station number.
+// NaN coordinates or elevations are not valid site data, so equality is reflexive.
+impl Eq for SiteInfo {}
+
+impl std::hash::Hash for SiteInfo {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.station_num.hash(state);
+    }
+}
+
 impl Default for SiteInfo {
     fn default() -> Self {
         SiteInfo {
@@ -77,6 +129,11 @@ impl Default for SiteInfo {
             notes: None,
             state: None,
             time_zone: None,
+            country: None,
+            coords: None,
+            elevation: None,
+            icao_id: None,
+            wmo_id: None,
         }
     }
 }
@@ -98,6 +155,37 @@ impl SiteInfo {
     fn get_station_name(&self) -> String {
         self.name.clone().unwrap_or_else(|| "No Name".to_owned())
     }
+
+    #[getter]
+    fn get_time_zone(&self) -> Option<String> {
+        self.time_zone.map(|tz| tz.to_string())
+    }
+
+    #[getter]
+    fn get_country(&self) -> Option<String> {
+        self.resolved_country()
+            .map(|c| c.as_static_str().to_owned())
+    }
+
+    #[getter]
+    fn get_coords(&self) -> Option<(f64, f64)> {
+        self.coords.map(Into::into)
+    }
+
+    #[getter]
+    fn get_elevation_m(&self) -> Option<f64> {
+        self.elevation.map(|elev| elev.0)
+    }
+
+    #[getter]
+    fn get_icao_id(&self) -> Option<String> {
+        self.icao_id.clone()
+    }
+
+    #[getter]
+    fn get_wmo_id(&self) -> Option<String> {
+        self.wmo_id.clone()
+    }
 }
 
 /*--------------------------------------------------------------------------------------------------
@@ -114,7 +202,8 @@ mod unit {
             name: Some("tv station".to_owned()),
             state: Some(StateProv::VI),
             notes: Some("".to_owned()),
-            time_zone: Some(chrono::FixedOffset::west_opt(7 * 3600).unwrap()),
+            time_zone: Some(chrono_tz::America::Denver.into()),
+            ..SiteInfo::default()
         };
 
         let incomplete_site = SiteInfo {
@@ -123,9 +212,58 @@ mod unit {
             state: None,
             notes: None,
             time_zone: None,
+            ..SiteInfo::default()
         };
 
         assert!(!complete_site.incomplete());
         assert!(incomplete_site.incomplete());
     }
+
+    #[test]
+    fn test_site_description() {
+        let missoula = SiteInfo {
+            station_num: StationNumber::from(727730),
+            name: Some("Missoula".to_owned()),
+            state: Some(StateProv::MT),
+            ..SiteInfo::default()
+        };
+
+        let calgary = SiteInfo {
+            station_num: StationNumber::from(718770),
+            name: Some("Calgary".to_owned()),
+            state: Some(StateProv::AB),
+            ..SiteInfo::default()
+        };
+
+        let offshore = SiteInfo {
+            station_num: StationNumber::from(1),
+            name: Some("Gulf of California".to_owned()),
+            country: Some(Country::MX),
+            ..SiteInfo::default()
+        };
+
+        assert_eq!(missoula.description(), "Missoula, MT (727730)");
+        assert_eq!(calgary.description(), "Calgary, AB, Canada (718770)");
+        assert_eq!(offshore.description(), "Gulf of California, Mexico (1)");
+        assert_eq!(calgary.resolved_country(), Some(Country::CA));
+    }
+
+    #[test]
+    fn test_site_in_set() {
+        let missoula = SiteInfo {
+            station_num: StationNumber::from(727730),
+            coords: Some(Coords::from((46.92, -114.08))),
+            ..SiteInfo::default()
+        };
+        let renamed = SiteInfo {
+            name: Some("Missoula".to_owned()),
+            ..missoula.clone()
+        };
+
+        let mut sites = std::collections::HashSet::new();
+        assert!(sites.insert(missoula.clone()));
+        assert!(!sites.insert(missoula));
+        assert!(sites.insert(renamed));
+        assert_eq!(sites.len(), 2);
+    }
 }
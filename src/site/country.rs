@@ -0,0 +1,50 @@
+use strum_macros::{EnumIter, EnumString, IntoStaticStr};
+
+#[cfg(feature = "pylib")]
+use pyo3::prelude::*;
+
+/// ISO 3166-1 country codes for the countries bufkit sites are located in.
+#[cfg_attr(feature = "pylib", pyclass(module = "bufkit_data"))]
+#[derive(Debug, Hash, Clone, Copy, PartialEq, Eq, EnumString, IntoStaticStr, EnumIter)]
+pub enum Country {
+    /// United States, including commonwealths and territories.
+    US,
+    /// Canada
+    CA,
+    /// Mexico
+    MX,
+}
+
+impl Country {
+    /// Get a static string representation.
+    pub fn as_static_str(self) -> &'static str {
+        self.into()
+    }
+
+    /// Get the common name of the country.
+    pub fn name(self) -> &'static str {
+        match self {
+            Country::US => "United States",
+            Country::CA => "Canada",
+            Country::MX => "Mexico",
+        }
+    }
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                          Unit Tests
+--------------------------------------------------------------------------------------------------*/
+#[cfg(test)]
+mod unit {
+    use super::*;
+
+    use std::str::FromStr;
+    use strum::IntoEnumIterator;
+
+    #[test]
+    fn round_trip_strings_for_country() {
+        for country in Country::iter() {
+            assert_eq!(Country::from_str(country.as_static_str()).unwrap(), country);
+        }
+    }
+}
@@ -1,9 +1,14 @@
 use strum_macros::{EnumIter, EnumString, IntoStaticStr};
 
+use super::Country;
+
 #[cfg(feature = "pylib")]
 use pyo3::prelude::*;
 
 /// State/Providence abreviations for declaring a state in the site.
+///
+/// U.S. states and Canadian provinces use their postal abbreviations, Mexican states use their
+/// ISO 3166-2 codes.
 #[cfg_attr(feature = "pylib", pyclass(module = "bufkit_data"))]
 #[derive(Debug, Hash, Clone, Copy, PartialEq, Eq, EnumString, IntoStaticStr, EnumIter)]
 #[allow(missing_docs)]
@@ -67,6 +72,53 @@ pub enum StateProv {
     PW, // Palau
     PR, // Puerto Rico
     VI, // Virgin Islands
+    // Canadian Provinces and Territories
+    AB, // Alberta
+    BC, // British Columbia
+    MB, // Manitoba
+    NB, // New Brunswick
+    NL, // Newfoundland and Labrador
+    NS, // Nova Scotia
+    NT, // Northwest Territories
+    NU, // Nunavut
+    ON, // Ontario
+    PE, // Prince Edward Island
+    QC, // Quebec
+    SK, // Saskatchewan
+    YT, // Yukon
+    // Mexican States
+    AGU, // Aguascalientes
+    BCN, // Baja California
+    BCS, // Baja California Sur
+    CAM, // Campeche
+    CHP, // Chiapas
+    CHH, // Chihuahua
+    CMX, // Ciudad de Mexico
+    COA, // Coahuila
+    COL, // Colima
+    DUR, // Durango
+    GUA, // Guanajuato
+    GRO, // Guerrero
+    HID, // Hidalgo
+    JAL, // Jalisco
+    MEX, // Mexico
+    MIC, // Michoacan
+    MOR, // Morelos
+    NAY, // Nayarit
+    NLE, // Nuevo Leon
+    OAX, // Oaxaca
+    PUE, // Puebla
+    QUE, // Queretaro
+    ROO, // Quintana Roo
+    SLP, // San Luis Potosi
+    SIN, // Sinaloa
+    SON, // Sonora
+    TAB, // Tabasco
+    TAM, // Tamaulipas
+    TLA, // Tlaxcala
+    VER, // Veracruz
+    YUC, // Yucatan
+    ZAC, // Zacatecas
 }
 
 impl StateProv {
@@ -74,6 +126,19 @@ impl StateProv {
     pub fn as_static_str(self) -> &'static str {
         self.into()
     }
+
+    /// Get the country this state or province is in.
+    pub fn country(self) -> Country {
+        use StateProv::*;
+
+        match self {
+            AB | BC | MB | NB | NL | NS | NT | NU | ON | PE | QC | SK | YT => Country::CA,
+            AGU | BCN | BCS | CAM | CHP | CHH | CMX | COA | COL | DUR | GUA | GRO | HID | JAL
+            | MEX | MIC | MOR | NAY | NLE | OAX | PUE | QUE | ROO | SLP | SIN | SON | TAB | TAM
+            | TLA | VER | YUC | ZAC => Country::MX,
+            _ => Country::US,
+        }
+    }
 }
 
 /*--------------------------------------------------------------------------------------------------
@@ -96,6 +161,14 @@ mod unit {
         assert_eq!(StateProv::from_str("AL").unwrap(), StateProv::AL);
     }
 
+    #[test]
+    fn test_country_for_state_prov() {
+        assert_eq!(StateProv::MT.country(), Country::US);
+        assert_eq!(StateProv::PR.country(), Country::US);
+        assert_eq!(StateProv::AB.country(), Country::CA);
+        assert_eq!(StateProv::SON.country(), Country::MX);
+    }
+
     #[test]
     fn round_trip_strings_for_state_prov() {
         for state_prov in StateProv::iter() {
@@ -0,0 +1,171 @@
+use chrono::{FixedOffset, NaiveDateTime, Offset, TimeZone};
+use chrono_tz::{OffsetComponents, Tz};
+use std::{fmt::Display, str::FromStr};
+
+/// The time zone of a site.
+///
+/// Sites should use an IANA time zone so that daylight saving time is handled correctly. Archives
+/// created with older versions of this crate only stored a fixed offset from UTC, those are still
+/// supported but they are always in standard time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SiteTimeZone {
+    /// A named time zone from the IANA time zone database, e.g. America/Denver.
+    Iana(Tz),
+    /// A fixed offset from UTC.
+    Fixed(FixedOffset),
+}
+
+impl SiteTimeZone {
+    /// Get the offset from UTC in effect at a valid time. Valid times in the archive are UTC.
+    pub fn offset_at(self, valid_time: NaiveDateTime) -> FixedOffset {
+        match self {
+            SiteTimeZone::Iana(tz) => tz.offset_from_utc_datetime(&valid_time).fix(),
+            SiteTimeZone::Fixed(offset) => offset,
+        }
+    }
+
+    /// Get the offset from UTC to local standard time, ignoring daylight saving time.
+    ///
+    /// Time zones sometimes change their standard offset, this uses the one in effect on January
+    /// 1, 2020 so the result does not depend on when it is called.
+    pub fn standard_offset(self) -> FixedOffset {
+        match self {
+            SiteTimeZone::Iana(tz) => {
+                let reference = chrono::NaiveDate::from_ymd_opt(2020, 1, 1)
+                    .and_then(|date| date.and_hms_opt(0, 0, 0))
+                    .expect("valid date");
+                let offset = tz.offset_from_utc_datetime(&reference);
+                let seconds = offset.base_utc_offset().num_seconds() as i32;
+
+                FixedOffset::east_opt(seconds).unwrap_or(offset.fix())
+            }
+            SiteTimeZone::Fixed(offset) => offset,
+        }
+    }
+
+    /// Convert a valid time, which is in UTC, to the local time at the site.
+    pub fn local_time(self, valid_time: NaiveDateTime) -> chrono::DateTime<FixedOffset> {
+        self.offset_at(valid_time).from_utc_datetime(&valid_time)
+    }
+
+    /// Get the IANA name of the time zone, if it has one.
+    pub fn iana_name(self) -> Option<&'static str> {
+        match self {
+            SiteTimeZone::Iana(tz) => Some(tz.name()),
+            SiteTimeZone::Fixed(_) => None,
+        }
+    }
+}
+
+impl From<Tz> for SiteTimeZone {
+    fn from(tz: Tz) -> Self {
+        SiteTimeZone::Iana(tz)
+    }
+}
+
+impl From<FixedOffset> for SiteTimeZone {
+    fn from(offset: FixedOffset) -> Self {
+        SiteTimeZone::Fixed(offset)
+    }
+}
+
+impl FromStr for SiteTimeZone {
+    type Err = String;
+
+    /// Parse an IANA time zone name, e.g. America/Denver, or a fixed offset as it is displayed,
+    /// e.g. UTC-07:00.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("UTC") {
+            Some(offset) if !offset.is_empty() => FixedOffset::from_str(offset)
+                .map(SiteTimeZone::Fixed)
+                .map_err(|err| err.to_string()),
+            _ => Tz::from_str(s)
+                .map(SiteTimeZone::Iana)
+                .map_err(|err| err.to_string()),
+        }
+    }
+}
+
+impl Display for SiteTimeZone {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            SiteTimeZone::Iana(tz) => write!(formatter, "{}", tz.name()),
+            SiteTimeZone::Fixed(offset) => write!(formatter, "UTC{}", offset),
+        }
+    }
+}
+
+/*--------------------------------------------------------------------------------------------------
+                                          Unit Tests
+--------------------------------------------------------------------------------------------------*/
+#[cfg(test)]
+mod unit {
+    use super::*;
+
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_iana_daylight_saving_time() {
+        let tz = SiteTimeZone::from_str("America/Denver").unwrap();
+
+        let winter = NaiveDate::from_ymd_opt(2017, 1, 1)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap();
+        let summer = NaiveDate::from_ymd_opt(2017, 7, 1)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap();
+
+        assert_eq!(
+            tz.offset_at(winter),
+            FixedOffset::west_opt(7 * 3600).unwrap()
+        );
+        assert_eq!(
+            tz.offset_at(summer),
+            FixedOffset::west_opt(6 * 3600).unwrap()
+        );
+        assert_eq!(
+            tz.standard_offset(),
+            FixedOffset::west_opt(7 * 3600).unwrap()
+        );
+
+        assert_eq!(
+            tz.local_time(summer).naive_local(),
+            summer - chrono::Duration::hours(6)
+        );
+        assert_eq!(tz.iana_name(), Some("America/Denver"));
+
+        // Southern hemisphere zones are in daylight saving time in January.
+        let tz = SiteTimeZone::from_str("Australia/Sydney").unwrap();
+        assert_eq!(
+            tz.offset_at(winter),
+            FixedOffset::east_opt(11 * 3600).unwrap()
+        );
+        assert_eq!(
+            tz.standard_offset(),
+            FixedOffset::east_opt(10 * 3600).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_fixed_offset() {
+        let offset = FixedOffset::west_opt(7 * 3600).unwrap();
+        let tz = SiteTimeZone::from(offset);
+
+        let summer = NaiveDate::from_ymd_opt(2017, 7, 1)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap();
+
+        assert_eq!(tz.offset_at(summer), offset);
+        assert_eq!(tz.standard_offset(), offset);
+        assert_eq!(tz.iana_name(), None);
+        assert_eq!(SiteTimeZone::from_str(&tz.to_string()), Ok(tz));
+    }
+
+    #[test]
+    fn test_bad_name() {
+        assert!(SiteTimeZone::from_str("America/Missoula").is_err());
+    }
+}